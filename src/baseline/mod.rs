@@ -0,0 +1,80 @@
+use crate::engine::{QueryResult, QueryStatus};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+
+/// Claimed accounts per username from earlier runs, so scheduled monitoring
+/// only reports sites that are newly claimed.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Baseline {
+    /// username -> site name -> profile URL
+    usernames: BTreeMap<String, BTreeMap<String, String>>,
+}
+
+impl Baseline {
+    /// Reads the baseline at `path`; `None` if it doesn't exist yet.
+    pub fn load(path: &str) -> Result<Option<Self>, Box<dyn std::error::Error>> {
+        match std::fs::read(path) {
+            Ok(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Folds one username's results into the baseline and returns the
+    /// `(site_name, profile_url)` pairs claimed now but not before, in site
+    /// order. A username seen for the first time is recorded without reporting
+    /// anything. Sites leave the baseline only when checked as available, so an
+    /// error or skipped check doesn't re-announce the account on the next run.
+    pub fn update(&mut self, username: &str, results: &[QueryResult]) -> Vec<(String, String)> {
+        let first_run = !self.usernames.contains_key(username);
+        let known = self.usernames.entry(username.to_string()).or_default();
+        let mut added = Vec::new();
+        for result in results {
+            match result.status {
+                QueryStatus::Claimed => {
+                    let previously_known = known.insert(result.site_name.clone(), result.profile_url.clone()).is_some();
+                    if !previously_known && !first_run {
+                        added.push((result.site_name.clone(), result.profile_url.clone()));
+                    }
+                }
+                QueryStatus::Available => {
+                    known.remove(&result.site_name);
+                }
+                _ => {}
+            }
+        }
+        added.sort();
+        added
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn second_run_reports_only_the_new_claim() {
+        let claimed = |site: &str| QueryResult::claimed("alice", site, "", &format!("https://{}.example/alice", site));
+        let available = |site: &str| QueryResult::available("alice", site, "", "");
+        let mut baseline = Baseline::default();
+
+        assert!(baseline.update("alice", &[claimed("a"), available("b")]).is_empty());
+        assert_eq!(
+            baseline.update("alice", &[claimed("a"), claimed("b")]),
+            [("b".to_string(), "https://b.example/alice".to_string())]
+        );
+        assert!(baseline.update("alice", &[claimed("a"), claimed("b")]).is_empty());
+
+        // An error keeps the account; only an available check forgets it
+        let errored = QueryResult::error("alice", "b", "", "", "refused");
+        assert!(baseline.update("alice", &[claimed("a"), errored]).is_empty());
+        assert!(baseline.update("alice", &[claimed("a"), available("b")]).is_empty());
+        assert_eq!(baseline.update("alice", &[claimed("a"), claimed("b")]).len(), 1);
+    }
+}
@@ -1,89 +1,367 @@
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 
 #[derive(Parser, Debug)]
 #[command(name = "watson")]
 #[command(version = "0.1.0")]
 #[command(about = "Watson - OSINT username and email lookup tool", long_about = None)]
 pub struct Cli {
-    /// Username to search for
-    #[arg(value_name = "USERNAME", short = 'u', long = "username")]
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Username to search for; older spelling of `watson search <USERNAME>`
+    #[arg(value_name = "USERNAME", short = 'u', long = "username", global = true, hide = true)]
     pub username: Option<String>,
 
     /// File containing usernames to search (one per line)
-    #[arg(long = "file", short = 'F')]
+    #[arg(long = "file", short = 'F', global = true)]
     pub file: Option<String>,
 
+    /// Search a username as often as it appears in --file instead of once
+    #[arg(long = "allow-duplicates", requires = "file", global = true)]
+    pub allow_duplicates: bool,
+
     /// Generate username variations
-    #[arg(long = "variations")]
+    #[arg(long = "variations", global = true)]
     pub variations: bool,
 
+    /// Also write an HTML grid of which searched usernames (e.g. --variations) are
+    /// claimed on which sites
+    #[arg(long = "heatmap", value_name = "FILE", global = true)]
+    pub heatmap: Option<String>,
+
+    /// After the scan, check variations of each username on a set of top sites and
+    /// suggest the one available on the most of them
+    #[arg(long = "suggest", global = true)]
+    pub suggest: bool,
+
+    /// With --variations, print the generated usernames and their count, then exit
+    #[arg(long = "preview", requires = "variations", global = true)]
+    pub preview: bool,
+
     /// Scrape found profiles for emails
-    #[arg(long = "emails")]
+    #[arg(long = "emails", global = true)]
     pub scrape_emails: bool,
 
+    /// With --emails, list each scraped email once with the sites it was found on,
+    /// and add the list to JSON reports
+    #[arg(long = "dedupe-emails", requires = "scrape_emails", global = true)]
+    pub dedupe_emails: bool,
+
+    /// Record the TLS certificate (CN, SANs, issuer, expiry) of each claimed site's
+    /// host; skipped with --proxy or --tor, as the handshakes connect directly
+    #[arg(long = "capture-tls", global = true)]
+    pub capture_tls: bool,
+
     /// Rotate User-Agent to avoid detection
-    #[arg(long = "rotate-ua")]
+    #[arg(long = "rotate-ua", global = true)]
     pub rotate_ua: bool,
 
     /// Rate limit in milliseconds between requests to same domain
-    #[arg(long = "rate-limit")]
+    #[arg(long = "rate-limit", global = true)]
     pub rate_limit: Option<u64>,
 
     /// Enable interactive TUI mode
-    #[arg(long = "tui")]
+    #[arg(long = "tui", global = true)]
     pub tui: bool,
 
-    /// Email to search for
-    #[arg(value_name = "EMAIL", short = 'm', long = "email")]
+    /// Show a one-line "checked X/Y" spinner on stderr while each search runs
+    /// (ignored when stderr isn't a terminal)
+    #[arg(long = "spinner", conflicts_with = "tui", global = true)]
+    pub spinner: bool,
+
+    /// Email to search for; older spelling of `watson email <EMAIL>`
+    #[arg(value_name = "EMAIL", short = 'm', long = "email", global = true, hide = true)]
     pub email: Option<String>,
 
     /// Output file path
-    #[arg(short = 'o', long = "output", value_name = "FILE")]
+    #[arg(short = 'o', long = "output", value_name = "FILE", global = true)]
     pub output: Option<String>,
 
-    /// Output format (text, json, csv, html)
-    #[arg(short = 'f', long = "format", default_value = "text")]
+    /// Write one report per username into this directory, named <username>.<ext>
+    #[arg(long = "output-dir", value_name = "DIR", conflicts_with = "output", global = true)]
+    pub output_dir: Option<String>,
+
+    /// Output format (text, json, csv, html, term, msgpack, maltego, bookmarks, urls)
+    #[arg(short = 'f', long = "format", default_value = "text", global = true)]
     pub format: OutputFormat,
 
+    /// Write JSON reports minified instead of indented
+    #[arg(long = "compact-json", global = true)]
+    pub compact_json: bool,
+
+    /// Compress written reports; implied by an --output path ending in .zst
+    #[arg(long = "compress", value_name = "ALGO", global = true)]
+    pub compress: Option<Compression>,
+
+    /// Text encoding of report files written with --output or --output-dir (stdout stays UTF-8)
+    #[arg(long = "output-encoding", value_name = "ENCODING", default_value = "utf8", global = true)]
+    pub output_encoding: OutputEncoding,
+
+    /// Re-check only the sites that errored in an earlier saved report (msgpack or JSON)
+    /// and output the merged report like a search would (--output, --format)
+    #[arg(long = "retry-errors", value_name = "PRIOR", conflicts_with_all = ["file", "output_dir"], global = true)]
+    pub retry_errors: Option<String>,
+
+    /// With --retry-errors, overwrite PRIOR with the merged report in its own format
+    #[arg(long = "in-place", requires = "retry_errors", conflicts_with = "output", global = true)]
+    pub in_place: bool,
+
+    /// Reuse claimed/available results from earlier runs stored in this file
+    /// (default: watson_cache.json), and record this run's
+    #[arg(long = "cache-results", value_name = "FILE", num_args = 0..=1, default_missing_value = "watson_cache.json", global = true)]
+    pub cache_results: Option<String>,
+
+    /// How long a cached result stays fresh, in seconds
+    #[arg(long = "result-ttl", value_name = "SECS", default_value = "86400", global = true)]
+    pub result_ttl: u64,
+
+    /// With --cache-results, probe every site again but still update the cache
+    #[arg(long = "force", requires = "cache_results", global = true)]
+    pub force: bool,
+
+    /// Report changes against an earlier saved report (msgpack or JSON) instead of
+    /// the full results; with --format html, a color-coded change page
+    #[arg(long = "diff-against", value_name = "OLD", global = true)]
+    pub diff_against: Option<String>,
+
+    /// Monitoring mode: print only sites newly claimed since the baseline FILE
+    /// (created on first run, then kept up to date) and exit 1 if there are any
+    #[arg(long = "baseline", value_name = "FILE", conflicts_with = "diff_against", global = true)]
+    pub baseline: Option<String>,
+
+    /// Also send each claimed site and a per-username summary to the local syslog
+    /// daemon as key=value messages
+    #[arg(long = "syslog", global = true)]
+    pub syslog: bool,
+
+    /// Print and export only the N claimed sites that responded fastest, fastest first
+    #[arg(long = "top-fastest", value_name = "N", conflicts_with_all = ["baseline", "diff_against"], global = true)]
+    pub top_fastest: Option<usize>,
+
+    /// Add a source column to CSV output naming the input line, pattern or variation behind each row
+    #[arg(long = "include-source-column", global = true)]
+    pub include_source_column: bool,
+
+    /// Section found accounts under category headers in text output
+    #[arg(long = "group-by-category", global = true)]
+    pub group_by_category: bool,
+
+    /// Expand available/error rows in the HTML report instead of collapsing them
+    #[arg(long = "include-available-in-html", global = true)]
+    pub include_available_in_html: bool,
+
+    /// Upload the JSON report to a paste service and print its URL
+    #[arg(long = "paste", global = true)]
+    pub paste: bool,
+
+    /// Paste service endpoint that accepts the report as a POST body
+    #[arg(long = "paste-url", value_name = "URL", default_value = "https://paste.rs/", global = true)]
+    pub paste_url: String,
+
     /// Proxy URL (e.g., socks5://127.0.0.1:1080)
-    #[arg(short = 'p', long = "proxy")]
+    #[arg(short = 'p', long = "proxy", global = true)]
     pub proxy: Option<String>,
 
+    /// Proxy for http:// requests only (instead of --proxy)
+    #[arg(long = "http-proxy", value_name = "URL", conflicts_with_all = ["proxy", "tor"], global = true)]
+    pub http_proxy: Option<String>,
+
+    /// Proxy for https:// requests only, tunnelled with CONNECT (instead of --proxy)
+    #[arg(long = "https-proxy", value_name = "URL", conflicts_with_all = ["proxy", "tor"], global = true)]
+    pub https_proxy: Option<String>,
+
+    /// Comma-separated hosts or domains that bypass the proxy (e.g. "localhost,.corp.local")
+    #[arg(long = "no-proxy", value_name = "HOSTS", conflicts_with = "tor", global = true)]
+    pub no_proxy: Option<String>,
+
+    /// Maximum profiles scraped at once with --emails
+    #[arg(long = "scrape-concurrent", value_name = "N", default_value = "10", global = true)]
+    pub scrape_concurrent: usize,
+
+    /// Domains --emails may fetch from, subdomains included (can be specified multiple
+    /// times); by default each profile's own host, and redirects never leave it
+    #[arg(long = "scrape-allow-domains", value_name = "DOMAIN", global = true)]
+    pub scrape_allow_domains: Option<Vec<String>>,
+
+    /// Proxy URL for --emails profile scraping only; without it scraping goes
+    /// through --proxy/--tor like the search
+    #[arg(long = "scrape-proxy", value_name = "URL", global = true)]
+    pub scrape_proxy: Option<String>,
+
+    /// Username for an authenticating --proxy
+    #[arg(long = "proxy-user", value_name = "USER", global = true)]
+    pub proxy_user: Option<String>,
+
+    /// Password for --proxy-user (or set WATSON_PROXY_PASS)
+    #[arg(long = "proxy-pass", value_name = "PASS", global = true)]
+    pub proxy_pass: Option<String>,
+
+    /// HaveIBeenPwned API key for email searches (or set WATSON_HIBP_KEY)
+    #[arg(long = "hibp-api-key", value_name = "KEY", global = true)]
+    pub hibp_api_key: Option<String>,
+
+    /// Dotenv-style file of secrets (WATSON_HIBP_KEY=..., WATSON_PROXY_PASS=...)
+    #[arg(long = "secrets-file", value_name = "FILE", global = true)]
+    pub secrets_file: Option<String>,
+
+    /// Default Referer header for sites that don't set their own
+    #[arg(long = "referer", global = true)]
+    pub referer: Option<String>,
+
+    /// Accept invalid TLS certificates on all sites (unsafe)
+    #[arg(long = "insecure", global = true)]
+    pub insecure: bool,
+
+    /// Also trust the CA certificates in this PEM file, e.g. a TLS-inspecting proxy's CA (can be specified multiple times)
+    #[arg(long = "ca-cert", value_name = "PATH", global = true)]
+    pub ca_cert: Vec<String>,
+
     /// Use Tor for requests
-    #[arg(short = 't', long = "tor")]
+    #[arg(short = 't', long = "tor", global = true)]
     pub tor: bool,
 
     /// Request timeout in seconds
-    #[arg(long = "timeout", default_value = "15")]
+    #[arg(long = "timeout", default_value = "15", global = true)]
     pub timeout: u64,
 
+    /// Give up on any single site after this many milliseconds (marked timed_out)
+    #[arg(long = "time-budget-per-site", value_name = "MS", global = true)]
+    pub time_budget_per_site: Option<u64>,
+
+    /// Longest accepted username, counted in characters rather than bytes
+    #[arg(long = "max-username-len", value_name = "N", default_value = "50", global = true)]
+    pub max_username_len: usize,
+
+    /// Regex character class every username character must match, e.g. "[A-Za-z0-9._-]"
+    /// (default: anything except whitespace and control characters)
+    #[arg(long = "username-charset", value_name = "CLASS", global = true)]
+    pub username_charset: Option<String>,
+
+    /// Only warn about usernames with disallowed characters instead of skipping them
+    #[arg(long = "allow-invalid-chars", global = true)]
+    pub allow_invalid_chars: bool,
+
     /// Maximum concurrent requests
-    #[arg(long = "max-concurrent", default_value = "50")]
+    #[arg(long = "max-concurrent", default_value = "50", global = true)]
     pub max_concurrent: usize,
 
+    /// Treat HTTP 403 as claimed instead of blocked/unknown
+    #[arg(long = "403-is-claimed", global = true)]
+    pub forbidden_is_claimed: bool,
+
+    /// Maximum concurrent requests to any single host, across all usernames
+    #[arg(long = "max-per-host", value_name = "N", global = true)]
+    pub max_per_host: Option<usize>,
+
+    /// Time requests out at each host's observed p95 latency plus a margin, capped by --timeout
+    #[arg(long = "adaptive-timeout", global = true)]
+    pub adaptive_timeout: bool,
+
+    /// Record the UTC time each site was checked (JSON/CSV)
+    #[arg(long = "timestamps", global = true)]
+    pub timestamps: bool,
+
+    /// Stop the scan as soon as any site claims the username
+    #[arg(long = "first-match", global = true)]
+    pub first_match: bool,
+
+    /// Fast triage: check only the "quick" preset of major networks, stop once
+    /// --quick-hits of them claim the username, and print a one-line verdict
+    #[arg(long = "quick", conflicts_with = "preset", global = true)]
+    pub quick: bool,
+
+    /// With --quick, claimed sites needed to call the username taken and stop
+    #[arg(long = "quick-hits", value_name = "N", default_value = "3", requires = "quick", global = true)]
+    pub quick_hits: usize,
+
+    /// Abort a username's scan after N sites error or time out (in total or in a
+    /// row), keeping the partial results
+    #[arg(long = "max-errors", value_name = "N", global = true)]
+    pub max_errors: Option<usize>,
+
+    /// Keep checking sites that failed to connect for earlier usernames
+    #[arg(long = "no-negative-cache", global = true)]
+    pub no_negative_cache: bool,
+
+    /// Adapt concurrency to network conditions, up to --max-concurrent
+    #[arg(long = "auto-concurrency", global = true)]
+    pub auto_concurrency: bool,
+
+    /// Give up reading a response body after this many milliseconds (marked timed_out)
+    #[arg(long = "body-timeout", value_name = "MS", global = true)]
+    pub body_timeout: Option<u64>,
+
+    /// For message-type sites, request only the first N body bytes via a Range header
+    #[arg(long = "range-probe", value_name = "BYTES", num_args = 0..=1, default_missing_value = "8192", global = true)]
+    pub range_probe: Option<u64>,
+
     /// Include NSFW sites in search
-    #[arg(long = "nsfw")]
+    #[arg(long = "nsfw", global = true)]
     pub nsfw: bool,
 
     /// Print all results (including not found)
-    #[arg(short = 'a', long = "print-all")]
+    #[arg(short = 'a', long = "print-all", global = true)]
     pub print_all: bool,
 
     /// Print only found results
-    #[arg(short = 's', long = "print-found")]
+    #[arg(short = 's', long = "print-found", global = true)]
     pub print_found: bool,
 
     /// Use local data file
-    #[arg(short = 'l', long = "local")]
+    #[arg(short = 'l', long = "local", global = true)]
     pub local: bool,
 
+    /// Offline demo mode: bundled mock sites with stubbed, predictable responses
+    #[arg(long = "mock", global = true)]
+    pub mock: bool,
+
     /// Site to search (can be specified multiple times)
-    #[arg(long = "site")]
+    #[arg(long = "site", global = true)]
     pub site: Option<Vec<String>>,
 
-    /// Enable verbose output
-    #[arg(short = 'v', long = "verbose")]
-    pub verbose: bool,
+    /// Check only this site and explain the result step by step: URL, method,
+    /// headers, response, detection rule and classification (implies -vv)
+    #[arg(long = "explain", value_name = "SITE", conflicts_with_all = ["site", "preset", "quick", "retry_errors"], global = true)]
+    pub explain: Option<String>,
+
+    /// Check every site's detection with its known usernames: `username_claimed` must be
+    /// found and `username_unclaimed` must not; sites getting either wrong are listed as broken
+    #[arg(long = "self-test", conflicts_with_all = ["username", "file", "email", "retry_errors", "explain"], global = true)]
+    pub self_test: bool,
+
+    /// Colorize terminal output: auto (only when stdout is a terminal and NO_COLOR is unset), always, never
+    #[arg(long = "color", value_name = "WHEN", default_value = "auto", global = true)]
+    pub color: ColorChoice,
+
+    /// Suppress everything except results (errors still go to stderr)
+    #[arg(short = 'q', long = "quiet", global = true)]
+    pub quiet: bool,
+
+    /// Only search sites in a named preset (e.g. social, gaming, dev; can be specified multiple times)
+    #[arg(long = "preset", global = true)]
+    pub preset: Option<Vec<String>>,
+
+    /// JSON file with additional presets (name -> list of site names)
+    #[arg(long = "presets-file", value_name = "FILE", global = true)]
+    pub presets_file: Option<String>,
+
+    /// Keep sites that send exactly the same request, and detect the same way, as
+    /// another site in the data
+    #[arg(long = "no-dedupe", global = true)]
+    pub no_dedupe: bool,
+
+    /// Stop with an error if any site data entry is malformed, instead of
+    /// skipping it with a warning
+    #[arg(long = "strict-data", global = true)]
+    pub strict_data: bool,
+
+    /// List available presets
+    #[arg(long = "list-presets", global = true)]
+    pub list_presets: bool,
+
+    /// Verbose output; -vv adds each site's request, response and decision, -vvv everything
+    #[arg(short = 'v', long = "verbose", action = clap::ArgAction::Count, global = true)]
+    pub verbose: u8,
 
     /// List supported sites
     #[arg(long = "list-sites")]
@@ -100,6 +378,109 @@ pub enum OutputFormat {
     Csv,
     /// HTML report
     Html,
+    /// Compact one-line-per-site view sized to the terminal
+    Term,
+    /// MessagePack binary archive (read back with `watson load`)
+    Msgpack,
+    /// CSV laid out for Maltego entity import
+    Maltego,
+    /// Netscape bookmark file of found profiles, importable by browsers
+    Bookmarks,
+    /// Found profile URLs only, one per line
+    Urls,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq)]
+pub enum Compression {
+    /// Zstandard, read back transparently by `watson load` and `watson diff`
+    Zstd,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq)]
+pub enum OutputEncoding {
+    /// UTF-8 without a byte order mark
+    Utf8,
+    /// UTF-8 with a byte order mark, for tools that need it to detect UTF-8
+    Utf8Bom,
+    /// UTF-16 little-endian with a byte order mark, as Windows tools expect
+    Utf16le,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum, PartialEq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Search for a username (also accepted as -u)
+    Search {
+        #[arg(id = "search_username", value_name = "USERNAME", required_unless_present = "username", conflicts_with = "username")]
+        username: Option<String>,
+    },
+    /// Search for an email address (also accepted as -m)
+    Email {
+        #[arg(value_name = "EMAIL", required_unless_present = "email", conflicts_with = "email")]
+        address: Option<String>,
+    },
+    /// List supported sites (same as --list-sites)
+    ListSites,
+    /// Show sites that became claimed or unclaimed between two saved reports
+    Diff {
+        /// Earlier report (msgpack or JSON, optionally zstd-compressed)
+        #[arg(value_name = "OLD")]
+        old: String,
+        /// Later report (msgpack or JSON, optionally zstd-compressed)
+        #[arg(value_name = "NEW")]
+        new: String,
+    },
+    /// Print a shell completion script
+    Completions {
+        #[arg(value_name = "SHELL")]
+        shell: clap_complete::Shell,
+    },
+    /// Run a local HTTP API: GET /search?username=NAME[&format=json|text|csv|html],
+    /// GET /healthz and GET /openapi.json
+    #[cfg(feature = "server")]
+    Serve {
+        /// Port to listen on (localhost only)
+        #[arg(long = "port", default_value = "8080")]
+        port: u16,
+        /// Searches allowed to run at once; further requests wait
+        #[arg(long = "max-scans", default_value = "4")]
+        max_scans: usize,
+    },
+    /// Re-run detection on responses recorded in a HAR capture, without network
+    /// access, printing a report per username found
+    Replay {
+        /// HAR file, e.g. saved from a browser's network panel
+        #[arg(value_name = "HAR")]
+        file: String,
+        /// Only replay requests for this username
+        #[arg(short = 'u', long = "username", value_name = "USERNAME")]
+        username: Option<String>,
+    },
+    /// Pretty-print a report saved with --format msgpack or json
+    Load {
+        /// Archived report file, optionally zstd-compressed
+        #[arg(value_name = "FILE")]
+        file: String,
+    },
+}
+
+impl OutputFormat {
+    /// File extension used for per-username reports in `--output-dir`.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Text | OutputFormat::Term | OutputFormat::Urls => "txt",
+            OutputFormat::Json => "json",
+            OutputFormat::Csv | OutputFormat::Maltego => "csv",
+            OutputFormat::Html | OutputFormat::Bookmarks => "html",
+            OutputFormat::Msgpack => "msgpack",
+        }
+    }
 }
 
 impl std::fmt::Display for OutputFormat {
@@ -109,6 +490,72 @@ impl std::fmt::Display for OutputFormat {
             OutputFormat::Json => write!(f, "json"),
             OutputFormat::Csv => write!(f, "csv"),
             OutputFormat::Html => write!(f, "html"),
+            OutputFormat::Term => write!(f, "term"),
+            OutputFormat::Msgpack => write!(f, "msgpack"),
+            OutputFormat::Maltego => write!(f, "maltego"),
+            OutputFormat::Bookmarks => write!(f, "bookmarks"),
+            OutputFormat::Urls => write!(f, "urls"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("watson").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn search_takes_the_username_as_argument_or_flag() {
+        let cli = parse(&["search", "alice", "--timeout", "5"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Search { username: Some(ref u) }) if u == "alice"));
+        assert_eq!(cli.timeout, 5);
+
+        let cli = parse(&["search", "-u", "alice"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Search { username: None })));
+        assert_eq!(cli.username.as_deref(), Some("alice"));
+
+        let cli = parse(&["-u", "alice"]).unwrap();
+        assert!(cli.command.is_none());
+        assert_eq!(cli.username.as_deref(), Some("alice"));
+
+        assert!(parse(&["search"]).is_err());
+        assert!(parse(&["search", "alice", "-u", "bob"]).is_err());
+    }
+
+    #[test]
+    fn email_takes_the_address_as_argument_or_flag() {
+        let cli = parse(&["email", "a@example.com"]).unwrap();
+        assert!(matches!(cli.command, Some(Command::Email { address: Some(ref a) }) if a == "a@example.com"));
+
+        let cli = parse(&["email", "-m", "a@example.com"]).unwrap();
+        assert_eq!(cli.email.as_deref(), Some("a@example.com"));
+
+        assert!(parse(&["email"]).is_err());
+        assert!(parse(&["email", "a@example.com", "-m", "b@example.com"]).is_err());
+    }
+
+    #[test]
+    fn other_subcommands_parse() {
+        assert!(matches!(parse(&["list-sites"]).unwrap().command, Some(Command::ListSites)));
+        assert!(matches!(
+            parse(&["diff", "old.json", "new.json"]).unwrap().command,
+            Some(Command::Diff { ref old, ref new }) if old == "old.json" && new == "new.json"
+        ));
+        assert!(matches!(
+            parse(&["completions", "bash"]).unwrap().command,
+            Some(Command::Completions { shell: clap_complete::Shell::Bash })
+        ));
+    }
+
+    #[test]
+    fn username_flag_is_hidden_from_help() {
+        let help = Cli::command().render_help().to_string();
+        assert!(!help.contains("--username <USERNAME>"));
+        assert!(!help.contains("--email <EMAIL>"));
+        assert!(help.contains("search"));
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+use tracing::info;
+
+/// Number of completed checks between adjustments.
+const WINDOW: usize = 20;
+/// Back off when more than this share of a window timed out or was rate limited.
+const MAX_FAILURE_RATE: f64 = 0.1;
+/// Only ramp up while the window's average latency stays under this.
+const MAX_AVG_LATENCY_MS: u64 = 3000;
+const RAMP_STEP: usize = 2;
+
+struct State {
+    limit: usize,
+    in_flight: usize,
+    completed: usize,
+    failures: usize,
+    total_latency_ms: u64,
+}
+
+/// AIMD-style controller for the number of in-flight checks: ramps up while
+/// failures and latency stay low, halves the limit when they spike.
+pub struct AdaptiveConcurrency {
+    min: usize,
+    max: usize,
+    state: Mutex<State>,
+    notify: Notify,
+}
+
+pub struct ConcurrencySlot {
+    controller: Arc<AdaptiveConcurrency>,
+}
+
+impl AdaptiveConcurrency {
+    pub fn new(initial: usize, min: usize, max: usize) -> Arc<Self> {
+        let max = max.max(1);
+        let min = min.clamp(1, max);
+        Arc::new(Self {
+            min,
+            max,
+            state: Mutex::new(State {
+                limit: initial.clamp(min, max),
+                in_flight: 0,
+                completed: 0,
+                failures: 0,
+                total_latency_ms: 0,
+            }),
+            notify: Notify::new(),
+        })
+    }
+
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    /// Waits until the number of in-flight checks is below the current limit.
+    pub async fn acquire(self: &Arc<Self>) -> ConcurrencySlot {
+        loop {
+            let notified = self.notify.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.in_flight < state.limit {
+                    state.in_flight += 1;
+                    return ConcurrencySlot {
+                        controller: self.clone(),
+                    };
+                }
+            }
+            notified.await;
+        }
+    }
+
+    /// Records the outcome of one check; `failed` should be set for timeouts and 429s.
+    pub fn record(&self, latency_ms: Option<u64>, failed: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.completed += 1;
+        state.total_latency_ms += latency_ms.unwrap_or(0);
+        if failed {
+            state.failures += 1;
+        }
+
+        if state.completed < WINDOW {
+            return;
+        }
+
+        let failure_rate = state.failures as f64 / state.completed as f64;
+        let avg_latency = state.total_latency_ms / state.completed as u64;
+        let previous = state.limit;
+
+        if failure_rate > MAX_FAILURE_RATE {
+            state.limit = (state.limit / 2).max(self.min);
+        } else if avg_latency < MAX_AVG_LATENCY_MS {
+            state.limit = (state.limit + RAMP_STEP).min(self.max);
+        }
+
+        if state.limit != previous {
+            info!(
+                "Auto-concurrency: {} -> {} (failure rate {:.0}%, avg latency {}ms)",
+                previous,
+                state.limit,
+                failure_rate * 100.0,
+                avg_latency
+            );
+        }
+
+        state.completed = 0;
+        state.failures = 0;
+        state.total_latency_ms = 0;
+        drop(state);
+        self.notify.notify_one();
+    }
+}
+
+impl Drop for ConcurrencySlot {
+    fn drop(&mut self) {
+        self.controller.state.lock().unwrap().in_flight -= 1;
+        self.controller.notify.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(controller: &AdaptiveConcurrency, latency_ms: u64, failures: usize) {
+        for i in 0..WINDOW {
+            controller.record(Some(latency_ms), i < failures);
+        }
+    }
+
+    #[test]
+    fn ramps_up_while_healthy_and_halves_on_failures() {
+        let controller = AdaptiveConcurrency::new(10, 2, 16);
+        window(&controller, 100, 0);
+        assert_eq!(controller.limit(), 10 + RAMP_STEP);
+        window(&controller, 100, 0);
+        window(&controller, 100, 0);
+        assert_eq!(controller.limit(), 16, "capped at max");
+
+        // 3 of 20 is over the 10% failure budget
+        window(&controller, 100, 3);
+        assert_eq!(controller.limit(), 8);
+        window(&controller, 100, 3);
+        window(&controller, 100, 3);
+        assert_eq!(controller.limit(), 2, "floored at min");
+
+        // Slow but not failing: hold steady
+        window(&controller, MAX_AVG_LATENCY_MS, 0);
+        assert_eq!(controller.limit(), 2);
+        window(&controller, 100, 0);
+        assert_eq!(controller.limit(), 2 + RAMP_STEP);
+        // Nothing changes until a full window has completed
+        controller.record(Some(100), true);
+        assert_eq!(controller.limit(), 2 + RAMP_STEP);
+    }
+
+    #[tokio::test]
+    async fn acquire_waits_for_a_free_slot() {
+        let controller = AdaptiveConcurrency::new(1, 1, 1);
+        let slot = controller.acquire().await;
+        let waiting = tokio::spawn({
+            let controller = controller.clone();
+            async move { controller.acquire().await }
+        });
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        assert!(!waiting.is_finished());
+        drop(slot);
+        tokio::time::timeout(std::time::Duration::from_secs(1), waiting).await.unwrap().unwrap();
+    }
+}
@@ -1,4 +1,7 @@
-use serde::{Deserialize, Serialize};
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::io::Read;
+use tracing::{info, warn};
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub enum ErrorType {
@@ -10,6 +13,22 @@ pub enum ErrorType {
     Redirect,
     #[serde(rename = "response_url")]
     ResponseUrl,
+    /// The body is JSON and the value at `jsonPointer` decides claimed/available.
+    #[serde(rename = "json_field")]
+    JsonField,
+}
+
+impl ErrorType {
+    /// The name used for the type in site data.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorType::StatusCode => "status_code",
+            ErrorType::Message => "message",
+            ErrorType::Redirect => "redirect",
+            ErrorType::ResponseUrl => "response_url",
+            ErrorType::JsonField => "json_field",
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,6 +38,191 @@ pub enum ErrorMessages {
     Multiple(Vec<String>),
 }
 
+impl ErrorMessages {
+    /// The configured messages, minus empty or whitespace-only entries, which
+    /// would match every page.
+    pub fn messages(&self) -> Vec<&str> {
+        let all = match self {
+            ErrorMessages::Single(s) => vec![s.as_str()],
+            ErrorMessages::Multiple(v) => v.iter().map(|s| s.as_str()).collect(),
+        };
+        all.into_iter().filter(|m| !m.trim().is_empty()).collect()
+    }
+
+    /// Whether any entry is empty or whitespace-only.
+    pub fn has_blank(&self) -> bool {
+        match self {
+            ErrorMessages::Single(s) => s.trim().is_empty(),
+            ErrorMessages::Multiple(v) => v.iter().any(|s| s.trim().is_empty()),
+        }
+    }
+}
+
+/// A rewrite a site applies to handles, e.g. `John.Doe` becoming `johndoe`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum UsernameTransform {
+    Lowercase,
+    StripDots,
+    StripUnderscores,
+}
+
+impl UsernameTransform {
+    pub fn apply(&self, username: &str) -> String {
+        match self {
+            UsernameTransform::Lowercase => username.to_lowercase(),
+            UsernameTransform::StripDots => username.replace('.', ""),
+            UsernameTransform::StripUnderscores => username.replace('_', ""),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum UsernameTransforms {
+    Single(UsernameTransform),
+    Multiple(Vec<UsernameTransform>),
+}
+
+impl UsernameTransforms {
+    pub fn transforms(&self) -> Vec<UsernameTransform> {
+        match self {
+            UsernameTransforms::Single(t) => vec![*t],
+            UsernameTransforms::Multiple(v) => v.clone(),
+        }
+    }
+}
+
+/// An HTTP status or an inclusive range of them, written `404` or `"500-599"`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StatusRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl StatusRange {
+    pub const fn new(start: u16, end: u16) -> Self {
+        Self { start, end }
+    }
+
+    pub fn contains(&self, status: u16) -> bool {
+        (self.start..=self.end).contains(&status)
+    }
+}
+
+impl std::fmt::Display for StatusRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}-{}", self.start, self.end)
+        }
+    }
+}
+
+impl std::str::FromStr for StatusRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |part: &str| {
+            part.trim()
+                .parse::<u16>()
+                .ok()
+                .filter(|code| (100..=599).contains(code))
+                .ok_or_else(|| format!("invalid HTTP status {:?}", part))
+        };
+        let (start, end) = match s.split_once('-') {
+            Some((start, end)) => (parse(start)?, parse(end)?),
+            None => (parse(s)?, parse(s)?),
+        };
+        if start > end {
+            return Err(format!("status range {:?} is backwards", s));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+impl Serialize for StatusRange {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.start == self.end {
+            serializer.serialize_u16(self.start)
+        } else {
+            serializer.serialize_str(&self.to_string())
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for StatusRange {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::Number(n) => n.to_string().parse(),
+            serde_json::Value::String(s) => s.parse(),
+            other => Err(format!("expected a status or range, got {}", other)),
+        }
+        .map_err(serde::de::Error::custom)
+    }
+}
+
+/// Which final HTTP statuses mean claimed and which mean available for
+/// `status_code` and `redirect` sites; any other status is reported unknown.
+/// Claimed wins where the two overlap. A 403 never gets here: it's reported
+/// blocked unless `--403-is-claimed` is given.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct StatusPolicy {
+    pub claimed: Vec<StatusRange>,
+    pub available: Vec<StatusRange>,
+}
+
+impl Default for StatusPolicy {
+    /// Success means the profile exists; a redirect that wasn't followed or a
+    /// client error means it doesn't; server errors say nothing either way.
+    fn default() -> Self {
+        Self {
+            claimed: vec![StatusRange::new(200, 299)],
+            available: vec![StatusRange::new(300, 499)],
+        }
+    }
+}
+
+impl StatusPolicy {
+    /// `Some(true)` for claimed, `Some(false)` for available, `None` for unknown.
+    pub fn classify(&self, status: u16) -> Option<bool> {
+        if self.claimed.iter().any(|r| r.contains(status)) {
+            Some(true)
+        } else if self.available.iter().any(|r| r.contains(status)) {
+            Some(false)
+        } else {
+            None
+        }
+    }
+
+    /// Human-readable summary, e.g. `claimed on HTTP 200-299, available on 300-499`.
+    pub fn describe(&self) -> String {
+        let list = |ranges: &[StatusRange]| match ranges {
+            [] => "nothing".to_string(),
+            _ => ranges.iter().map(|r| r.to_string()).collect::<Vec<_>>().join(", "),
+        };
+        format!("claimed on HTTP {}, available on {}", list(&self.claimed), list(&self.available))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum UrlProbe {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl UrlProbe {
+    pub fn patterns(&self) -> Vec<&str> {
+        match self {
+            UrlProbe::Single(s) => vec![s.as_str()],
+            UrlProbe::Multiple(v) => v.iter().map(|s| s.as_str()).collect(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SiteInfo {
     pub url: String,
@@ -28,22 +232,175 @@ pub struct SiteInfo {
     pub error_type: ErrorType,
     #[serde(rename = "errorMsg", skip_serializing_if = "Option::is_none")]
     pub error_msg: Option<ErrorMessages>,
-    #[serde(rename = "errorUrl", skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "errorUrl", default, deserialize_with = "lenient_string", skip_serializing_if = "Option::is_none")]
     pub error_url: Option<String>,
-    #[serde(rename = "regexCheck", skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "regexCheck", default, deserialize_with = "lenient_string", skip_serializing_if = "Option::is_none")]
     pub regex_check: Option<String>,
-    #[serde(rename = "username_claimed", skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "username_claimed", default, deserialize_with = "lenient_string", skip_serializing_if = "Option::is_none")]
     pub username_claimed: Option<String>,
-    #[serde(rename = "request_method", skip_serializing_if = "Option::is_none")]
+    /// A username known not to exist on the site, the counterpart of `username_claimed`.
+    #[serde(rename = "username_unclaimed", default, deserialize_with = "lenient_string", skip_serializing_if = "Option::is_none")]
+    pub username_unclaimed: Option<String>,
+    #[serde(rename = "request_method", default, deserialize_with = "lenient_string", skip_serializing_if = "Option::is_none")]
     pub request_method: Option<String>,
     #[serde(rename = "request_payload", skip_serializing_if = "Option::is_none")]
     pub request_payload: Option<serde_json::Value>,
     #[serde(rename = "urlProbe", skip_serializing_if = "Option::is_none")]
-    pub url_probe: Option<String>,
+    pub url_probe: Option<UrlProbe>,
     #[serde(rename = "headers", skip_serializing_if = "Option::is_none")]
-    pub headers: Option<std::collections::HashMap<String, String>>,
+    pub headers: Option<std::collections::BTreeMap<String, String>>,
     #[serde(rename = "isNSFW", skip_serializing_if = "Option::is_none")]
     pub is_nsfw: Option<bool>,
+    #[serde(rename = "referer", default, deserialize_with = "lenient_string", skip_serializing_if = "Option::is_none")]
+    pub referer: Option<String>,
+    #[serde(rename = "metaRefresh", skip_serializing_if = "Option::is_none")]
+    pub meta_refresh: Option<bool>,
+    #[serde(rename = "placeholderMsg", skip_serializing_if = "Option::is_none")]
+    pub placeholder_msg: Option<ErrorMessages>,
+    #[serde(rename = "insecure", skip_serializing_if = "Option::is_none")]
+    pub insecure: Option<bool>,
+    /// RFC 6901 pointer into the JSON body, e.g. `/data/found` (for `json_field`).
+    #[serde(rename = "jsonPointer", default, deserialize_with = "lenient_string", skip_serializing_if = "Option::is_none")]
+    pub json_pointer: Option<String>,
+    /// Value at `jsonPointer` that means claimed; without it any value but `null`/`false` does.
+    #[serde(rename = "jsonClaimedValue", skip_serializing_if = "Option::is_none")]
+    pub json_claimed_value: Option<serde_json::Value>,
+    /// Categories such as `dev` or `social`; the first is the site's primary category.
+    #[serde(rename = "tags", skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// Wait at least this long before requesting the site, on top of any global `--rate-limit`.
+    #[serde(rename = "requestDelayMs", skip_serializing_if = "Option::is_none")]
+    pub request_delay_ms: Option<u64>,
+    /// Environment variable holding an API token for this site; the site is skipped when it's unset.
+    #[serde(rename = "authTokenEnv", default, deserialize_with = "lenient_string", skip_serializing_if = "Option::is_none")]
+    pub auth_token_env: Option<String>,
+    /// Header the token is sent in (default `Authorization`, as `Bearer <token>`).
+    #[serde(rename = "authHeader", default, deserialize_with = "lenient_string", skip_serializing_if = "Option::is_none")]
+    pub auth_header: Option<String>,
+    /// Overrides the default `StatusPolicy` for `status_code` and `redirect` sites.
+    #[serde(rename = "statusPolicy", skip_serializing_if = "Option::is_none")]
+    pub status_policy: Option<StatusPolicy>,
+    /// Applied in order to the username before the `regexCheck` and URL substitution.
+    #[serde(rename = "usernameTransform", skip_serializing_if = "Option::is_none")]
+    pub username_transform: Option<UsernameTransforms>,
+}
+
+impl SiteInfo {
+    /// The token from `authTokenEnv`; empty values count as unset.
+    fn auth_token(&self) -> Option<String> {
+        let name = self.auth_token_env.as_deref()?;
+        std::env::var(name).ok().filter(|token| !token.is_empty())
+    }
+
+    /// True if the site needs a token whose environment variable isn't set.
+    pub fn missing_auth_token(&self) -> bool {
+        self.auth_token_env.is_some() && self.auth_token().is_none()
+    }
+
+    /// `username` as this site spells it, after its `usernameTransform`.
+    pub fn site_username(&self, username: &str) -> String {
+        let transforms = self.username_transform.as_ref().map(|t| t.transforms()).unwrap_or_default();
+        transforms.iter().fold(username.to_string(), |name, t| t.apply(&name))
+    }
+
+    /// The profile URL for `username` on this site.
+    pub fn profile_url(&self, username: &str) -> String {
+        self.url.replace("{}", &self.site_username(username))
+    }
+
+    /// The site's `statusPolicy`, or the default one.
+    pub fn status_policy(&self) -> StatusPolicy {
+        self.status_policy.clone().unwrap_or_default()
+    }
+
+    /// What checking the site requests (method, probe URL(s), payload, headers and
+    /// Referer) and how the response is read (errorType and its detection fields).
+    fn probe_key(&self) -> String {
+        let method = self.request_method.as_deref().unwrap_or("GET").to_uppercase();
+        let urls = match self.url_probe {
+            Some(ref probe) => probe.patterns().join(" "),
+            None => self.url.clone(),
+        };
+        let transforms = self.username_transform.as_ref().map(|t| t.transforms()).unwrap_or_default();
+        serde_json::json!({
+            "method": method,
+            "urls": urls,
+            "payload": self.request_payload,
+            "transforms": format!("{:?}", transforms),
+            "headers": self.headers,
+            "referer": self.referer,
+            "authTokenEnv": self.auth_token_env,
+            "authHeader": self.auth_header,
+            "errorType": self.error_type,
+            "errorMsg": self.error_msg,
+            "errorUrl": self.error_url,
+            "placeholderMsg": self.placeholder_msg,
+            "jsonPointer": self.json_pointer,
+            "jsonClaimedValue": self.json_claimed_value,
+            "statusPolicy": self.status_policy,
+            "metaRefresh": self.meta_refresh,
+        })
+        .to_string()
+    }
+
+    /// The `(name, value)` header carrying the site's token, if it has one.
+    /// `Authorization` gets the `Bearer` scheme; any other header the bare token.
+    pub fn auth_request_header(&self) -> Option<(String, String)> {
+        let token = self.auth_token()?;
+        let name = self.auth_header.clone().unwrap_or_else(|| "Authorization".to_string());
+        let value = if name.eq_ignore_ascii_case("authorization") {
+            format!("Bearer {}", token)
+        } else {
+            token
+        };
+        Some((name, value))
+    }
+}
+
+/// Accepts a string field that upstream sometimes ships as a number or bool;
+/// anything else (arrays, objects) is treated as absent rather than failing the
+/// file, and `load_from_json` logs which fields that happened to.
+fn lenient_string<'de, D>(deserializer: D) -> Result<Option<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Option::<serde_json::Value>::deserialize(deserializer)? {
+        Some(serde_json::Value::String(s)) => Some(s),
+        Some(serde_json::Value::Number(n)) => Some(n.to_string()),
+        Some(serde_json::Value::Bool(b)) => Some(b.to_string()),
+        _ => None,
+    })
+}
+
+/// The `SiteInfo` fields read with `lenient_string`.
+const LENIENT_STRING_FIELDS: [&str; 9] = [
+    "errorUrl",
+    "regexCheck",
+    "username_claimed",
+    "username_unclaimed",
+    "request_method",
+    "referer",
+    "jsonPointer",
+    "authTokenEnv",
+    "authHeader",
+];
+
+/// The lenient string fields of a raw site entry that hold an array or object,
+/// which `lenient_string` drops.
+fn dropped_string_fields(site: &serde_json::Value) -> Vec<&'static str> {
+    LENIENT_STRING_FIELDS
+        .into_iter()
+        .filter(|field| matches!(site.get(field), Some(serde_json::Value::Array(_) | serde_json::Value::Object(_))))
+        .collect()
+}
+
+/// `SitesData` before each site is checked; one malformed entry shouldn't sink the rest.
+#[derive(Deserialize)]
+struct RawSitesData {
+    #[serde(rename = "$schema")]
+    schema: Option<String>,
+    #[serde(flatten)]
+    sites: std::collections::HashMap<String, serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,17 +409,261 @@ pub struct SitesData {
     pub schema: Option<String>,
     #[serde(flatten)]
     pub sites: std::collections::HashMap<String, SiteInfo>,
+    /// Where the data came from (file path, URL or `mock`), set by the caller.
+    #[serde(skip)]
+    pub source: String,
+    /// CRC-32 of the (decompressed) JSON, to tell whether two scans used the same data.
+    #[serde(skip)]
+    pub hash: String,
+    /// Names of entries skipped because they didn't fit `SiteInfo`, sorted.
+    #[serde(skip)]
+    pub malformed: Vec<String>,
+    /// Names of sites whose `errorMsg` has empty or whitespace-only entries, which
+    /// are ignored; a site left with none is detected by status code. Sorted.
+    #[serde(skip)]
+    pub blank_error_msg: Vec<String>,
 }
 
 impl SitesData {
-    pub fn load_from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let content = std::fs::read_to_string(path)?;
-        let data: SitesData = serde_json::from_str(&content)?;
-        Ok(data)
+    /// Gzipped files (a `.gz` extension or the gzip magic bytes) are decompressed first.
+    pub fn load_from_file(path: &str, strict: bool) -> Result<Self, Box<dyn std::error::Error>> {
+        let bytes = std::fs::read(path)?;
+        let content = if path.ends_with(".gz") || bytes.starts_with(&[0x1f, 0x8b]) {
+            let mut content = String::new();
+            GzDecoder::new(bytes.as_slice()).read_to_string(&mut content)?;
+            content
+        } else {
+            String::from_utf8(bytes)?
+        };
+        Self::load_from_json(&content, strict)
+    }
+
+    /// Unknown fields are ignored. Sites that still don't fit `SiteInfo` are skipped
+    /// with a warning and listed in `malformed`, or with `strict` fail the whole load.
+    pub fn load_from_json(json: &str, strict: bool) -> Result<Self, Box<dyn std::error::Error>> {
+        let raw: RawSitesData = serde_json::from_str(json)?;
+        let mut sites = std::collections::HashMap::new();
+        let mut broken = Vec::new();
+        let mut blank_error_msg = Vec::new();
+        for (name, value) in raw.sites {
+            for field in dropped_string_fields(&value) {
+                warn!("Site {}: ignoring {}, which should be a string", name, field);
+            }
+            match serde_json::from_value::<SiteInfo>(value) {
+                Ok(info) => {
+                    if info.error_msg.as_ref().is_some_and(|msgs| msgs.has_blank()) {
+                        blank_error_msg.push(name.clone());
+                    }
+                    sites.insert(name, info);
+                }
+                Err(e) => broken.push((name, e)),
+            }
+        }
+        broken.sort_by(|a, b| a.0.cmp(&b.0));
+        blank_error_msg.sort();
+        if strict && !broken.is_empty() {
+            let details: Vec<String> = broken.iter().map(|(name, e)| format!("{}: {}", name, e)).collect();
+            return Err(format!("Malformed site data entries: {}", details.join("; ")).into());
+        }
+        for (name, e) in &broken {
+            warn!("Skipping site {}: {}", name, e);
+        }
+        let mut crc = flate2::Crc::new();
+        crc.update(json.as_bytes());
+        Ok(Self {
+            schema: raw.schema,
+            sites,
+            source: String::new(),
+            hash: format!("{:08x}", crc.sum()),
+            malformed: broken.into_iter().map(|(name, _)| name).collect(),
+            blank_error_msg,
+        })
+    }
+
+    /// `dedupe_sites` over every loaded site.
+    pub fn dedupe(&mut self) {
+        dedupe_sites(&mut self.sites);
+    }
+}
+
+/// Drops sites that send exactly the same request as another and read the
+/// response the same way (see `SiteInfo::probe_key`), keeping the first by name
+/// so the result doesn't depend on map order. Run it after `--site`/`--preset`
+/// filtering so a site asked for by name isn't dropped for a duplicate that
+/// wasn't selected.
+pub fn dedupe_sites(sites: &mut std::collections::HashMap<String, SiteInfo>) {
+    let mut names: Vec<String> = sites.keys().cloned().collect();
+    names.sort();
+    let mut seen: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+    for name in names {
+        let key = sites[&name].probe_key();
+        match seen.get(&key) {
+            Some(kept) => {
+                info!("Dropping site {}: same request as {}", name, kept);
+                sites.remove(&name);
+            }
+            None => {
+                seen.insert(key, name);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CODES: [u16; 5] = [200, 301, 403, 404, 500];
+
+    #[test]
+    fn default_status_policy_classifies_common_codes() {
+        let verdicts = CODES.map(|code| StatusPolicy::default().classify(code));
+        assert_eq!(verdicts, [Some(true), Some(false), Some(false), Some(false), None]);
+    }
+
+    #[test]
+    fn custom_status_policy_classifies_common_codes() {
+        let policy: StatusPolicy =
+            serde_json::from_value(serde_json::json!({"claimed": [200, "301-302"], "available": [404, "500-599"]})).unwrap();
+        let verdicts = CODES.map(|code| policy.classify(code));
+        assert_eq!(verdicts, [Some(true), Some(true), None, Some(false), Some(false)]);
+
+        // Claimed wins where the ranges overlap; an empty list leaves codes unknown
+        let policy: StatusPolicy = serde_json::from_value(serde_json::json!({"claimed": ["200-404"], "available": []})).unwrap();
+        let verdicts = CODES.map(|code| policy.classify(code));
+        assert_eq!(verdicts, [Some(true), Some(true), Some(true), Some(true), None]);
+    }
+
+    fn sites(json: &str) -> std::collections::HashMap<String, SiteInfo> {
+        SitesData::load_from_json(json, true).unwrap().sites
+    }
+
+    #[test]
+    fn gzipped_files_load_by_extension_or_magic_bytes() {
+        use std::io::Write;
+
+        let json = r#"{"Site": {"url": "https://a.example/{}", "urlMain": "https://a.example/", "errorType": "status_code"}}"#;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(json.as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let dir = std::env::temp_dir();
+        let path = |name: &str| dir.join(format!("watson-test-{}-{}", std::process::id(), name)).to_string_lossy().to_string();
+        let (plain, by_extension, by_magic, fake) =
+            (path("sites.json"), path("sites.json.gz"), path("sites-gz.json"), path("fake.json.gz"));
+        std::fs::write(&plain, json).unwrap();
+        std::fs::write(&by_extension, &gzipped).unwrap();
+        std::fs::write(&by_magic, &gzipped).unwrap();
+        std::fs::write(&fake, json).unwrap();
+
+        let expected = SitesData::load_from_file(&plain, true).unwrap();
+        for file in [&by_extension, &by_magic] {
+            let data = SitesData::load_from_file(file, true).unwrap();
+            assert_eq!(data.sites.keys().collect::<Vec<_>>(), ["Site"]);
+            assert_eq!(data.hash, expected.hash);
+        }
+        // A .gz name doesn't make plain JSON readable as gzip
+        assert!(SitesData::load_from_file(&fake, true).is_err());
+
+        for file in [plain, by_extension, by_magic, fake] {
+            let _ = std::fs::remove_file(file);
+        }
+    }
+
+    #[test]
+    fn loose_fields_are_coerced_or_dropped() {
+        let json = r#"{
+            "Loose": {
+                "url": "https://a.example/{}", "urlMain": "https://a.example/", "errorType": "status_code",
+                "someFutureField": {"nested": true},
+                "regexCheck": 123,
+                "referer": true,
+                "errorUrl": ["https://a.example/404"],
+                "jsonPointer": {"path": "/user"}
+            }
+        }"#;
+        let data = SitesData::load_from_json(json, true).unwrap();
+        assert!(data.malformed.is_empty());
+        let info = &data.sites["Loose"];
+        assert_eq!(info.regex_check.as_deref(), Some("123"));
+        assert_eq!(info.referer.as_deref(), Some("true"));
+        assert_eq!((info.error_url.as_deref(), info.json_pointer.as_deref()), (None, None));
+
+        let raw: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(dropped_string_fields(&raw["Loose"]), ["errorUrl", "jsonPointer"]);
+    }
+
+    #[test]
+    fn lenient_string_fields_match_site_info() {
+        // Every listed field really is a lenient string: a number comes back as text
+        for field in LENIENT_STRING_FIELDS {
+            let site: SiteInfo = serde_json::from_value(serde_json::json!({
+                "url": "https://a.example/{}", "urlMain": "https://a.example/", "errorType": "status_code", field: 7,
+            }))
+            .unwrap();
+            let json = serde_json::to_value(&site).unwrap();
+            assert_eq!(json[field], "7", "{}", field);
+        }
+    }
+
+    #[test]
+    fn dedupe_keeps_one_of_two_identical_sites() {
+        let mut data = sites(
+            r#"{
+                "Beta": {"url": "https://a.example/{}", "urlMain": "https://a.example/", "errorType": "status_code"},
+                "Alpha": {"url": "https://a.example/{}", "urlMain": "https://www.a.example/", "errorType": "status_code"},
+                "Other": {"url": "https://b.example/{}", "urlMain": "https://b.example/", "errorType": "status_code"}
+            }"#,
+        );
+        dedupe_sites(&mut data);
+        let mut names: Vec<&str> = data.keys().map(|n| n.as_str()).collect();
+        names.sort();
+        assert_eq!(names, ["Alpha", "Other"]);
+    }
+
+    #[test]
+    fn dedupe_keeps_sites_differing_in_headers_or_detection() {
+        let mut data = sites(
+            r#"{
+                "Plain": {"url": "https://a.example/{}", "urlMain": "https://a.example/", "errorType": "status_code"},
+                "Header": {"url": "https://a.example/{}", "urlMain": "https://a.example/", "errorType": "status_code", "headers": {"Accept": "application/json"}},
+                "Referer": {"url": "https://a.example/{}", "urlMain": "https://a.example/", "errorType": "status_code", "referer": "https://a.example/"},
+                "Message": {"url": "https://a.example/{}", "urlMain": "https://a.example/", "errorType": "message", "errorMsg": "Not Found"},
+                "OtherMessage": {"url": "https://a.example/{}", "urlMain": "https://a.example/", "errorType": "message", "errorMsg": "No such user"}
+            }"#,
+        );
+        dedupe_sites(&mut data);
+        assert_eq!(data.len(), 5);
+    }
+
+    #[test]
+    fn malformed_entry_is_skipped_unless_strict() {
+        let json = r#"{
+            "$schema": "data.schema.json",
+            "Good": {"url": "https://a.example/{}", "urlMain": "https://a.example/", "errorType": "status_code", "futureField": 1},
+            "Broken": {"url": "https://b.example/{}", "errorType": "status_code"}
+        }"#;
+        let data = SitesData::load_from_json(json, false).unwrap();
+        assert_eq!(data.sites.keys().collect::<Vec<_>>(), ["Good"]);
+        assert_eq!(data.malformed, ["Broken"]);
+
+        let err = SitesData::load_from_json(json, true).unwrap_err().to_string();
+        assert!(err.starts_with("Malformed site data entries: Broken: "), "{}", err);
     }
 
-    pub fn load_from_json(json: &str) -> Result<Self, Box<dyn std::error::Error>> {
-        let data: SitesData = serde_json::from_str(json)?;
-        Ok(data)
+    #[test]
+    fn blank_error_msg_entries_are_listed_and_dropped() {
+        let data = SitesData::load_from_json(
+            r#"{
+                "Blank": {"url": "https://a.example/{}", "urlMain": "https://a.example/", "errorType": "message", "errorMsg": "  "},
+                "Mixed": {"url": "https://b.example/{}", "urlMain": "https://b.example/", "errorType": "message", "errorMsg": ["", "Not Found"]},
+                "Fine": {"url": "https://c.example/{}", "urlMain": "https://c.example/", "errorType": "message", "errorMsg": "Not Found"}
+            }"#,
+            false,
+        )
+        .unwrap();
+        assert_eq!(data.blank_error_msg, ["Blank", "Mixed"]);
+        assert!(data.sites["Blank"].error_msg.as_ref().unwrap().messages().is_empty());
+        assert_eq!(data.sites["Mixed"].error_msg.as_ref().unwrap().messages(), ["Not Found"]);
     }
 }
@@ -12,6 +12,12 @@ pub struct EmailService {
     pub error_msg: Option<String>,
     #[serde(rename = "requestMethod", skip_serializing_if = "Option::is_none")]
     pub request_method: Option<String>,
+    /// A claimed response body is a JSON array of breaches to be counted.
+    #[serde(rename = "countsBreaches", default)]
+    pub counts_breaches: bool,
+    /// Header carrying the service's API key; the service is skipped without one.
+    #[serde(rename = "apiKeyHeader", skip_serializing_if = "Option::is_none")]
+    pub api_key_header: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,6 +39,8 @@ pub fn get_email_services() -> HashMap<String, EmailService> {
             error_type: EmailErrorType::Message,
             error_msg: Some("User not found".to_string()),
             request_method: None,
+            counts_breaches: false,
+            api_key_header: None,
         },
     );
 
@@ -44,6 +52,8 @@ pub fn get_email_services() -> HashMap<String, EmailService> {
             error_type: EmailErrorType::StatusCode,
             error_msg: None,
             request_method: None,
+            counts_breaches: false,
+            api_key_header: None,
         },
     );
 
@@ -55,8 +65,17 @@ pub fn get_email_services() -> HashMap<String, EmailService> {
             error_type: EmailErrorType::StatusCode,
             error_msg: None,
             request_method: None,
+            counts_breaches: true,
+            api_key_header: Some("hibp-api-key".to_string()),
         },
     );
 
     services
 }
+
+/// Counts entries in a breach-list response body (a JSON array), if it is one.
+pub fn parse_breach_count(body: &str) -> Option<usize> {
+    serde_json::from_str::<Vec<serde_json::Value>>(body)
+        .ok()
+        .map(|breaches| breaches.len())
+}
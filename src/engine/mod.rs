@@ -1,20 +1,67 @@
-use crate::data::{ErrorMessages, ErrorType, SiteInfo};
-use crate::http::HttpClient;
-use crate::ratelimit::RateLimiterHandle;
-use regex::Regex;
+use crate::concurrency::AdaptiveConcurrency;
+use crate::data::{ErrorType, SiteInfo};
+use crate::http::{read_body_text, HttpClient, RequestOptions, TrafficStats};
+use crate::latency::HostLatency;
+use crate::ratelimit::{HostLimiter, RateLimiterHandle};
+use crate::regexcache::get_regex;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use futures::{Stream, StreamExt};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use tracing::{debug, info};
 
 pub type ProgressCallback = Arc<dyn Fn(ProgressUpdate) + Send + Sync>;
 
+/// Pushed to the `with_progress_callback` listener as a search runs. Only the
+/// TUI listens, so without it the fields go unread.
 #[derive(Debug, Clone)]
+#[cfg_attr(not(feature = "tui"), allow(dead_code))]
 pub enum ProgressUpdate {
     Started { total: usize, username: String },
     SiteChecked { site: String, url: String, found: bool },
     Completed { total_found: usize },
 }
 
+/// Live counters for the engine's current search, for UIs that poll instead of
+/// listening for `ProgressUpdate`s. Reset at the start of every search.
+#[derive(Debug, Default)]
+pub struct ScanProgress {
+    pub total: AtomicUsize,
+    pub completed: AtomicUsize,
+    pub found: AtomicUsize,
+    /// Set when `--max-errors` stopped the search early.
+    pub aborted: AtomicBool,
+}
+
+impl ScanProgress {
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    pub fn completed(&self) -> usize {
+        self.completed.load(Ordering::Relaxed)
+    }
+
+    pub fn found(&self) -> usize {
+        self.found.load(Ordering::Relaxed)
+    }
+
+    pub fn aborted(&self) -> bool {
+        self.aborted.load(Ordering::Relaxed)
+    }
+
+    fn reset(&self, total: usize) {
+        self.total.store(total, Ordering::Relaxed);
+        self.completed.store(0, Ordering::Relaxed);
+        self.found.store(0, Ordering::Relaxed);
+        self.aborted.store(false, Ordering::Relaxed);
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum QueryStatus {
     #[serde(rename = "claimed")]
@@ -27,6 +74,118 @@ pub enum QueryStatus {
     Illegal,
     #[serde(rename = "unknown")]
     Unknown,
+    #[serde(rename = "timed_out")]
+    TimedOut,
+}
+
+/// Why a check failed, coarse enough to tell a dead network from a slow site.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Timeout,
+    Dns,
+    Connect,
+    Tls,
+    Redirect,
+    Body,
+    Other,
+}
+
+impl ErrorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorKind::Timeout => "timeout",
+            ErrorKind::Dns => "dns",
+            ErrorKind::Connect => "connect",
+            ErrorKind::Tls => "tls",
+            ErrorKind::Redirect => "redirect",
+            ErrorKind::Body => "body",
+            ErrorKind::Other => "other",
+        }
+    }
+
+    /// Classifies a failed request. reqwest reports DNS and TLS failures as
+    /// generic connect errors, so those are told apart by the underlying causes
+    /// (not the top-level message, which contains the URL).
+    pub fn of(error: &reqwest::Error) -> Self {
+        if error.is_timeout() {
+            return ErrorKind::Timeout;
+        }
+        let mut chain = String::new();
+        let mut source = std::error::Error::source(error);
+        while let Some(e) = source {
+            chain.push_str(&e.to_string().to_lowercase());
+            chain.push('\n');
+            source = e.source();
+        }
+        if chain.contains("dns error") || chain.contains("failed to lookup address") {
+            ErrorKind::Dns
+        } else if ["certificate", "tls", "ssl", "handshake"].iter().any(|marker| chain.contains(marker)) {
+            ErrorKind::Tls
+        } else if error.is_connect() {
+            ErrorKind::Connect
+        } else if error.is_redirect() {
+            ErrorKind::Redirect
+        } else if error.is_body() || error.is_decode() {
+            ErrorKind::Body
+        } else {
+            ErrorKind::Other
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SkipReason {
+    /// Username doesn't match the site's `regexCheck`
+    #[serde(rename = "regex")]
+    Regex,
+    /// NSFW site and `--nsfw` not given
+    #[serde(rename = "nsfw")]
+    Nsfw,
+    /// Not selected by `--site`
+    #[serde(rename = "excluded")]
+    Excluded,
+    /// Failed to connect for an earlier username in the same run
+    #[serde(rename = "prior_error")]
+    PriorError,
+    /// Answered 429 earlier in the run and its cooldown hasn't expired
+    #[serde(rename = "rate_limited")]
+    RateLimited,
+    /// Needs an API token (`authTokenEnv`) that isn't set
+    #[serde(rename = "missing_token")]
+    MissingToken,
+    /// Not reached because the scan stopped early (`--first-match`, `--quick`, `--max-errors`)
+    #[serde(rename = "capped")]
+    Capped,
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::Regex => write!(f, "regex"),
+            SkipReason::Nsfw => write!(f, "nsfw"),
+            SkipReason::Excluded => write!(f, "excluded"),
+            SkipReason::PriorError => write!(f, "prior error"),
+            SkipReason::RateLimited => write!(f, "rate limited"),
+            SkipReason::MissingToken => write!(f, "missing token"),
+            SkipReason::Capped => write!(f, "capped"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SkippedSite {
+    pub site_name: String,
+    pub reason: SkipReason,
+}
+
+impl SkippedSite {
+    pub fn new(site_name: &str, reason: SkipReason) -> Self {
+        Self {
+            site_name: site_name.to_string(),
+            reason,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,6 +198,36 @@ pub struct QueryResult {
     pub http_status: Option<u16>,
     pub error_message: Option<String>,
     pub response_time_ms: Option<u64>,
+    /// For `message`-type sites, the configured error string found in the body.
+    pub matched_error_msg: Option<String>,
+    /// The `urlProbe` pattern that produced this result, when the site configures any.
+    pub matched_probe: Option<String>,
+    /// Where the request ended up after following redirects.
+    pub final_url: Option<String>,
+    /// When the check finished; only set with `--timestamps`.
+    pub checked_at: Option<DateTime<Utc>>,
+    /// The site's primary tag, copied from `SiteInfo` at check time.
+    #[serde(default)]
+    pub category: Option<String>,
+    /// The site's `errorType`, copied from `SiteInfo` at check time.
+    #[serde(default)]
+    pub detection: Option<ErrorType>,
+    /// Taken from the `--cache-results` file instead of probing the site.
+    #[serde(default)]
+    pub from_cache: bool,
+    /// Why an `error` or `timed_out` check failed, when known.
+    #[serde(default)]
+    pub error_kind: Option<ErrorKind>,
+    /// The input that produced `username` (file line, pattern or variation);
+    /// only set with `--include-source-column`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// The request failed to connect (DNS, refused, TLS handshake).
+    #[serde(skip)]
+    pub connect_failed: bool,
+    /// How long the site asked us to back off after a 429, from `Retry-After`.
+    #[serde(skip)]
+    pub retry_after_secs: Option<u64>,
 }
 
 impl QueryResult {
@@ -58,6 +247,17 @@ impl QueryResult {
             http_status: None,
             error_message: None,
             response_time_ms: None,
+            matched_error_msg: None,
+            matched_probe: None,
+            final_url: None,
+            checked_at: None,
+            category: None,
+            detection: None,
+            from_cache: false,
+            error_kind: None,
+            source: None,
+            connect_failed: false,
+            retry_after_secs: None,
         }
     }
 
@@ -75,6 +275,20 @@ impl QueryResult {
         result
     }
 
+    pub fn unknown(username: &str, site_name: &str, site_url: &str, profile_url: &str, reason: &str) -> Self {
+        let mut result = Self::new(username, site_name, site_url, profile_url, QueryStatus::Unknown);
+        result.error_message = Some(reason.to_string());
+        result
+    }
+
+    pub fn timed_out(username: &str, site_name: &str, site_url: &str, profile_url: &str, budget_ms: u64) -> Self {
+        let mut result = Self::new(username, site_name, site_url, profile_url, QueryStatus::TimedOut);
+        result.error_message = Some(format!("exceeded per-site budget of {}ms", budget_ms));
+        result.response_time_ms = Some(budget_ms);
+        result.error_kind = Some(ErrorKind::Timeout);
+        result
+    }
+
     pub fn illegal(username: &str, site_name: &str, site_url: &str) -> Self {
         Self::new(username, site_name, site_url, "", QueryStatus::Illegal)
     }
@@ -84,12 +298,38 @@ impl QueryResult {
     }
 }
 
+/// Per-check detection settings, copied into every spawned check.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CheckConfig {
+    /// Treat HTTP 403 as claimed instead of unknown/blocked.
+    pub forbidden_is_claimed: bool,
+    /// For message-type GETs, only request this many leading body bytes.
+    pub range_bytes: Option<u64>,
+    /// Bound on reading a response body, separate from the request timeout.
+    pub body_timeout_ms: Option<u64>,
+}
+
+/// Cooldown after a 429 when the site sends no usable `Retry-After`.
+const DEFAULT_COOLDOWN_SECS: u64 = 30;
+/// Upper bound on honoured `Retry-After` values, so one site can't stall a long run.
+const MAX_COOLDOWN_SECS: u64 = 600;
+
 pub struct SearchEngine {
     http_client: HttpClient,
-    timeout: u64,
     max_concurrent: usize,
     include_nsfw: bool,
+    check_config: CheckConfig,
+    auto_concurrency: bool,
+    site_budget_ms: Option<u64>,
+    timestamps: bool,
+    stop_after_claims: Option<usize>,
+    max_errors: Option<usize>,
     rate_limiter: Option<RateLimiterHandle>,
+    host_limiter: Option<Arc<HostLimiter>>,
+    host_latency: Option<Arc<HostLatency>>,
+    failed_sites: Option<Arc<std::sync::Mutex<HashSet<String>>>>,
+    cooldowns: Arc<std::sync::Mutex<HashMap<String, Instant>>>,
+    progress: Arc<ScanProgress>,
     progress_callback: Option<ProgressCallback>,
 }
 
@@ -97,14 +337,25 @@ impl SearchEngine {
     pub fn new(timeout: u64, max_concurrent: usize, include_nsfw: bool, rotate_ua: bool) -> Result<Self, reqwest::Error> {
         Ok(Self {
             http_client: HttpClient::new(timeout, rotate_ua)?,
-            timeout,
             max_concurrent,
             include_nsfw,
+            check_config: CheckConfig::default(),
+            auto_concurrency: false,
+            site_budget_ms: None,
+            timestamps: false,
+            stop_after_claims: None,
+            max_errors: None,
             rate_limiter: None,
+            host_limiter: None,
+            host_latency: None,
+            failed_sites: Some(Arc::new(std::sync::Mutex::new(HashSet::new()))),
+            cooldowns: Arc::new(std::sync::Mutex::new(HashMap::new())),
+            progress: Arc::new(ScanProgress::default()),
             progress_callback: None,
         })
     }
 
+    #[cfg_attr(not(feature = "tui"), allow(dead_code))]
     pub fn with_progress_callback<F>(mut self, callback: F) -> Self 
     where
         F: Fn(ProgressUpdate) + Send + Sync + 'static,
@@ -113,6 +364,49 @@ impl SearchEngine {
         self
     }
 
+    /// Counters updated by every search on this engine; grab it before starting one
+    /// and poll it from another task or thread.
+    pub fn progress_handle(&self) -> Arc<ScanProgress> {
+        self.progress.clone()
+    }
+
+    /// Treat HTTP 403 as claimed instead of reporting the site as blocked.
+    pub fn with_forbidden_is_claimed(mut self, forbidden_is_claimed: bool) -> Self {
+        self.check_config.forbidden_is_claimed = forbidden_is_claimed;
+        self
+    }
+
+    /// Fetch only the first `max_bytes` of message-type pages, falling back to a
+    /// full GET when the marker may lie beyond the partial body.
+    pub fn with_range_probe(mut self, max_bytes: u64) -> Self {
+        if max_bytes > 0 {
+            self.check_config.range_bytes = Some(max_bytes);
+        }
+        self
+    }
+
+    /// Stop reading a response body after `ms` milliseconds, so a server that
+    /// trickles bytes can't hold a check for the whole request timeout.
+    pub fn with_body_timeout(mut self, ms: u64) -> Self {
+        self.check_config.body_timeout_ms = Some(ms);
+        self
+    }
+
+    /// Adapt the number of in-flight checks (up to `max_concurrent`) to observed
+    /// failures and latency instead of always running at the maximum.
+    pub fn with_auto_concurrency(mut self, enabled: bool) -> Self {
+        self.auto_concurrency = enabled;
+        self
+    }
+
+    /// Abandon any single site check after `budget_ms`, independent of `--timeout`.
+    pub fn with_site_budget(mut self, budget_ms: u64) -> Self {
+        if budget_ms > 0 {
+            self.site_budget_ms = Some(budget_ms);
+        }
+        self
+    }
+
     pub fn with_rate_limit(mut self, rate_limit_ms: u64) -> Self {
         if rate_limit_ms > 0 {
             self.rate_limiter = Some(crate::ratelimit::create_rate_limiter(rate_limit_ms));
@@ -120,301 +414,1948 @@ impl SearchEngine {
         self
     }
 
-    pub fn with_proxy(mut self, proxy: String) -> Self {
-        self.http_client = self.http_client.with_proxy(proxy);
+    /// Stamp each result with the UTC time its check finished.
+    pub fn with_timestamps(mut self, timestamps: bool) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    /// End the search at the first claimed result, cancelling checks still in
+    /// flight and never starting the rest.
+    pub fn with_first_match(self, first_match: bool) -> Self {
+        if first_match {
+            self.with_stop_after_claims(1)
+        } else {
+            self
+        }
+    }
+
+    /// End the search once `claims` sites have claimed the username, like
+    /// `with_first_match` but for a larger count.
+    pub fn with_stop_after_claims(mut self, claims: usize) -> Self {
+        self.stop_after_claims = Some(claims);
+        self
+    }
+
+    /// End the search once `max_errors` checks have errored or timed out, in total
+    /// or in a row, cancelling checks still in flight. The results so far are kept
+    /// and `aborted` reports that it happened.
+    pub fn with_max_errors(mut self, max_errors: usize) -> Self {
+        self.max_errors = Some(max_errors);
+        self
+    }
+
+    /// True if the last search was stopped by `with_max_errors`.
+    pub fn aborted(&self) -> bool {
+        self.progress.aborted()
+    }
+
+    /// Allow at most `per_host` concurrent requests to any one host, shared by all
+    /// searches run on this engine.
+    pub fn with_max_per_host(mut self, per_host: usize) -> Self {
+        if per_host > 0 {
+            self.host_limiter = Some(Arc::new(HostLimiter::new(per_host)));
+        }
+        self
+    }
+
+    /// Time each request out at its host's observed p95 latency plus a margin,
+    /// never longer than `max_secs`. Estimates are shared by all searches run on
+    /// this engine.
+    pub fn with_adaptive_timeout(mut self, max_secs: u64) -> Self {
+        self.host_latency = Some(Arc::new(HostLatency::new(Duration::from_secs(max_secs))));
+        self
+    }
+
+    /// Whether sites that failed to connect are skipped for the rest of the run
+    /// (on by default).
+    pub fn with_negative_cache(mut self, enabled: bool) -> Self {
+        self.failed_sites = if enabled {
+            Some(Arc::new(std::sync::Mutex::new(HashSet::new())))
+        } else {
+            None
+        };
+        self
+    }
+
+    pub fn with_proxy(mut self, proxy: String) -> Result<Self, Box<dyn std::error::Error>> {
+        self.http_client = self.http_client.with_proxy(proxy)?;
+        Ok(self)
+    }
+
+    pub fn with_http_proxy(mut self, proxy: String) -> Result<Self, Box<dyn std::error::Error>> {
+        self.http_client = self.http_client.with_http_proxy(proxy)?;
+        Ok(self)
+    }
+
+    pub fn with_https_proxy(mut self, proxy: String) -> Result<Self, Box<dyn std::error::Error>> {
+        self.http_client = self.http_client.with_https_proxy(proxy)?;
+        Ok(self)
+    }
+
+    pub fn with_no_proxy(mut self, hosts: String) -> Self {
+        self.http_client = self.http_client.with_no_proxy(hosts);
+        self
+    }
+
+    pub fn with_proxy_auth(mut self, user: String, pass: String) -> Self {
+        self.http_client = self.http_client.with_proxy_auth(user, pass);
+        self
+    }
+
+    pub fn with_referer(mut self, referer: String) -> Self {
+        self.http_client = self.http_client.with_referer(referer);
+        self
+    }
+
+    pub fn with_mock(mut self) -> Self {
+        self.http_client = self.http_client.with_mock();
+        self
+    }
+
+    pub fn with_insecure(mut self) -> Self {
+        self.http_client = self.http_client.with_insecure();
         self
     }
 
+    /// Trust `certs` in addition to the system roots; see `HttpClient::with_root_certificates`.
+    pub fn with_root_certificates(mut self, certs: Vec<reqwest::Certificate>) -> Result<Self, reqwest::Error> {
+        self.http_client = self.http_client.with_root_certificates(certs)?;
+        Ok(self)
+    }
+
     pub fn with_tor(mut self) -> Self {
         self.http_client = self.http_client.with_tor();
         self
     }
 
+    /// Sites that `search_username` will leave out because of the NSFW filter.
+    pub fn nsfw_skipped(&self, sites: &HashMap<String, SiteInfo>) -> Vec<SkippedSite> {
+        if self.include_nsfw {
+            return Vec::new();
+        }
+        sites
+            .iter()
+            .filter(|(_, info)| info.is_nsfw.unwrap_or(false))
+            .map(|(name, _)| SkippedSite::new(name, SkipReason::Nsfw))
+            .collect()
+    }
+
+    /// Sites that `search_username` will leave out because their `authTokenEnv`
+    /// variable isn't set.
+    pub fn token_skipped(&self, sites: &HashMap<String, SiteInfo>) -> Vec<SkippedSite> {
+        sites
+            .iter()
+            .filter(|(_, info)| info.missing_auth_token())
+            .map(|(name, _)| SkippedSite::new(name, SkipReason::MissingToken))
+            .collect()
+    }
+
+    /// Sites that `search_username` will leave out because an earlier search on
+    /// this engine couldn't connect to them.
+    pub fn negative_skipped(&self, sites: &HashMap<String, SiteInfo>) -> Vec<SkippedSite> {
+        let failed = match self.failed_sites {
+            Some(ref failed) => failed.lock().unwrap(),
+            None => return Vec::new(),
+        };
+        sites
+            .keys()
+            .filter(|name| failed.contains(*name))
+            .map(|name| SkippedSite::new(name, SkipReason::PriorError))
+            .collect()
+    }
+
+    /// Sites that `search_username` will leave out because they answered 429
+    /// earlier on this engine and are still cooling down.
+    pub fn cooldown_skipped(&self, sites: &HashMap<String, SiteInfo>) -> Vec<SkippedSite> {
+        let cooling = cooling_sites(&self.cooldowns);
+        sites
+            .keys()
+            .filter(|name| cooling.contains(*name))
+            .map(|name| SkippedSite::new(name, SkipReason::RateLimited))
+            .collect()
+    }
+
+    /// Collects every result of `search_stream`.
     pub async fn search_username(
         &self,
         username: &str,
         sites: &HashMap<String, SiteInfo>,
     ) -> Vec<QueryResult> {
+        self.search_stream(username, sites).collect().await
+    }
+
+    /// Checks `username` against `sites`, yielding results as each check finishes.
+    /// The stream ends once every check has completed, or after enough claimed
+    /// results with `with_first_match` / `with_stop_after_claims`.
+    pub fn search_stream(
+        &self,
+        username: &str,
+        sites: &HashMap<String, SiteInfo>,
+    ) -> impl Stream<Item = QueryResult> + Send + 'static {
         use tokio::sync::Semaphore;
-        use std::sync::Arc;
 
-        let sites_to_check: Vec<(String, SiteInfo)> = sites
+        let previously_failed: HashSet<String> = match self.failed_sites {
+            Some(ref failed) => failed.lock().unwrap().clone(),
+            None => HashSet::new(),
+        };
+        let cooling = cooling_sites(&self.cooldowns);
+        let mut sites_to_check: Vec<(String, SiteInfo)> = sites
             .iter()
             .filter(|(_, info)| self.include_nsfw || !info.is_nsfw.unwrap_or(false))
+            .filter(|(_, info)| !info.missing_auth_token())
+            .filter(|(name, _)| !previously_failed.contains(*name))
+            .filter(|(name, _)| !cooling.contains(*name))
             .map(|(name, info)| (name.clone(), info.clone()))
             .collect();
+        // Spawn in a fixed order so which sites get the first permits (and finish
+        // before a deadline or early stop) doesn't vary with HashMap order
+        sites_to_check.sort_by(|a, b| a.0.cmp(&b.0));
 
-        let total = sites_to_check.len();
-        
-        // Send started message
-        if let Some(ref callback) = self.progress_callback {
-            callback(ProgressUpdate::Started { total, username: username.to_string() });
-        }
-
-        let semaphore = Arc::new(Semaphore::new(self.max_concurrent));
+        let (tx, rx) = mpsc::unbounded_channel();
+        let username = username.to_string();
+        let max_concurrent = self.max_concurrent;
+        let auto_concurrency = self.auto_concurrency;
+        let http_client = self.http_client.clone();
         let rate_limiter = self.rate_limiter.clone();
+        let host_limiter = self.host_limiter.clone();
+        let host_latency = self.host_latency.clone();
+        let failed_sites = self.failed_sites.clone();
+        let cooldowns = self.cooldowns.clone();
+        let progress = self.progress.clone();
+        progress.reset(sites_to_check.len());
         let callback = self.progress_callback.clone();
-        let mut handles = Vec::new();
-
-        for (site_name, site_info) in sites_to_check {
-            let permit = semaphore.clone().acquire_owned().await.unwrap();
-            let username = username.to_string();
-            let http_client = self.http_client.clone();
-            let rate_limiter_clone = rate_limiter.clone();
-            let callback_clone = callback.clone();
-
-            let handle = tokio::spawn(async move {
-                // Apply rate limiting if enabled
-                if let Some(ref rl) = rate_limiter_clone {
-                    let domain = site_info.url_main.clone();
-                    if let Ok(domain) = url::Url::parse(&domain) {
-                        if let Some(host) = domain.host_str() {
-                            let mut limiter = rl.write().await;
-                            limiter.wait_for(host).await;
-                        }
-                    }
+        let check_config = self.check_config;
+        let site_budget_ms = self.site_budget_ms;
+        let timestamps = self.timestamps;
+        let stop_after_claims = self.stop_after_claims;
+        let max_errors = self.max_errors;
+
+        tokio::spawn(async move {
+            let total = sites_to_check.len();
+
+            // Send started message
+            if let Some(ref cb) = callback {
+                cb(ProgressUpdate::Started { total, username: username.clone() });
+            }
+
+            let semaphore = Arc::new(Semaphore::new(max_concurrent));
+            let controller = if auto_concurrency {
+                Some(AdaptiveConcurrency::new(5.min(max_concurrent), 1, max_concurrent))
+            } else {
+                None
+            };
+            // `stop` keeps new checks from being spawned; `cancel` aborts those in flight
+            let stop = Arc::new(AtomicBool::new(false));
+            let cancel = Arc::new(watch::Sender::new(false));
+            // Errors seen so far, in total and since the last non-error result
+            let errors = Arc::new(AtomicUsize::new(0));
+            let consecutive_errors = Arc::new(AtomicUsize::new(0));
+            let mut handles = Vec::new();
+
+            for (site_name, site_info) in sites_to_check {
+                if stop.load(Ordering::Relaxed) {
+                    break;
                 }
-                
-                let result = check_site_internal(&http_client, &username, &site_name, &site_info).await;
-                
-                // Report progress
-                if let Some(ref cb) = callback_clone {
-                    if let Some(ref r) = result {
-                        cb(ProgressUpdate::SiteChecked {
-                            site: site_name.clone(),
-                            url: r.profile_url.clone(),
-                            found: r.is_claimed(),
-                        });
-                    }
+                let permit = semaphore.clone().acquire_owned().await.unwrap();
+                if stop.load(Ordering::Relaxed) {
+                    break;
                 }
-                
-                drop(permit);
-                result
-            });
-            handles.push(handle);
-        }
+                let slot = match controller {
+                    Some(ref c) => Some(c.acquire().await),
+                    None => None,
+                };
+                let controller_clone = controller.clone();
+                let username = username.clone();
+                let http_client = http_client.clone();
+                let rate_limiter_clone = rate_limiter.clone();
+                let host_limiter_clone = host_limiter.clone();
+                let host_latency_clone = host_latency.clone();
+                let failed_sites_clone = failed_sites.clone();
+                let cooldowns_clone = cooldowns.clone();
+                let progress_clone = progress.clone();
+                let callback_clone = callback.clone();
+                let tx = tx.clone();
+                let stop_clone = stop.clone();
+                let cancel_clone = cancel.clone();
+                let mut cancelled = cancel.subscribe();
+                let errors_clone = errors.clone();
+                let consecutive_clone = consecutive_errors.clone();
 
-        let mut results = Vec::new();
-        for handle in handles {
-            if let Ok(Some(result)) = handle.await {
-                results.push(result);
-            }
-        }
+                let handle = tokio::spawn(async move {
+                    let run = async {
+                        let host = url::Url::parse(&site_info.url_main)
+                            .ok()
+                            .and_then(|u| u.host_str().map(|h| h.to_string()));
 
-        // Send completed message
-        if let Some(ref callback) = self.progress_callback {
-            let found = results.iter().filter(|r| r.is_claimed()).count();
-            callback(ProgressUpdate::Completed { total_found: found });
-        }
+                        // Apply rate limiting if enabled
+                        if let (Some(ref rl), Some(ref host)) = (&rate_limiter_clone, &host) {
+                            rl.wait_for(host).await;
+                        }
 
-        results
-    }
+                        // Site-specific politeness from the data
+                        if let Some(delay_ms) = site_info.request_delay_ms {
+                            tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+                        }
 
-    async fn check_site(
-        &self,
-        username: &str,
-        site_name: &str,
-        site_info: &SiteInfo,
-    ) -> Option<QueryResult> {
-        if let Some(ref regex) = site_info.regex_check {
-            if let Ok(re) = Regex::new(regex) {
-                if !re.is_match(username) {
-                    return Some(QueryResult::illegal(username, site_name, &site_info.url_main));
-                }
-            }
-        }
+                        let host_permit = match (&host_limiter_clone, &host) {
+                            (Some(hl), Some(host)) => Some(hl.acquire(host).await),
+                            _ => None,
+                        };
 
-        let profile_url = site_info.url.replace("{}", username);
-        let probe_url = site_info.url_probe.as_ref().unwrap_or(&profile_url).replace("{}", username);
+                        let check = check_site_internal(
+                            &http_client,
+                            &username,
+                            &site_name,
+                            &site_info,
+                            &check_config,
+                            host_latency_clone.as_deref(),
+                        );
+                        let result = match site_budget_ms {
+                            Some(budget_ms) => {
+                                match tokio::time::timeout(std::time::Duration::from_millis(budget_ms), check).await {
+                                    Ok(result) => result,
+                                    Err(_) => {
+                                        let profile_url = site_info.profile_url(&username);
+                                        Some(QueryResult::timed_out(&username, &site_name, &site_info.url_main, &profile_url, budget_ms))
+                                    }
+                                }
+                            }
+                            None => check.await,
+                        };
+                        (result, host_permit)
+                    };
 
-        let start = std::time::Instant::now();
+                    let (mut result, host_permit) = tokio::select! {
+                        Ok(_) = cancelled.wait_for(|c| *c) => return false,
+                        outcome = run => outcome,
+                    };
 
-        let result = match site_info.request_method.as_deref() {
-            Some("POST") => {
-                let body = site_info.request_payload.as_ref().map(|p| p.to_string());
-                self.http_client.post(&probe_url, body).await
-            }
-            Some("PUT") => {
-                let body = site_info.request_payload.as_ref().map(|p| p.to_string());
-                self.http_client.put(&probe_url, body).await
-            }
-            Some("HEAD") | None => {
-                if site_info.error_type == ErrorType::StatusCode {
-                    self.http_client.head(&probe_url).await
-                } else {
-                    self.http_client.get(&probe_url).await
-                }
-            }
-            _ => self.http_client.get(&probe_url).await,
-        };
+                    if let Some(ref mut r) = result {
+                        if timestamps {
+                            r.checked_at = Some(Utc::now());
+                        }
+                        r.category = site_info.tags.as_ref().and_then(|tags| tags.first().cloned());
+                        r.detection = Some(site_info.error_type.clone());
+                    }
 
-        let elapsed = start.elapsed().as_millis() as u64;
+                    // Report progress
+                    if let Some(ref cb) = callback_clone {
+                        if let Some(ref r) = result {
+                            cb(ProgressUpdate::SiteChecked {
+                                site: site_name.clone(),
+                                url: r.profile_url.clone(),
+                                found: r.is_claimed(),
+                            });
+                        }
+                    }
 
-        match result {
-            Ok(response) => {
-                let status = response.status();
-                let http_status = status.as_u16();
+                    if let (Some(ref c), Some(ref r)) = (&controller_clone, &result) {
+                        let failed = matches!(r.status, QueryStatus::Error | QueryStatus::TimedOut)
+                            || r.http_status == Some(429);
+                        c.record(r.response_time_ms, failed);
+                    }
 
-                let detected = match site_info.error_type {
-                    ErrorType::StatusCode => {
-                        status == reqwest::StatusCode::OK
+                    if let (Some(ref failed), Some(ref r)) = (&failed_sites_clone, &result) {
+                        if r.connect_failed {
+                            failed.lock().unwrap().insert(site_name.clone());
+                        }
                     }
-                    ErrorType::Message => {
-                        if let Ok(text) = response.text().await {
-                            if let Some(ref error_msgs) = site_info.error_msg {
-                                let msg_list: Vec<&str> = match error_msgs {
-                                    ErrorMessages::Single(s) => vec![s.as_str()],
-                                    ErrorMessages::Multiple(v) => v.iter().map(|s| s.as_str()).collect(),
-                                };
-                                !msg_list.iter().any(|msg| text.contains(msg))
-                            } else {
-                                status == reqwest::StatusCode::OK
-                            }
-                        } else {
-                            status == reqwest::StatusCode::OK
+
+                    if let Some(ref r) = result {
+                        if r.http_status == Some(429) {
+                            let secs = r.retry_after_secs.unwrap_or(DEFAULT_COOLDOWN_SECS).min(MAX_COOLDOWN_SECS);
+                            cooldowns_clone
+                                .lock()
+                                .unwrap()
+                                .insert(site_name.clone(), Instant::now() + Duration::from_secs(secs));
                         }
                     }
-                    ErrorType::Redirect => {
-                        status != reqwest::StatusCode::NOT_FOUND
-                            && status != reqwest::StatusCode::FORBIDDEN
+
+                    drop(host_permit);
+                    drop(slot);
+                    drop(permit);
+
+                    let claimed = result.as_ref().map(|r| r.is_claimed()).unwrap_or(false);
+                    progress_clone.completed.fetch_add(1, Ordering::Relaxed);
+                    if claimed {
+                        let claims = progress_clone.found.fetch_add(1, Ordering::Relaxed) + 1;
+                        let enough = stop_after_claims.is_some_and(|limit| claims >= limit);
+                        if enough && !stop_clone.swap(true, Ordering::Relaxed) {
+                            cancel_clone.send_replace(true);
+                        }
                     }
-                    ErrorType::ResponseUrl => {
-                        if let Some(ref error_url) = site_info.error_url {
-                            let resp_url = response.url();
-                            resp_url.to_string() != *error_url
+                    if let (Some(limit), Some(ref r)) = (max_errors, &result) {
+                        let (total, in_a_row) = if matches!(r.status, QueryStatus::Error | QueryStatus::TimedOut) {
+                            (
+                                errors_clone.fetch_add(1, Ordering::Relaxed) + 1,
+                                consecutive_clone.fetch_add(1, Ordering::Relaxed) + 1,
+                            )
                         } else {
-                            status == reqwest::StatusCode::OK
+                            consecutive_clone.store(0, Ordering::Relaxed);
+                            (errors_clone.load(Ordering::Relaxed), 0)
+                        };
+                        if total.max(in_a_row) >= limit && !stop_clone.swap(true, Ordering::Relaxed) {
+                            progress_clone.aborted.store(true, Ordering::Relaxed);
+                            cancel_clone.send_replace(true);
                         }
                     }
-                };
+                    if let Some(r) = result {
+                        let _ = tx.send(r);
+                    }
+                    claimed
+                });
+                handles.push(handle);
+            }
 
-                let query_result = if detected {
-                    QueryResult::claimed(username, site_name, &site_info.url_main, &profile_url)
-                } else {
-                    QueryResult::available(username, site_name, &site_info.url_main, &profile_url)
-                };
+            let mut found = 0;
+            for handle in handles {
+                if let Ok(true) = handle.await {
+                    found += 1;
+                }
+            }
 
-                Some(QueryResult {
-                    http_status: Some(http_status),
-                    response_time_ms: Some(elapsed),
-                    ..query_result
-                })
+            if let Some(ref c) = controller {
+                info!("Auto-concurrency finished at {} concurrent requests", c.limit());
             }
-            Err(e) => {
-                Some(QueryResult::error(
-                    username,
-                    site_name,
-                    &site_info.url_main,
-                    &profile_url,
-                    &e.to_string(),
-                ))
+
+            // Send completed message
+            if let Some(ref cb) = callback {
+                cb(ProgressUpdate::Completed { total_found: found });
             }
-        }
+        });
+
+        futures::stream::unfold(rx, |mut rx| async move {
+            rx.recv().await.map(|result| (result, rx))
+        })
     }
 
     pub fn is_using_tor(&self) -> bool {
         self.http_client.is_using_tor()
     }
+
+    /// The client used for site checks, with this engine's proxy/Tor settings.
+    pub fn http_client(&self) -> &HttpClient {
+        &self.http_client
+    }
+
+    /// Requests and response bytes used by this engine so far.
+    pub fn traffic(&self) -> TrafficStats {
+        self.http_client.traffic()
+    }
+}
+
+/// Whether the value at `pointer` marks the profile as claimed: equal to `expected`
+/// when the site sets one, otherwise present and neither `null` nor `false`.
+fn json_field_claimed(json: &serde_json::Value, pointer: &str, expected: Option<&serde_json::Value>) -> bool {
+    match (json.pointer(pointer), expected) {
+        (Some(value), Some(expected)) => value == expected,
+        (Some(value), None) => !matches!(value, serde_json::Value::Null | serde_json::Value::Bool(false)),
+        (None, _) => false,
+    }
+}
+
+/// Sites whose 429 cooldown is still running; expired entries are dropped.
+fn cooling_sites(cooldowns: &std::sync::Mutex<HashMap<String, Instant>>) -> HashSet<String> {
+    let now = Instant::now();
+    let mut cooldowns = cooldowns.lock().unwrap();
+    cooldowns.retain(|_, until| *until > now);
+    cooldowns.keys().cloned().collect()
+}
+
+/// Reads the body as text, giving up after `timeout_ms` when set. `Ok(None)`
+/// when reading fails for any other reason.
+async fn read_body_within(
+    response: reqwest::Response,
+    timeout_ms: Option<u64>,
+) -> Result<Option<String>, tokio::time::error::Elapsed> {
+    match timeout_ms {
+        Some(ms) => tokio::time::timeout(Duration::from_millis(ms), read_body_text(response))
+            .await
+            .map(Result::ok),
+        None => Ok(read_body_text(response).await.ok()),
+    }
 }
 
+/// `Retry-After` as a number of seconds; HTTP-date values are converted relative to now.
+fn parse_retry_after(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(secs);
+    }
+    let at = DateTime::parse_from_rfc2822(value).ok()?;
+    Some((at.with_timezone(&Utc) - Utc::now()).num_seconds().max(0) as u64)
+}
+
+#[tracing::instrument(level = "debug", skip_all, fields(site = site_name, username = username))]
 async fn check_site_internal(
     http_client: &HttpClient,
     username: &str,
     site_name: &str,
     site_info: &SiteInfo,
+    config: &CheckConfig,
+    latency: Option<&HostLatency>,
 ) -> Option<QueryResult> {
+    // Results keep the searched username; the site is asked about its own spelling
+    let site_username = site_info.site_username(username);
     if let Some(ref regex) = site_info.regex_check {
-        if let Ok(re) = Regex::new(regex) {
-            if !re.is_match(username) {
+        if let Some(re) = get_regex(regex) {
+            if !re.is_match(&site_username) {
                 return Some(QueryResult::illegal(username, site_name, &site_info.url_main));
             }
         }
     }
 
-    let profile_url = site_info.url.replace("{}", username);
-    let probe_url = site_info.url_probe.as_ref().unwrap_or(&profile_url).replace("{}", username);
+    let profile_url = site_info.profile_url(username);
+
+    let patterns = match site_info.url_probe {
+        Some(ref probe) => probe.patterns(),
+        None => vec![],
+    };
+    if patterns.is_empty() {
+        return Some(probe_site(http_client, username, site_name, site_info, &profile_url, config, latency).await);
+    }
+
+    // Try each probe pattern in order; the first claimed hit wins
+    let mut last = None;
+    for pattern in patterns {
+        let probe_url = pattern.replace("{}", &site_username);
+        let result = probe_site(http_client, username, site_name, site_info, &probe_url, config, latency).await;
+        let result = QueryResult {
+            matched_probe: Some(pattern.to_string()),
+            ..result
+        };
+        debug!(probe = pattern, status = ?result.status, "probe result");
+        if result.is_claimed() {
+            return Some(result);
+        }
+        last = Some(result);
+    }
+    last
+}
+
+/// The HTTP method `probe_site` sends for `site_info`, mirroring its dispatch.
+pub fn request_method(site_info: &SiteInfo) -> &'static str {
+    match site_info.request_method.as_deref() {
+        Some("POST") => "POST",
+        Some("PUT") => "PUT",
+        Some("HEAD") | None if site_info.error_type == ErrorType::StatusCode && site_info.placeholder_msg.is_none() => "HEAD",
+        _ => "GET",
+    }
+}
+
+async fn probe_site(
+    http_client: &HttpClient,
+    username: &str,
+    site_name: &str,
+    site_info: &SiteInfo,
+    probe_url: &str,
+    config: &CheckConfig,
+    latency: Option<&HostLatency>,
+) -> QueryResult {
+    let profile_url = site_info.profile_url(username);
+    let profile_url = profile_url.as_str();
+    let auth = site_info.auth_request_header();
+    let headers: Vec<(&str, &str)> = auth.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect();
+    let probe_host = url::Url::parse(probe_url).ok().and_then(|u| u.host_str().map(|h| h.to_string()));
+    let adaptive = match (latency, &probe_host) {
+        (Some(latency), Some(host)) => Some((latency, host.as_str())),
+        _ => None,
+    };
+    let request_timeout = adaptive.map(|(latency, host)| latency.timeout_for(host));
+    let opts = RequestOptions {
+        referer: site_info.referer.as_deref(),
+        insecure: site_info.insecure.unwrap_or(false),
+        headers: &headers,
+        timeout: request_timeout,
+        ..Default::default()
+    };
+
+    let method = request_method(site_info);
+    let site_username = site_info.site_username(username);
 
     let start = std::time::Instant::now();
 
     let result = match site_info.request_method.as_deref() {
         Some("POST") => {
-            let body = site_info.request_payload.as_ref().map(|p| p.to_string());
-            http_client.post(&probe_url, body).await
+            let body = site_info.request_payload.as_ref().map(|p| fill_payload(p, &site_username).to_string());
+            http_client.post(probe_url, body, &opts).await
         }
         Some("PUT") => {
-            let body = site_info.request_payload.as_ref().map(|p| p.to_string());
-            http_client.put(&probe_url, body).await
+            let body = site_info.request_payload.as_ref().map(|p| fill_payload(p, &site_username).to_string());
+            http_client.put(probe_url, body, &opts).await
         }
         Some("HEAD") | None => {
-            if site_info.error_type == ErrorType::StatusCode {
-                http_client.head(&probe_url).await
+            if site_info.error_type == ErrorType::StatusCode && site_info.placeholder_msg.is_none() {
+                http_client.head(probe_url, &opts).await
+            } else if let (ErrorType::Message, Some(max_bytes)) = (&site_info.error_type, config.range_bytes) {
+                let ranged = RequestOptions {
+                    range_bytes: Some(max_bytes),
+                    ..opts
+                };
+                match http_client.get(probe_url, &ranged).await {
+                    // Body shorter than the range start; retry without Range
+                    Ok(r) if r.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE => {
+                        http_client.get(probe_url, &opts).await
+                    }
+                    other => other,
+                }
             } else {
-                http_client.get(&probe_url).await
+                http_client.get(probe_url, &opts).await
             }
         }
-        _ => http_client.get(&probe_url).await,
+        _ => http_client.get(probe_url, &opts).await,
     };
 
     let elapsed = start.elapsed().as_millis() as u64;
 
     match result {
         Ok(response) => {
-            let status = response.status();
-            let http_status = status.as_u16();
+            let http_status = response.status().as_u16();
+            let final_url = response.url().to_string();
+            debug!(method, url = probe_url, http_status, final_url = %response.url(), elapsed_ms = elapsed, "response");
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string());
+            let content_range = response
+                .headers()
+                .get(reqwest::header::CONTENT_RANGE)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string());
 
-            let detected = match site_info.error_type {
-                ErrorType::StatusCode => {
-                    status == reqwest::StatusCode::OK
-                }
-                ErrorType::Message => {
-                    if let Ok(text) = response.text().await {
-                        if let Some(ref error_msgs) = site_info.error_msg {
-                            let msg_list: Vec<&str> = match error_msgs {
-                                ErrorMessages::Single(s) => vec![s.as_str()],
-                                ErrorMessages::Multiple(v) => v.iter().map(|s| s.as_str()).collect(),
-                            };
-                            !msg_list.iter().any(|msg| text.contains(msg))
-                        } else {
-                            status == reqwest::StatusCode::OK
+            // 429 and 403 are decided without looking at the page
+            let needs_body = !matches!(http_status, 429 | 403)
+                && (matches!(site_info.error_type, ErrorType::Message | ErrorType::JsonField)
+                    || site_info.meta_refresh.unwrap_or(false)
+                    || site_info.placeholder_msg.is_some());
+            let mut body = if needs_body {
+                match read_body_within(response, config.body_timeout_ms).await {
+                    Ok(body) => {
+                        if let Some(ref text) = body {
+                            debug!(snippet = %body_snippet(text), "body");
                         }
-                    } else {
-                        status == reqwest::StatusCode::OK
+                        body
                     }
-                }
-                ErrorType::Redirect => {
-                    status != reqwest::StatusCode::NOT_FOUND
-                        && status != reqwest::StatusCode::FORBIDDEN
-                }
-                ErrorType::ResponseUrl => {
-                    if let Some(ref error_url) = site_info.error_url {
-                        let resp_url = response.url();
-                        resp_url.to_string() != *error_url
-                    } else {
-                        status == reqwest::StatusCode::OK
+                    Err(_) => {
+                        return QueryResult {
+                            http_status: Some(http_status),
+                            response_time_ms: Some(start.elapsed().as_millis() as u64),
+                            error_message: Some(format!(
+                                "response body not received within {}ms",
+                                config.body_timeout_ms.unwrap_or_default()
+                            )),
+                            final_url: Some(final_url),
+                            error_kind: Some(ErrorKind::Timeout),
+                            ..QueryResult::new(username, site_name, &site_info.url_main, profile_url, QueryStatus::TimedOut)
+                        };
                     }
                 }
-            };
-
-            let query_result = if detected {
-                QueryResult::claimed(username, site_name, &site_info.url_main, &profile_url)
             } else {
-                QueryResult::available(username, site_name, &site_info.url_main, &profile_url)
+                None
             };
 
-            Some(QueryResult {
-                http_status: Some(http_status),
+            // The marker may sit past a partial body; confirm with a full GET, unless
+            // the range already covered the whole page
+            if http_status == 206 && site_info.error_type == ErrorType::Message {
+                let markers = site_info.error_msg.as_ref().map(|m| m.messages()).unwrap_or_default();
+                let has_marker = body.as_deref().is_some_and(|text| markers.iter().any(|m| text.contains(m)));
+                let truncated = content_range.as_deref().is_none_or(range_truncated);
+                if !markers.is_empty() && body.is_some() && !has_marker && truncated {
+                    if let Ok(full) = http_client.get(probe_url, &opts).await {
+                        if let Ok(Some(full_text)) = read_body_within(full, config.body_timeout_ms).await {
+                            body = Some(full_text);
+                        }
+                    }
+                }
+            }
+
+            if let Some((latency, host)) = adaptive {
+                latency.record(host, start.elapsed().as_millis() as u64);
+            }
+
+            let response = ProbeResponse {
+                status: http_status,
+                final_url: &final_url,
+                retry_after: retry_after.as_deref(),
+                body: body.as_deref(),
+            };
+            QueryResult {
                 response_time_ms: Some(elapsed),
-                ..query_result
-            })
+                ..classify_response(username, site_name, site_info, profile_url, &response, config)
+            }
         }
         Err(e) => {
-            Some(QueryResult::error(
+            debug!(method, url = probe_url, error = %e, "request failed");
+            // A timeout counts as a slow sample so the estimate can recover
+            if let (Some((latency, host)), Some(timeout), true) = (adaptive, request_timeout, e.is_timeout()) {
+                latency.record(host, timeout.as_millis() as u64);
+            }
+            QueryResult {
+                connect_failed: e.is_connect(),
+                error_kind: Some(ErrorKind::of(&e)),
+                ..QueryResult::error(
+                    username,
+                    site_name,
+                    &site_info.url_main,
+                    profile_url,
+                    &e.to_string(),
+                )
+            }
+        }
+    }
+}
+
+/// Whether a `Content-Range` of `bytes START-END/TOTAL` stops short of the end.
+/// An unknown (`*`) or unreadable total counts as truncated.
+fn range_truncated(content_range: &str) -> bool {
+    let parsed = content_range
+        .strip_prefix("bytes ")
+        .and_then(|rest| rest.split_once('/'))
+        .and_then(|(span, total)| {
+            let end: u64 = span.split_once('-')?.1.trim().parse().ok()?;
+            let total: u64 = total.trim().parse().ok()?;
+            Some((end, total))
+        });
+    match parsed {
+        Some((end, total)) => end + 1 < total,
+        None => true,
+    }
+}
+
+/// A response as detection sees it, whether fetched live or replayed from a capture.
+#[derive(Debug, Clone, Copy)]
+pub struct ProbeResponse<'a> {
+    pub status: u16,
+    /// Where the request ended up after following redirects.
+    pub final_url: &'a str,
+    pub retry_after: Option<&'a str>,
+    /// The decoded page, when the site's rule needs it.
+    pub body: Option<&'a str>,
+}
+
+/// Applies `site_info`'s detection rule to one response. Makes no requests, so
+/// the same verdicts come from live probes and `watson replay`. The caller fills
+/// in `response_time_ms`.
+pub fn classify_response(
+    username: &str,
+    site_name: &str,
+    site_info: &SiteInfo,
+    profile_url: &str,
+    response: &ProbeResponse<'_>,
+    config: &CheckConfig,
+) -> QueryResult {
+    let http_status = response.status;
+    let mut final_url = Some(response.final_url.to_string());
+    let site_url = site_info.url_main.as_str();
+
+    // Rate limited: the status says nothing about the profile
+    if http_status == 429 {
+        return QueryResult {
+            http_status: Some(http_status),
+            final_url,
+            retry_after_secs: response.retry_after.and_then(parse_retry_after),
+            ..QueryResult::unknown(username, site_name, site_url, profile_url, "rate limited")
+        };
+    }
+
+    // WAFs commonly answer 403 regardless of whether the profile exists
+    if http_status == 403 {
+        let query_result = if config.forbidden_is_claimed {
+            QueryResult::claimed(username, site_name, site_url, profile_url)
+        } else {
+            QueryResult::unknown(username, site_name, site_url, profile_url, "blocked")
+        };
+        return QueryResult {
+            http_status: Some(http_status),
+            final_url,
+            ..query_result
+        };
+    }
+
+    let body = response.body;
+
+    // "Create your profile" pages answer 200 for unclaimed names
+    if let (Some(ref placeholders), Some(text)) = (&site_info.placeholder_msg, body) {
+        if let Some(placeholder) = placeholders.messages().into_iter().find(|m| text.contains(m)) {
+            return QueryResult {
+                http_status: Some(http_status),
+                matched_error_msg: Some(placeholder.to_string()),
+                final_url,
+                ..QueryResult::available(username, site_name, site_url, profile_url)
+            };
+        }
+    }
+
+    let json = match site_info.error_type {
+        ErrorType::JsonField => match body.and_then(|text| serde_json::from_str(text).ok()) {
+            Some(json) => Some(json),
+            None => {
+                return QueryResult {
+                    http_status: Some(http_status),
+                    final_url,
+                    ..QueryResult::unknown(username, site_name, site_url, profile_url, "response is not JSON")
+                };
+            }
+        },
+        _ => None,
+    };
+
+    let meta_target = match (site_info.meta_refresh.unwrap_or(false), body) {
+        (true, Some(text)) => parse_meta_refresh(text)
+            .and_then(|target| url::Url::parse(response.final_url).ok()?.join(&target).ok())
+            .map(|u| u.to_string()),
+        _ => None,
+    };
+
+    // Sites judged by status alone can't say anything about statuses outside their policy
+    let status_verdict = site_info.status_policy().classify(http_status);
+    let by_status = match site_info.error_type {
+        ErrorType::StatusCode => true,
+        ErrorType::Redirect => meta_target.is_none(),
+        _ => false,
+    };
+    if by_status && status_verdict.is_none() {
+        return QueryResult {
+            http_status: Some(http_status),
+            final_url,
+            ..QueryResult::unknown(
                 username,
                 site_name,
-                &site_info.url_main,
-                &profile_url,
-                &e.to_string(),
-            ))
+                site_url,
+                profile_url,
+                &format!("unexpected HTTP {}", http_status),
+            )
+        };
+    }
+
+    let mut matched_error_msg = None;
+
+    let detected = match site_info.error_type {
+        ErrorType::StatusCode => status_verdict.unwrap_or(false),
+        ErrorType::Message => {
+            let ok = http_status == 200 || http_status == 206;
+            match body {
+                Some(text) => {
+                    // Only blank messages configured: fall back to the status code
+                    let msg_list = site_info.error_msg.as_ref().map(|m| m.messages()).filter(|l| !l.is_empty());
+                    match msg_list {
+                        Some(msg_list) => {
+                            matched_error_msg = msg_list.iter().find(|msg| text.contains(*msg)).map(|msg| msg.to_string());
+                            matched_error_msg.is_none()
+                        }
+                        None => ok,
+                    }
+                }
+                None => ok,
+            }
+        }
+        ErrorType::Redirect => match meta_target {
+            // A meta refresh is the page redirecting away, same as a 3xx
+            Some(ref target) => {
+                final_url = Some(target.clone());
+                false
+            }
+            None => status_verdict.unwrap_or(false),
+        },
+        ErrorType::JsonField => match (&json, &site_info.json_pointer) {
+            (Some(json), Some(pointer)) => json_field_claimed(json, pointer, site_info.json_claimed_value.as_ref()),
+            _ => http_status == 200,
+        },
+        ErrorType::ResponseUrl => match site_info.error_url {
+            Some(ref error_url) => {
+                let landed = match meta_target {
+                    Some(ref target) => {
+                        final_url = Some(target.clone());
+                        target.clone()
+                    }
+                    None => response.final_url.to_string(),
+                };
+                landed != *error_url
+            }
+            None => http_status == 200,
+        },
+    };
+
+    debug!(
+        error_type = ?site_info.error_type,
+        matched_error_msg = matched_error_msg.as_deref().unwrap_or_default(),
+        claimed = detected,
+        "decision"
+    );
+    let query_result = if detected {
+        QueryResult::claimed(username, site_name, site_url, profile_url)
+    } else {
+        QueryResult::available(username, site_name, site_url, profile_url)
+    };
+
+    QueryResult {
+        http_status: Some(http_status),
+        matched_error_msg,
+        final_url,
+        ..query_result
+    }
+}
+
+/// The start of a response body on one line, for debug logs.
+fn body_snippet(text: &str) -> String {
+    const SNIPPET_CHARS: usize = 200;
+    let flat = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    match flat.char_indices().nth(SNIPPET_CHARS) {
+        Some((cut, _)) => format!("{}…", &flat[..cut]),
+        None => flat,
+    }
+}
+
+/// Substitutes `{}` with the username in every string inside a request payload,
+/// including object keys and nested arrays/objects.
+fn fill_payload(value: &serde_json::Value, username: &str) -> serde_json::Value {
+    use serde_json::Value;
+
+    match value {
+        Value::String(s) => Value::String(s.replace("{}", username)),
+        Value::Array(items) => Value::Array(items.iter().map(|v| fill_payload(v, username)).collect()),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(k, v)| (k.replace("{}", username), fill_payload(v, username)))
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// Returns the raw target of a `<meta http-equiv="refresh" content="0; url=...">` tag, if present.
+fn parse_meta_refresh(body: &str) -> Option<String> {
+    let tag_re = get_regex(r#"(?is)<meta\s[^>]*http-equiv\s*=\s*["']?refresh["']?[^>]*>"#)?;
+    let content_re = get_regex(r#"(?is)content\s*=\s*["']?\s*\d*\s*[;,]?\s*url\s*=\s*['"]?([^"'>\s]+)"#)?;
+
+    let tag = tag_re.find(body)?;
+    content_re
+        .captures(tag.as_str())
+        .and_then(|c| c.get(1))
+        .map(|m| m.as_str().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{path_of, serve};
+
+    fn site(json: serde_json::Value) -> HashMap<String, SiteInfo> {
+        HashMap::from([("Local".to_string(), serde_json::from_value(json).unwrap())])
+    }
+
+    fn classify(site_info: &SiteInfo, status: u16, body: &str) -> QueryResult {
+        let response = ProbeResponse {
+            status,
+            final_url: "https://example.com/alice",
+            retry_after: None,
+            body: Some(body),
+        };
+        classify_response("alice", "Example", site_info, "https://example.com/alice", &response, &CheckConfig::default())
+    }
+
+    #[test]
+    fn blank_error_msg_falls_back_to_status_code() {
+        let info: SiteInfo = serde_json::from_value(serde_json::json!({
+            "url": "https://example.com/{}",
+            "urlMain": "https://example.com/",
+            "errorType": "message",
+            "errorMsg": "",
+        }))
+        .unwrap();
+        // `"".contains("")` would call every profile available
+        assert_eq!(classify(&info, 200, "<html>alice's profile</html>").status, QueryStatus::Claimed);
+        assert_eq!(classify(&info, 404, "<html>no such user</html>").status, QueryStatus::Available);
+    }
+
+    #[test]
+    fn any_listed_error_msg_means_available() {
+        let info: SiteInfo = serde_json::from_value(serde_json::json!({
+            "url": "https://example.com/{}",
+            "urlMain": "https://example.com/",
+            "errorType": "message",
+            "errorMsg": ["Not Found", "This account is suspended"],
+        }))
+        .unwrap();
+        let result = classify(&info, 200, "<html>This account is suspended</html>");
+        assert_eq!(result.status, QueryStatus::Available);
+        assert_eq!(result.matched_error_msg.as_deref(), Some("This account is suspended"));
+
+        let result = classify(&info, 200, "<html>Not Found</html>");
+        assert_eq!(result.matched_error_msg.as_deref(), Some("Not Found"));
+
+        let result = classify(&info, 200, "<html>alice's profile</html>");
+        assert_eq!(result.status, QueryStatus::Claimed);
+        assert_eq!(result.matched_error_msg, None);
+    }
+
+    #[test]
+    fn json_field_reads_the_pointed_value() {
+        let info = |claimed_value: Option<serde_json::Value>| -> SiteInfo {
+            let mut json = serde_json::json!({
+                "url": "https://example.com/{}",
+                "urlMain": "https://example.com/",
+                "errorType": "json_field",
+                "jsonPointer": "/data/user/exists",
+            });
+            if let Some(value) = claimed_value {
+                json["jsonClaimedValue"] = value;
+            }
+            serde_json::from_value(json).unwrap()
+        };
+        let any = info(None);
+        assert_eq!(classify(&any, 200, r#"{"data":{"user":{"exists":true}}}"#).status, QueryStatus::Claimed);
+        assert_eq!(classify(&any, 200, r#"{"data":{"user":{"exists":"yes"}}}"#).status, QueryStatus::Claimed);
+        assert_eq!(classify(&any, 200, r#"{"data":{"user":{"exists":false}}}"#).status, QueryStatus::Available);
+        assert_eq!(classify(&any, 200, r#"{"data":{"user":null}}"#).status, QueryStatus::Available);
+        assert_eq!(classify(&any, 200, "<html>Not Found</html>").status, QueryStatus::Unknown);
+
+        let exact = info(Some(serde_json::json!(1)));
+        assert_eq!(classify(&exact, 200, r#"{"data":{"user":{"exists":1}}}"#).status, QueryStatus::Claimed);
+        assert_eq!(classify(&exact, 200, r#"{"data":{"user":{"exists":0}}}"#).status, QueryStatus::Available);
+    }
+
+    #[test]
+    fn nsfw_and_token_skips_name_their_sites() {
+        let mut sites = site(serde_json::json!({
+            "url": "https://example.com/{}",
+            "urlMain": "https://example.com/",
+            "errorType": "status_code",
+            "isNSFW": true,
+            "authTokenEnv": "WATSON_TEST_TOKEN_NEVER_SET",
+        }));
+        sites.insert(
+            "Safe".to_string(),
+            serde_json::from_value(serde_json::json!({
+                "url": "https://safe.example/{}",
+                "urlMain": "https://safe.example/",
+                "errorType": "status_code",
+            }))
+            .unwrap(),
+        );
+
+        let engine = SearchEngine::new(5, 4, false, false).unwrap();
+        let nsfw = engine.nsfw_skipped(&sites);
+        assert_eq!(nsfw.len(), 1);
+        assert_eq!((nsfw[0].site_name.as_str(), nsfw[0].reason), ("Local", SkipReason::Nsfw));
+        assert!(SearchEngine::new(5, 4, true, false).unwrap().nsfw_skipped(&sites).is_empty());
+
+        let token = engine.token_skipped(&sites);
+        assert_eq!(token.len(), 1);
+        assert_eq!((token[0].site_name.as_str(), token[0].reason), ("Local", SkipReason::MissingToken));
+    }
+
+    #[tokio::test]
+    async fn connect_failures_and_429s_are_skipped_next_time() {
+        let addr = serve(|_| "HTTP/1.1 429 Too Many Requests\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()).await;
+        // Bound then dropped, so connecting is refused
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let mut sites = HashMap::new();
+        for (name, addr) in [("Limited", addr), ("Down", closed)] {
+            let info = serde_json::from_value(serde_json::json!({
+                "url": format!("http://{}/{{}}", addr),
+                "urlMain": format!("http://{}/", addr),
+                "errorType": "status_code",
+            }))
+            .unwrap();
+            sites.insert(name.to_string(), info);
+        }
+
+        let engine = SearchEngine::new(5, 4, false, false).unwrap();
+        engine.search_username("alice", &sites).await;
+
+        let prior = engine.negative_skipped(&sites);
+        assert_eq!(prior.len(), 1);
+        assert_eq!((prior[0].site_name.as_str(), prior[0].reason), ("Down", SkipReason::PriorError));
+        let cooling = engine.cooldown_skipped(&sites);
+        assert_eq!(cooling.len(), 1);
+        assert_eq!((cooling[0].site_name.as_str(), cooling[0].reason), ("Limited", SkipReason::RateLimited));
+    }
+
+    #[tokio::test]
+    async fn refused_host_is_not_checked_for_the_next_username() {
+        let addr = serve(|_| "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()).await;
+        let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let mut sites = HashMap::new();
+        for (name, addr) in [("Up", addr), ("Down", closed)] {
+            let info = serde_json::from_value(serde_json::json!({
+                "url": format!("http://{}/{{}}", addr),
+                "urlMain": format!("http://{}/", addr),
+                "errorType": "status_code",
+            }))
+            .unwrap();
+            sites.insert(name.to_string(), info);
+        }
+        let names = |results: &[QueryResult]| {
+            let mut names: Vec<String> = results.iter().map(|r| r.site_name.clone()).collect();
+            names.sort();
+            names
+        };
+
+        let engine = SearchEngine::new(5, 4, false, false).unwrap();
+        let first = engine.search_username("alice", &sites).await;
+        assert_eq!(names(&first), ["Down", "Up"]);
+        assert!(first.iter().any(|r| r.site_name == "Down" && r.connect_failed));
+
+        let second = engine.search_username("bob", &sites).await;
+        assert_eq!(names(&second), ["Up"]);
+
+        // Without the cache every username tries the host again
+        let engine = SearchEngine::new(5, 4, false, false).unwrap().with_negative_cache(false);
+        engine.search_username("alice", &sites).await;
+        assert_eq!(names(&engine.search_username("bob", &sites).await), ["Down", "Up"]);
+    }
+
+    #[tokio::test]
+    async fn forbidden_is_blocked_unless_treated_as_claimed() {
+        let addr = serve(|_| "HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()).await;
+        let sites = site(serde_json::json!({
+            "url": format!("http://{}/{{}}", addr),
+            "urlMain": format!("http://{}/", addr),
+            "errorType": "status_code",
+        }));
+
+        let engine = SearchEngine::new(5, 4, false, false).unwrap();
+        let results = engine.search_username("alice", &sites).await;
+        assert_eq!(results[0].status, QueryStatus::Unknown);
+        assert_eq!(results[0].error_message.as_deref(), Some("blocked"));
+
+        let engine = SearchEngine::new(5, 4, false, false).unwrap().with_forbidden_is_claimed(true);
+        let results = engine.search_username("alice", &sites).await;
+        assert_eq!(results[0].status, QueryStatus::Claimed);
+        assert_eq!(results[0].http_status, Some(403));
+    }
+
+    #[tokio::test]
+    async fn url_probe_tries_the_next_pattern_until_one_claims() {
+        let addr = serve(|request| match path_of(request) {
+            "/new/alice" => "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+            _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+        })
+        .await;
+        let old = format!("http://{}/old/{{}}", addr);
+        let new = format!("http://{}/new/{{}}", addr);
+        let sites = site(serde_json::json!({
+            "url": format!("http://{}/{{}}", addr),
+            "urlMain": format!("http://{}/", addr),
+            "urlProbe": [old, new],
+            "errorType": "status_code",
+        }));
+        let engine = SearchEngine::new(5, 4, false, false).unwrap();
+
+        let results = engine.search_username("alice", &sites).await;
+        assert_eq!(results[0].status, QueryStatus::Claimed);
+        assert_eq!(results[0].matched_probe.as_deref(), Some(new.as_str()));
+
+        // No pattern claims: the last one's verdict stands
+        let results = engine.search_username("bob", &sites).await;
+        assert_eq!(results[0].status, QueryStatus::Available);
+        assert_eq!(results[0].matched_probe.as_deref(), Some(new.as_str()));
+    }
+
+    #[tokio::test]
+    async fn final_url_follows_redirects() {
+        let addr = serve(|request| match path_of(request) {
+            "/user/alice" => "HTTP/1.1 302 Found\r\nLocation: /login?next=alice,profile\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+            _ => "HTTP/1.1 200 OK\r\nContent-Length: 10\r\nConnection: close\r\n\r\nPlease log".to_string(),
+        })
+        .await;
+        let sites = site(serde_json::json!({
+            "url": format!("http://{}/user/{{}}", addr),
+            "urlMain": format!("http://{}/", addr),
+            "errorType": "message",
+            "errorMsg": "Please log",
+        }));
+        let engine = SearchEngine::new(5, 4, false, false).unwrap();
+        let results = engine.search_username("alice", &sites).await;
+        assert_eq!(results[0].status, QueryStatus::Available);
+        assert_eq!(results[0].final_url.as_deref(), Some(format!("http://{}/login?next=alice,profile", addr).as_str()));
+    }
+
+    #[tokio::test]
+    async fn category_comes_from_the_first_tag() {
+        let addr = serve(|_| "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()).await;
+        let sites = site(serde_json::json!({
+            "url": format!("http://{}/{{}}", addr),
+            "urlMain": format!("http://{}/", addr),
+            "errorType": "status_code",
+            "tags": ["social", "dev"],
+        }));
+        let engine = SearchEngine::new(5, 4, false, false).unwrap();
+        let results = engine.search_username("alice", &sites).await;
+        let json = serde_json::to_value(&results[0]).unwrap();
+        assert_eq!(json["category"], "social");
+    }
+
+    #[tokio::test]
+    async fn referer_is_sent_from_site_or_global_default() {
+        // 200 only when the expected Referer comes along, as some sites do
+        let addr = serve(|request| {
+            if request.to_ascii_lowercase().contains("\r\nreferer: https://example.com/\r\n") {
+                "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+            } else {
+                "HTTP/1.1 403 Forbidden\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+            }
+        })
+        .await;
+        let check = |referer: Option<&str>| {
+            let mut json = serde_json::json!({
+                "url": format!("http://{}/{{}}", addr),
+                "urlMain": format!("http://{}/", addr),
+                "errorType": "status_code",
+            });
+            if let Some(referer) = referer {
+                json["referer"] = referer.into();
+            }
+            site(json)
+        };
+        let engine = || SearchEngine::new(5, 4, false, false).unwrap().with_negative_cache(false);
+
+        let results = engine().search_username("alice", &check(Some("https://example.com/"))).await;
+        assert_eq!(results[0].status, QueryStatus::Claimed);
+
+        let results = engine().search_username("alice", &check(None)).await;
+        assert_ne!(results[0].status, QueryStatus::Claimed);
+
+        let global = engine().with_referer("https://example.com/".to_string());
+        let results = global.search_username("alice", &check(None)).await;
+        assert_eq!(results[0].status, QueryStatus::Claimed);
+
+        // A site's own referer wins over the default
+        let results = global.search_username("alice", &check(Some("https://other.example/"))).await;
+        assert_ne!(results[0].status, QueryStatus::Claimed);
+    }
+
+    #[tokio::test]
+    async fn site_budget_times_out_a_slow_site() {
+        // Connections queue in the backlog but are never answered
+        let silent = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = silent.local_addr().unwrap();
+        let sites = site(serde_json::json!({
+            "url": format!("http://{}/{{}}", addr),
+            "urlMain": format!("http://{}/", addr),
+            "errorType": "status_code",
+        }));
+
+        let engine = SearchEngine::new(30, 4, false, false).unwrap().with_site_budget(200);
+        let started = Instant::now();
+        let results = engine.search_username("alice", &sites).await;
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(results[0].status, QueryStatus::TimedOut);
+        assert_eq!(results[0].error_message.as_deref(), Some("exceeded per-site budget of 200ms"));
+    }
+
+    static EARLY_STOP_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+    #[tokio::test]
+    async fn early_stop_ends_the_search_after_enough_claims() {
+        let addr = serve(|_| {
+            EARLY_STOP_REQUESTS.fetch_add(1, Ordering::SeqCst);
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+        })
+        .await;
+        let silent = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let local = |addr: std::net::SocketAddr| -> SiteInfo {
+            serde_json::from_value(serde_json::json!({
+                "url": format!("http://{}/{{}}", addr),
+                "urlMain": format!("http://{}/", addr),
+                "errorType": "status_code",
+            }))
+            .unwrap()
+        };
+        let claimed: HashMap<String, SiteInfo> = ["A", "B", "C", "D"].map(|name| (name.to_string(), local(addr))).into();
+
+        // One check at a time: the rest are never started
+        let engine = SearchEngine::new(5, 1, false, false).unwrap().with_first_match(true);
+        let results = engine.search_username("alice", &claimed).await;
+        assert_eq!(results.len(), 1);
+        assert_eq!(EARLY_STOP_REQUESTS.swap(0, Ordering::SeqCst), 1);
+
+        let engine = SearchEngine::new(5, 1, false, false).unwrap().with_stop_after_claims(2);
+        let results = engine.search_username("alice", &claimed).await;
+        assert_eq!(results.len(), 2);
+        assert_eq!(EARLY_STOP_REQUESTS.swap(0, Ordering::SeqCst), 2);
+
+        // A check still in flight is cancelled rather than waited for
+        let sites = HashMap::from([("A".to_string(), local(addr)), ("Z".to_string(), local(silent.local_addr().unwrap()))]);
+        let engine = SearchEngine::new(30, 4, false, false).unwrap().with_first_match(true);
+        let started = Instant::now();
+        let results = engine.search_username("alice", &sites).await;
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(results.len(), 1);
+        assert_eq!((results[0].site_name.as_str(), results[0].status), ("A", QueryStatus::Claimed));
+    }
+
+    #[tokio::test]
+    async fn body_timeout_gives_up_on_a_stalled_body() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Sends the headers and the start of the body, then stalls
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let _ = socket.read(&mut [0; 4096]).await;
+                    let _ = socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\n<html>").await;
+                    tokio::time::sleep(Duration::from_secs(30)).await;
+                });
+            }
+        });
+        let sites = site(serde_json::json!({
+            "url": format!("http://{}/{{}}", addr),
+            "urlMain": format!("http://{}/", addr),
+            "errorType": "message",
+            "errorMsg": "Not Found",
+        }));
+
+        let engine = SearchEngine::new(30, 1, false, false).unwrap().with_body_timeout(200);
+        let started = Instant::now();
+        let results = engine.search_username("alice", &sites).await;
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(results[0].status, QueryStatus::TimedOut);
+        assert_eq!(results[0].error_kind, Some(ErrorKind::Timeout));
+        assert_eq!(results[0].http_status, Some(200));
+        assert_eq!(results[0].error_message.as_deref(), Some("response body not received within 200ms"));
+    }
+
+    #[tokio::test]
+    async fn failures_are_classified_by_kind() {
+        let silent = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let refused = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let up = crate::testutil::serve(|_| "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()).await;
+        let local = |base: String| -> SiteInfo {
+            serde_json::from_value(serde_json::json!({
+                "url": format!("{}{{}}", base),
+                "urlMain": base,
+                "errorType": "status_code",
+            }))
+            .unwrap()
+        };
+        let sites = HashMap::from([
+            ("Silent".to_string(), local(format!("http://{}/", silent.local_addr().unwrap()))),
+            ("Refused".to_string(), local(format!("http://{}/", refused))),
+            ("Unresolvable".to_string(), local("http://watson-test.invalid/".to_string())),
+            ("Up".to_string(), local(format!("http://{}/", up))),
+        ]);
+
+        let engine = SearchEngine::new(1, 4, false, false).unwrap();
+        let results = engine.search_username("alice", &sites).await;
+        let kind = |name: &str| results.iter().find(|r| r.site_name == name).unwrap().error_kind;
+        assert_eq!(kind("Silent"), Some(ErrorKind::Timeout));
+        assert_eq!(kind("Refused"), Some(ErrorKind::Connect));
+        assert_eq!(kind("Unresolvable"), Some(ErrorKind::Dns));
+        assert_eq!(kind("Up"), None);
+
+        let report = crate::output::SearchReport::new("alice".to_string(), results, false);
+        assert_eq!(
+            report.error_reasons,
+            std::collections::BTreeMap::from([("connect".to_string(), 1), ("dns".to_string(), 1), ("timeout".to_string(), 1)])
+        );
+    }
+
+    #[tokio::test]
+    async fn timestamps_are_set_only_when_enabled() {
+        let addr = crate::testutil::serve(|_| "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()).await;
+        let sites = site(serde_json::json!({
+            "url": format!("http://{}/{{}}", addr),
+            "urlMain": format!("http://{}/", addr),
+            "errorType": "status_code",
+        }));
+
+        let results = SearchEngine::new(5, 1, false, false).unwrap().search_username("alice", &sites).await;
+        assert_eq!(results[0].checked_at, None);
+
+        let before = Utc::now();
+        let engine = SearchEngine::new(5, 1, false, false).unwrap().with_timestamps(true);
+        let results = engine.search_username("alice", &sites).await;
+        let checked_at = results[0].checked_at.expect("timestamp with --timestamps");
+        assert!(before <= checked_at && checked_at <= Utc::now());
+    }
+
+    #[tokio::test]
+    async fn reachability_counts_sites_that_answered_at_all() {
+        let up = crate::testutil::serve(|request| {
+            let status = match crate::testutil::path_of(request) {
+                "/found/alice" => "200 OK",
+                "/gone/alice" => "404 Not Found",
+                _ => "500 Internal Server Error",
+            };
+            format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status)
+        })
+        .await;
+        let refused = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let local = |base: String| -> SiteInfo {
+            serde_json::from_value(serde_json::json!({
+                "url": format!("{}{{}}", base),
+                "urlMain": base,
+                "errorType": "status_code",
+            }))
+            .unwrap()
+        };
+        let unreachable = HashMap::from([
+            ("Refused".to_string(), local(format!("http://{}/", refused))),
+            ("Unresolvable".to_string(), local("http://watson-test.invalid/".to_string())),
+        ]);
+        let mut mixed = unreachable.clone();
+        for path in ["found", "gone", "broken"] {
+            mixed.insert(path.to_string(), local(format!("http://{}/{}/", up, path)));
+        }
+
+        let engine = SearchEngine::new(5, 5, false, false).unwrap();
+        let report = crate::output::SearchReport::new("alice".to_string(), engine.search_username("alice", &mixed).await, false);
+        assert_eq!((report.reachable_count, report.unreachable_count), (3, 2));
+        assert_eq!(report.reachability_summary(), "3 of 5 checked sites responded (2 unreachable)");
+        assert!(!report.nothing_reachable());
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!((json["reachable_count"].as_u64(), json["unreachable_count"].as_u64()), (Some(3), Some(2)));
+
+        // A fresh engine, as this one now skips the sites that failed
+        let engine = SearchEngine::new(5, 5, false, false).unwrap();
+        let report = crate::output::SearchReport::new("alice".to_string(), engine.search_username("alice", &unreachable).await, false);
+        assert_eq!((report.reachable_count, report.unreachable_count), (0, 2));
+        assert!(report.nothing_reachable());
+    }
+
+    #[tokio::test]
+    async fn debug_events_carry_the_request_and_decision() {
+        use std::sync::Mutex;
+
+        struct Captured(Arc<Mutex<Vec<u8>>>);
+        impl std::io::Write for Captured {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.lock().unwrap().extend_from_slice(buf);
+                Ok(buf.len())
+            }
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let addr = serve(|_| {
+            let body = "<html>Profile of alice</html>";
+            format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+        })
+        .await;
+        let sites = site(serde_json::json!({
+            "url": format!("http://{}/u/{{}}", addr),
+            "urlMain": format!("http://{}/", addr),
+            "errorType": "message",
+            "errorMsg": "Not Found",
+        }));
+
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let captured = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || Captured(captured.clone()))
+            .finish();
+        let guard = tracing::subscriber::set_default(subscriber);
+        let results = SearchEngine::new(5, 1, false, false).unwrap().search_username("alice", &sites).await;
+        drop(guard);
+        assert_eq!(results[0].status, QueryStatus::Claimed);
+
+        let log = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+        let event = |message: &str| {
+            log.lines()
+                .find(|line| line.contains("check_site_internal{site=\"Local\" username=\"alice\"}: watson::engine:") && line.contains(message))
+                .unwrap_or_else(|| panic!("no {:?} event in {}", message, log))
+                .to_string()
+        };
+        let response = event(" response ");
+        assert!(response.contains("method=\"GET\""), "{}", response);
+        assert!(response.contains(&format!("url=\"http://{}/u/alice\"", addr)), "{}", response);
+        assert!(response.contains("http_status=200"), "{}", response);
+        assert!(event(" body ").contains("snippet=<html>Profile of alice</html>"));
+        let decision = event(" decision ");
+        assert!(decision.contains("error_type=Message") && decision.contains("claimed=true"), "{}", decision);
+    }
+
+    #[tokio::test]
+    async fn progress_handle_counts_up_to_the_totals() {
+        let addr = serve(|request| {
+            let status = if path_of(request).starts_with("/yes/") { "200 OK" } else { "404 Not Found" };
+            format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status)
+        })
+        .await;
+        let sites: HashMap<String, SiteInfo> = (0..6)
+            .map(|i| {
+                let path = if i % 2 == 0 { "yes" } else { "no" };
+                let info = serde_json::from_value(serde_json::json!({
+                    "url": format!("http://{}/{}/{{}}", addr, path),
+                    "urlMain": format!("http://{}/", addr),
+                    "errorType": "status_code",
+                }))
+                .unwrap();
+                (format!("Site{}", i), info)
+            })
+            .collect();
+
+        let engine = SearchEngine::new(5, 1, false, false).unwrap();
+        let progress = engine.progress_handle();
+        let mut stream = Box::pin(engine.search_stream("alice", &sites));
+        let mut seen = 0;
+        while let Some(_result) = stream.next().await {
+            seen += 1;
+            assert_eq!(progress.total(), 6);
+            assert!(progress.completed() >= seen && progress.completed() <= 6);
+            assert!(progress.found() <= progress.completed());
         }
+        assert_eq!(seen, 6);
+        assert_eq!((progress.completed(), progress.found()), (6, 3));
+        assert!(!progress.aborted());
+    }
+
+    /// Claimed only for requests carrying the test token, in either header.
+    fn token_gated(request: &str) -> String {
+        let authorized = request.lines().any(|line| {
+            let (name, value) = line.split_once(':').unwrap_or_default();
+            let value = value.trim();
+            (name.eq_ignore_ascii_case("authorization") && value == "Bearer s3cret")
+                || (name.eq_ignore_ascii_case("x-api-key") && value == "s3cret")
+        });
+        let status = if authorized { "200 OK" } else { "404 Not Found" };
+        format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status)
+    }
+
+    #[tokio::test]
+    async fn site_token_is_sent_in_its_header() {
+        std::env::set_var("WATSON_TEST_TOKEN_SET", "s3cret");
+        let addr = serve(token_gated).await;
+        let local = |extra: serde_json::Value| -> SiteInfo {
+            let mut json = serde_json::json!({
+                "url": format!("http://{}/{{}}", addr),
+                "urlMain": format!("http://{}/", addr),
+                "errorType": "status_code",
+            });
+            json.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+            serde_json::from_value(json).unwrap()
+        };
+        let sites = HashMap::from([
+            ("Bearer".to_string(), local(serde_json::json!({ "authTokenEnv": "WATSON_TEST_TOKEN_SET" }))),
+            (
+                "ApiKey".to_string(),
+                local(serde_json::json!({ "authTokenEnv": "WATSON_TEST_TOKEN_SET", "authHeader": "X-Api-Key" })),
+            ),
+            ("Anonymous".to_string(), local(serde_json::json!({}))),
+        ]);
+
+        let engine = SearchEngine::new(5, 4, false, false).unwrap();
+        let mut results = engine.search_username("alice", &sites).await;
+        results.sort_by(|a, b| a.site_name.cmp(&b.site_name));
+        let statuses: Vec<(&str, QueryStatus)> = results.iter().map(|r| (r.site_name.as_str(), r.status)).collect();
+        assert_eq!(
+            statuses,
+            [("Anonymous", QueryStatus::Available), ("ApiKey", QueryStatus::Claimed), ("Bearer", QueryStatus::Claimed)]
+        );
+    }
+
+    #[tokio::test]
+    async fn username_transform_picks_the_probed_url() {
+        let addr = serve(|request| {
+            let status = if path_of(request) == "/johndoe" { "200 OK" } else { "404 Not Found" };
+            format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status)
+        })
+        .await;
+        let local = |transform: serde_json::Value| -> SiteInfo {
+            serde_json::from_value(serde_json::json!({
+                "url": format!("http://{}/{{}}", addr),
+                "urlMain": format!("http://{}/", addr),
+                "errorType": "status_code",
+                "usernameTransform": transform,
+            }))
+            .unwrap()
+        };
+        let sites = HashMap::from([
+            ("Strict".to_string(), local(serde_json::json!(["lowercase", "strip_dots"]))),
+            ("DotsOnly".to_string(), local(serde_json::json!("strip_dots"))),
+        ]);
+
+        let engine = SearchEngine::new(5, 4, false, false).unwrap();
+        let mut results = engine.search_username("John.Doe", &sites).await;
+        results.sort_by(|a, b| a.site_name.cmp(&b.site_name));
+        assert_eq!((results[0].site_name.as_str(), results[0].status), ("DotsOnly", QueryStatus::Available));
+        assert_eq!(results[0].profile_url, format!("http://{}/JohnDoe", addr));
+        assert_eq!((results[1].site_name.as_str(), results[1].status), ("Strict", QueryStatus::Claimed));
+        assert_eq!(results[1].profile_url, format!("http://{}/johndoe", addr));
+    }
+
+    /// `/alice` bounces to `/error` with a meta refresh; any other profile is a plain page.
+    fn meta_refreshing(request: &str) -> String {
+        let body = if path_of(request).ends_with("/alice") {
+            r#"<html><head><meta http-equiv="refresh" content="0; url=/error"></head></html>"#
+        } else {
+            "<html>profile</html>"
+        };
+        format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+    }
+
+    #[tokio::test]
+    async fn meta_refresh_to_the_error_url_is_available() {
+        let addr = serve(meta_refreshing).await;
+        let sites = HashMap::from([
+            (
+                "ByUrl".to_string(),
+                serde_json::from_value(serde_json::json!({
+                    "url": format!("http://{}/url/{{}}", addr),
+                    "urlMain": format!("http://{}/", addr),
+                    "errorType": "response_url",
+                    "errorUrl": format!("http://{}/error", addr),
+                    "metaRefresh": true,
+                }))
+                .unwrap(),
+            ),
+            (
+                "ByRedirect".to_string(),
+                serde_json::from_value(serde_json::json!({
+                    "url": format!("http://{}/redirect/{{}}", addr),
+                    "urlMain": format!("http://{}/", addr),
+                    "errorType": "redirect",
+                    "metaRefresh": true,
+                }))
+                .unwrap(),
+            ),
+        ]);
+
+        let engine = SearchEngine::new(5, 4, false, false).unwrap();
+        for result in engine.search_username("alice", &sites).await {
+            assert_eq!(result.status, QueryStatus::Available, "{}", result.site_name);
+            assert_eq!(result.final_url, Some(format!("http://{}/error", addr)));
+        }
+        for result in engine.search_username("bob", &sites).await {
+            assert_eq!(result.status, QueryStatus::Claimed, "{}", result.site_name);
+        }
+    }
+
+    #[tokio::test]
+    async fn placeholder_page_is_available_despite_the_200() {
+        let addr = serve(|request| {
+            let body = if path_of(request) == "/alice" { "<h1>Claim this username</h1>" } else { "<h1>bob's profile</h1>" };
+            format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+        })
+        .await;
+        let sites = site(serde_json::json!({
+            "url": format!("http://{}/{{}}", addr),
+            "urlMain": format!("http://{}/", addr),
+            "errorType": "status_code",
+            "placeholderMsg": ["This page is empty", "Claim this username"],
+        }));
+
+        let engine = SearchEngine::new(5, 1, false, false).unwrap();
+        let results = engine.search_username("alice", &sites).await;
+        assert_eq!(results[0].status, QueryStatus::Available);
+        assert_eq!(results[0].matched_error_msg.as_deref(), Some("Claim this username"));
+        let results = engine.search_username("bob", &sites).await;
+        assert_eq!(results[0].status, QueryStatus::Claimed);
+    }
+
+    #[tokio::test]
+    async fn request_delay_holds_back_only_its_site() {
+        let addr = serve(|_| "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()).await;
+        let local = |delay: Option<u64>| -> SiteInfo {
+            serde_json::from_value(serde_json::json!({
+                "url": format!("http://{}/{{}}", addr),
+                "urlMain": format!("http://{}/", addr),
+                "errorType": "status_code",
+                "requestDelayMs": delay,
+            }))
+            .unwrap()
+        };
+        let sites = HashMap::from([("Slow".to_string(), local(Some(400))), ("Fast".to_string(), local(None))]);
+
+        let engine = SearchEngine::new(5, 2, false, false).unwrap();
+        let started = Instant::now();
+        let mut stream = Box::pin(engine.search_stream("alice", &sites));
+        let first = stream.next().await.unwrap();
+        assert_eq!(first.site_name, "Fast");
+        assert!(started.elapsed() < Duration::from_millis(400));
+        let second = stream.next().await.unwrap();
+        assert_eq!(second.site_name, "Slow");
+        assert!(started.elapsed() >= Duration::from_millis(400));
+    }
+
+    #[tokio::test]
+    async fn per_host_cap_holds_across_searches() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Answers slowly, recording the most requests it had in flight at once
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (in_flight_server, peak_server) = (in_flight.clone(), peak.clone());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let (in_flight, peak) = (in_flight_server.clone(), peak_server.clone());
+                tokio::spawn(async move {
+                    let _ = socket.read(&mut [0; 4096]).await;
+                    peak.fetch_max(in_flight.fetch_add(1, Ordering::SeqCst) + 1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    let _ = socket.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").await;
+                });
+            }
+        });
+        let sites: HashMap<String, SiteInfo> = (0..3)
+            .map(|i| {
+                let info = serde_json::from_value(serde_json::json!({
+                    "url": format!("http://{}/{}/{{}}", addr, i),
+                    "urlMain": format!("http://{}/", addr),
+                    "errorType": "status_code",
+                }))
+                .unwrap();
+                (format!("Site{}", i), info)
+            })
+            .collect();
+
+        // Each search alone would run all three checks at once
+        let engine = SearchEngine::new(5, 4, false, false).unwrap().with_max_per_host(2);
+        let (alice, bob) = tokio::join!(engine.search_username("alice", &sites), engine.search_username("bob", &sites));
+        assert_eq!((alice.len(), bob.len()), (3, 3));
+        assert_eq!(peak.swap(0, Ordering::SeqCst), 2);
+
+        let carol = engine.search_username("carol", &sites).await;
+        assert_eq!(carol.len(), 3);
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn stream_yields_every_site_exactly_once() {
+        let addr = serve(|request| {
+            let status = if path_of(request).starts_with("/yes/") { "200 OK" } else { "404 Not Found" };
+            format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status)
+        })
+        .await;
+        let refused = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let mut sites: HashMap<String, SiteInfo> = (0..8)
+            .map(|i| {
+                let path = if i % 2 == 0 { "yes" } else { "no" };
+                let info = serde_json::from_value(serde_json::json!({
+                    "url": format!("http://{}/{}/{{}}", addr, path),
+                    "urlMain": format!("http://{}/", addr),
+                    "errorType": "status_code",
+                }))
+                .unwrap();
+                (format!("Site{}", i), info)
+            })
+            .collect();
+        sites.insert(
+            "Down".to_string(),
+            serde_json::from_value(serde_json::json!({
+                "url": format!("http://{}/{{}}", refused),
+                "urlMain": format!("http://{}/", refused),
+                "errorType": "status_code",
+            }))
+            .unwrap(),
+        );
+
+        let engine = SearchEngine::new(5, 4, false, false).unwrap();
+        let results: Vec<QueryResult> = engine.search_stream("alice", &sites).collect().await;
+        let mut seen: Vec<&str> = results.iter().map(|r| r.site_name.as_str()).collect();
+        seen.sort();
+        let mut expected: Vec<&str> = sites.keys().map(String::as_str).collect();
+        expected.sort();
+        assert_eq!(seen, expected);
+        assert_eq!(results.iter().filter(|r| r.is_claimed()).count(), 4);
+        assert_eq!(results.iter().filter(|r| r.status == QueryStatus::Error).count(), 1);
+    }
+
+    static REQUEST_ORDER: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+    #[tokio::test]
+    async fn sites_are_checked_in_name_order_every_run() {
+        let addr = serve(|request| {
+            REQUEST_ORDER.lock().unwrap().push(path_of(request).trim_start_matches('/').to_string());
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+        })
+        .await;
+        let names = ["Zeta", "Mid", "Alpha", "Omega", "Beta", "Kappa"];
+        let engine = SearchEngine::new(5, 1, false, false).unwrap();
+        for _ in 0..3 {
+            // A fresh map each run, so its iteration order is reshuffled
+            let sites: HashMap<String, SiteInfo> = names
+                .iter()
+                .map(|name| {
+                    let info = serde_json::from_value(serde_json::json!({
+                        "url": format!("http://{}/{}", addr, name),
+                        "urlMain": format!("http://{}/", addr),
+                        "errorType": "status_code",
+                    }))
+                    .unwrap();
+                    (name.to_string(), info)
+                })
+                .collect();
+            engine.search_username("alice", &sites).await;
+            let order = std::mem::take(&mut *REQUEST_ORDER.lock().unwrap());
+            assert_eq!(order, ["Alpha", "Beta", "Kappa", "Mid", "Omega", "Zeta"]);
+        }
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn insecure_sites_accept_an_untrusted_certificate() {
+        let addr = crate::testutil::serve_tls(|_| {
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+        })
+        .await;
+        let local = |insecure: bool| -> SiteInfo {
+            serde_json::from_value(serde_json::json!({
+                "url": format!("https://localhost:{}/{{}}", addr.port()),
+                "urlMain": format!("https://localhost:{}/", addr.port()),
+                "errorType": "status_code",
+                "insecure": insecure,
+            }))
+            .unwrap()
+        };
+        let sites = HashMap::from([("Strict".to_string(), local(false)), ("Lenient".to_string(), local(true))]);
+
+        let engine = SearchEngine::new(5, 2, false, false).unwrap();
+        let mut results = engine.search_username("alice", &sites).await;
+        results.sort_by(|a, b| a.site_name.cmp(&b.site_name));
+        assert_eq!((results[0].site_name.as_str(), results[0].status), ("Lenient", QueryStatus::Claimed));
+        assert_eq!((results[1].site_name.as_str(), results[1].status), ("Strict", QueryStatus::Error));
+        assert_eq!(results[1].error_kind, Some(ErrorKind::Tls));
+
+        // --insecure covers every site
+        let engine = SearchEngine::new(5, 2, false, false).unwrap().with_insecure();
+        let results = engine.search_username("alice", &sites).await;
+        assert!(results.iter().all(|r| r.status == QueryStatus::Claimed));
+    }
+
+    #[tokio::test]
+    async fn traffic_counts_a_request_per_checked_site() {
+        let addr = serve(|_| "HTTP/1.1 200 OK\r\nContent-Length: 12\r\nConnection: close\r\n\r\nprofile page".to_string()).await;
+        let local = |error_type: &str| -> SiteInfo {
+            serde_json::from_value(serde_json::json!({
+                "url": format!("http://{}/{}/{{}}", addr, error_type),
+                "urlMain": format!("http://{}/", addr),
+                "errorType": error_type,
+                "errorMsg": "Not Found",
+            }))
+            .unwrap()
+        };
+        let sites = HashMap::from([
+            ("ByStatus".to_string(), local("status_code")),
+            ("ByMessage".to_string(), local("message")),
+            ("AlsoByMessage".to_string(), local("message")),
+        ]);
+
+        // The status_code site is probed with HEAD, so only the message sites read a body
+        let engine = SearchEngine::new(5, 4, false, false).unwrap();
+        let results = engine.search_username("alice", &sites).await;
+        assert_eq!(results.len(), 3);
+        let traffic = engine.traffic();
+        assert_eq!((traffic.requests, traffic.bytes), (3, 24));
+    }
+
+    static FAILING_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+    #[tokio::test]
+    async fn max_errors_stops_probing_the_remaining_sites() {
+        // Hangs up without answering, so every check errors
+        let addr = serve(|_| {
+            FAILING_REQUESTS.fetch_add(1, Ordering::SeqCst);
+            String::new()
+        })
+        .await;
+        let sites: HashMap<String, SiteInfo> = (0..10)
+            .map(|i| {
+                let info = serde_json::from_value(serde_json::json!({
+                    "url": format!("http://{}/{{}}", addr),
+                    "urlMain": format!("http://{}/", addr),
+                    "errorType": "status_code",
+                }))
+                .unwrap();
+                (format!("Site{}", i), info)
+            })
+            .collect();
+
+        let engine = SearchEngine::new(5, 1, false, false).unwrap().with_max_errors(3);
+        let results = engine.search_username("alice", &sites).await;
+        assert!(engine.aborted());
+        assert_eq!(results.len(), 3);
+        assert!(results.iter().all(|r| r.status == QueryStatus::Error));
+        assert_eq!(FAILING_REQUESTS.load(Ordering::SeqCst), 3);
+        assert_eq!(engine.progress_handle().completed(), 3);
+    }
+
+    #[test]
+    fn payload_gets_the_username_with_json_escaping() {
+        let payload = serde_json::json!({
+            "query": "user:{}",
+            "variables": { "login": "{}", "first": 1, "aliases": ["{}", true] },
+        });
+        let username = "a\"b\\c";
+        let body = fill_payload(&payload, username).to_string();
+        assert!(body.contains(r#""login":"a\"b\\c""#));
+        let parsed: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(
+            parsed,
+            serde_json::json!({
+                "query": "user:a\"b\\c",
+                "variables": { "login": username, "first": 1, "aliases": [username, true] },
+            })
+        );
+    }
+
+    #[test]
+    fn range_truncation_from_content_range() {
+        assert!(range_truncated("bytes 0-8191/53210"));
+        assert!(!range_truncated("bytes 0-1023/1024"));
+        assert!(range_truncated("bytes 0-8191/*"));
+        assert!(range_truncated("garbage"));
+    }
+
+    static RANGE_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+    /// Honours `Range: bytes=0-99`. `/small/` pages fit in the range; `/big/` pages
+    /// carry their "Not Found" marker past it.
+    fn ranged_page(request: &str) -> String {
+        RANGE_REQUESTS.fetch_add(1, Ordering::SeqCst);
+        let page = if path_of(request).starts_with("/small/") {
+            "<html>profile</html>".to_string()
+        } else {
+            format!("<html>{}Not Found</html>", "x".repeat(200))
+        };
+        if request.to_ascii_lowercase().contains("range: bytes=0-99") {
+            let part = &page[..page.len().min(100)];
+            format!(
+                "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes 0-{}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                part.len() - 1,
+                page.len(),
+                part.len(),
+                part
+            )
+        } else {
+            format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", page.len(), page)
+        }
+    }
+
+    #[tokio::test]
+    async fn range_probe_refetches_only_truncated_pages() {
+        let addr = serve(ranged_page).await;
+        let engine = SearchEngine::new(5, 4, false, false).unwrap().with_range_probe(100);
+        let check = |kind: &str| {
+            site(serde_json::json!({
+                "url": format!("http://{}/{}/{{}}", addr, kind),
+                "urlMain": format!("http://{}/", addr),
+                "errorType": "message",
+                "errorMsg": "Not Found",
+            }))
+        };
+
+        // The whole page came back as 206: no second request
+        let results = engine.search_username("alice", &check("small")).await;
+        assert_eq!(results[0].status, QueryStatus::Claimed);
+        assert_eq!(results[0].http_status, Some(206));
+        assert_eq!(RANGE_REQUESTS.swap(0, Ordering::SeqCst), 1);
+
+        // The marker lies past the range: the full GET finds it
+        let results = engine.search_username("alice", &check("big")).await;
+        assert_eq!(results[0].status, QueryStatus::Available);
+        assert_eq!(RANGE_REQUESTS.swap(0, Ordering::SeqCst), 2);
     }
 }
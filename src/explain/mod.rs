@@ -0,0 +1,163 @@
+use crate::data::{ErrorType, SiteInfo};
+use crate::engine::{request_method, QueryResult, QueryStatus};
+
+/// Step-by-step account of one site's check for `--explain`: what was sent, what
+/// came back, which detection rule applied and why it gave `result`. `result` is
+/// `None` when the site was skipped instead of checked.
+pub fn explain(
+    username: &str,
+    site_name: &str,
+    site_info: &SiteInfo,
+    mut headers: Vec<(String, String)>,
+    range_bytes: Option<u64>,
+    result: Option<&QueryResult>,
+) -> String {
+    let method = request_method(site_info);
+    let site_username = site_info.site_username(username);
+    let profile_url = site_info.profile_url(username);
+    let probe_url = result
+        .and_then(|r| r.matched_probe.as_deref())
+        .map(|pattern| pattern.replace("{}", &site_username))
+        .unwrap_or_else(|| profile_url.clone());
+
+    if let Some((name, _)) = site_info.auth_request_header() {
+        headers.push((name, "<redacted>".to_string()));
+    }
+    if let (Some(max_bytes), ErrorType::Message, "GET") = (range_bytes, &site_info.error_type, method) {
+        headers.push(("Range".to_string(), format!("bytes=0-{}", max_bytes.saturating_sub(1))));
+    }
+
+    let mut output = format!("\n=== Explain: {} for '{}' ===\n", site_name, username);
+    let mut line = |label: &str, value: &str| {
+        // An empty label continues the previous entry
+        let label = if label.is_empty() { String::new() } else { format!("{}:", label) };
+        output.push_str(&format!("{:<16}{}\n", label, value));
+    };
+
+    if site_username != username {
+        line("Site username", &format!("{} (after usernameTransform)", site_username));
+    }
+    line("Profile URL", &profile_url);
+    if probe_url != profile_url {
+        line("Probe URL", &probe_url);
+    }
+    line("Method", method);
+    for (i, (name, value)) in headers.iter().enumerate() {
+        line(if i == 0 { "Headers sent" } else { "" }, &format!("{}: {}", name, value));
+    }
+    line("Detection rule", &detection_rule(site_info));
+
+    let Some(result) = result else {
+        let reason = match site_info.auth_token_env {
+            Some(ref var) if site_info.missing_auth_token() => format!("skipped; set {} to an API token", var),
+            _ => "skipped (NSFW, cooling down or failed earlier)".to_string(),
+        };
+        line("Classification", &reason);
+        return output;
+    };
+
+    let na = "N/A".to_string();
+    line("HTTP status", &result.http_status.map(|s| s.to_string()).unwrap_or_else(|| na.clone()));
+    line(
+        "Response time",
+        &result.response_time_ms.map(|ms| format!("{} ms", ms)).unwrap_or_else(|| na.clone()),
+    );
+    line("Final URL", result.final_url.as_deref().unwrap_or(&na));
+    if let Some(marker) = marker(site_info, result) {
+        line("Marker", &marker);
+    }
+    line("Reason", &reason(site_info, result));
+    line("Classification", &format!("{:?}", result.status).to_lowercase());
+    output
+}
+
+fn quoted(messages: &[&str]) -> String {
+    messages.iter().map(|m| format!("{:?}", m)).collect::<Vec<_>>().join(", ")
+}
+
+/// The rule `probe_site` applies for the site's `errorType`, in words.
+fn detection_rule(site_info: &SiteInfo) -> String {
+    let policy = site_info.status_policy().describe();
+    let rule = match site_info.error_type {
+        ErrorType::StatusCode => format!("status_code: {}, else unknown", policy),
+        ErrorType::Message => match site_info.error_msg.as_ref().map(|m| m.messages()).filter(|l| !l.is_empty()) {
+            Some(messages) => format!("message: available if the page contains {}, else claimed", quoted(&messages)),
+            None => "message (no usable errorMsg): claimed on HTTP 200 or 206".to_string(),
+        },
+        ErrorType::Redirect if site_info.meta_refresh.unwrap_or(false) => {
+            format!("redirect: available on a meta refresh away, else {}, else unknown", policy)
+        }
+        ErrorType::Redirect => format!("redirect: {}, else unknown", policy),
+        ErrorType::ResponseUrl => match site_info.error_url {
+            Some(ref error_url) => format!("response_url: available if the request lands on {}", error_url),
+            None => "response_url (no errorUrl): claimed on HTTP 200".to_string(),
+        },
+        ErrorType::JsonField => match (&site_info.json_pointer, &site_info.json_claimed_value) {
+            (Some(pointer), Some(value)) => format!("json_field: claimed if {} is {}", pointer, value),
+            (Some(pointer), None) => format!("json_field: claimed if {} is present and not null/false", pointer),
+            (None, _) => "json_field (no jsonPointer): claimed on HTTP 200".to_string(),
+        },
+    };
+    match site_info.placeholder_msg {
+        Some(ref placeholders) => format!(
+            "{}; available first if the page contains placeholder {}",
+            rule,
+            quoted(&placeholders.messages())
+        ),
+        None => rule,
+    }
+}
+
+/// Which configured marker was found, or that none was, for rules that look at the body.
+fn marker(site_info: &SiteInfo, result: &QueryResult) -> Option<String> {
+    if let Some(ref found) = result.matched_error_msg {
+        return Some(format!("{:?} found", found));
+    }
+    let uses_markers = site_info.placeholder_msg.is_some()
+        || (site_info.error_type == ErrorType::Message
+            && site_info.error_msg.as_ref().is_some_and(|m| !m.messages().is_empty()));
+    (uses_markers && result.http_status.is_some()).then(|| "none of the markers found".to_string())
+}
+
+fn reason(site_info: &SiteInfo, result: &QueryResult) -> String {
+    let status = result.http_status.map(|s| format!("HTTP {}", s)).unwrap_or_default();
+    match result.status {
+        QueryStatus::Error | QueryStatus::TimedOut | QueryStatus::Unknown => {
+            let detail = result.error_message.as_deref().unwrap_or("no detail");
+            match (detail, result.http_status) {
+                ("rate limited", _) => format!("{} means rate limited; the profile wasn't judged", status),
+                ("blocked", _) => format!("{} is usually a WAF block; pass --403-is-claimed to count it as claimed", status),
+                (_, Some(_)) => format!("{}: {}", status, detail),
+                (_, None) => detail.to_string(),
+            }
+        }
+        QueryStatus::Illegal => "username doesn't match the site's regexCheck, so it was never requested".to_string(),
+        QueryStatus::Claimed | QueryStatus::Available => {
+            let claimed = result.is_claimed();
+            if result.http_status == Some(403) && claimed {
+                return "HTTP 403 counted as claimed (--403-is-claimed)".to_string();
+            }
+            if let Some(ref found) = result.matched_error_msg {
+                let is_placeholder = site_info
+                    .placeholder_msg
+                    .as_ref()
+                    .is_some_and(|p| p.messages().contains(&found.as_str()));
+                let kind = if is_placeholder { "placeholder text" } else { "error marker" };
+                return format!("{} {:?} appeared in the page", kind, found);
+            }
+            match site_info.error_type {
+                ErrorType::Message if claimed => format!("{} and no error marker in the page", status),
+                ErrorType::ResponseUrl if site_info.error_url.is_some() => format!(
+                    "landed on {}",
+                    result.final_url.as_deref().unwrap_or("the requested URL")
+                ),
+                ErrorType::JsonField if site_info.json_pointer.is_some() => format!(
+                    "{} and the JSON field {} the claimed value",
+                    status,
+                    if claimed { "matched" } else { "didn't match" }
+                ),
+                _ => status,
+            }
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Most recent samples kept per host.
+const WINDOW: usize = 50;
+/// Samples needed before a host gets anything tighter than the global timeout.
+const MIN_SAMPLES: usize = 5;
+/// Headroom added on top of a host's p95.
+const MARGIN_MS: u64 = 1000;
+/// Floor for an adapted timeout, however fast the host has been.
+const MIN_TIMEOUT_MS: u64 = 2000;
+
+/// Rolling per-host latency estimate, used to give each request a timeout of
+/// p95 + margin instead of the global one. Shared by all searches on an engine,
+/// so later usernames benefit from what earlier ones observed.
+pub struct HostLatency {
+    max: Duration,
+    hosts: Mutex<HashMap<String, VecDeque<u64>>>,
+}
+
+impl HostLatency {
+    /// `max` bounds every timeout handed out, normally the global `--timeout`.
+    pub fn new(max: Duration) -> Self {
+        Self {
+            max,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Records how long one request to `host` took. Requests that hit their
+    /// timeout should record the timeout, so a host that slows down raises its
+    /// estimate instead of timing out forever.
+    pub fn record(&self, host: &str, latency_ms: u64) {
+        let mut hosts = self.hosts.lock().unwrap();
+        let samples = hosts.entry(host.to_string()).or_default();
+        if samples.len() == WINDOW {
+            samples.pop_front();
+        }
+        samples.push_back(latency_ms);
+    }
+
+    /// The timeout for the next request to `host`: p95 + margin once enough
+    /// samples exist, never below the floor or above the global timeout.
+    pub fn timeout_for(&self, host: &str) -> Duration {
+        let hosts = self.hosts.lock().unwrap();
+        let p95 = match hosts.get(host) {
+            Some(samples) if samples.len() >= MIN_SAMPLES => percentile(samples, 95),
+            _ => return self.max,
+        };
+        Duration::from_millis(p95 + MARGIN_MS).clamp(Duration::from_millis(MIN_TIMEOUT_MS).min(self.max), self.max)
+    }
+}
+
+/// Nearest-rank percentile of a non-empty sample set.
+fn percentile(samples: &VecDeque<u64>, pct: usize) -> u64 {
+    let mut sorted: Vec<u64> = samples.iter().copied().collect();
+    sorted.sort_unstable();
+    let rank = (sorted.len() * pct).div_ceil(100).max(1);
+    sorted[rank - 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeout_is_p95_plus_margin_within_the_clamps() {
+        let latency = HostLatency::new(Duration::from_secs(10));
+        for ms in 1..=4 {
+            latency.record("new.example", ms * 100);
+        }
+        assert_eq!(latency.timeout_for("new.example"), Duration::from_secs(10));
+
+        // Nearest rank 19 of 20 samples
+        for ms in 1..=20 {
+            latency.record("steady.example", ms * 100);
+        }
+        assert_eq!(latency.timeout_for("steady.example"), Duration::from_millis(1900 + MARGIN_MS));
+
+        for _ in 0..MIN_SAMPLES {
+            latency.record("fast.example", 10);
+            latency.record("slow.example", 30_000);
+        }
+        assert_eq!(latency.timeout_for("fast.example"), Duration::from_millis(MIN_TIMEOUT_MS));
+        assert_eq!(latency.timeout_for("slow.example"), Duration::from_secs(10));
+        assert_eq!(latency.timeout_for("unseen.example"), Duration::from_secs(10));
+
+        // A global timeout under the floor still wins
+        let tight = HostLatency::new(Duration::from_secs(1));
+        for _ in 0..MIN_SAMPLES {
+            tight.record("fast.example", 10);
+        }
+        assert_eq!(tight.timeout_for("fast.example"), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn old_samples_leave_the_window() {
+        let latency = HostLatency::new(Duration::from_secs(60));
+        for _ in 0..WINDOW {
+            latency.record("example.com", 20_000);
+        }
+        assert_eq!(latency.timeout_for("example.com"), Duration::from_millis(20_000 + MARGIN_MS));
+        for _ in 0..WINDOW {
+            latency.record("example.com", 1500);
+        }
+        assert_eq!(latency.timeout_for("example.com"), Duration::from_millis(1500 + MARGIN_MS));
+    }
+}
@@ -1,34 +1,116 @@
+mod baseline;
 mod cli;
+mod concurrency;
 mod data;
+#[cfg(feature = "email")]
 mod email;
 mod engine;
+mod explain;
 mod http;
+mod latency;
 mod output;
+mod paste;
+mod presets;
 mod ratelimit;
+mod regexcache;
+mod replay;
+mod resultcache;
+#[cfg(feature = "scrape")]
 mod scrape;
+mod secrets;
+mod selftest;
+mod spinner;
+mod suggest;
+#[cfg(feature = "server")]
+mod server;
+#[cfg(feature = "syslog")]
+mod syslogsink;
+#[cfg(test)]
+mod testutil;
+#[cfg(feature = "tls")]
+mod tls;
+#[cfg(feature = "tui")]
 mod tui;
 mod ua;
 mod variations;
 
-use clap::Parser;
+use baseline::Baseline;
+use clap::{CommandFactory, Parser};
 use cli::{Cli, OutputFormat};
 use data::SitesData;
-use engine::{ProgressUpdate, QueryResult, SearchEngine};
-use output::SearchReport;
+use engine::{QueryResult, SearchEngine, SkipReason, SkippedSite};
+#[cfg(feature = "email")]
+use output::EmailReport;
+use output::{OutputSink, Report, ReportDiff, ScanMetadata, SearchReport, VariationHeatmap};
+use resultcache::ResultCache;
+#[cfg(feature = "scrape")]
 use scrape::scrape_emails_from_results;
+use std::borrow::Cow;
+#[cfg(feature = "email")]
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::fs;
-use std::io::Write;
+use std::io::{IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
 use tracing::info;
 use variations::generate_variations;
 
-fn load_sites_data(local: bool) -> Result<HashMap<String, data::SiteInfo>, Box<dyn std::error::Error>> {
-    let data = if local {
+/// Set by `--compact-json`; JSON reports are written minified.
+static COMPACT_JSON: AtomicBool = AtomicBool::new(false);
+
+/// Set by `--compress zstd`; reports are written zstd-compressed.
+static COMPRESS_ZSTD: AtomicBool = AtomicBool::new(false);
+
+/// Leading bytes of every zstd frame, used to spot compressed reports on load.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// Set from `--output-encoding`; applies to report files, not stdout.
+static OUTPUT_ENCODING: OnceLock<cli::OutputEncoding> = OnceLock::new();
+
+/// `content` in the `--output-encoding` chosen for files.
+fn encode_output(content: &str) -> Cow<'_, [u8]> {
+    match OUTPUT_ENCODING.get().copied().unwrap_or(cli::OutputEncoding::Utf8) {
+        cli::OutputEncoding::Utf8 => Cow::Borrowed(content.as_bytes()),
+        cli::OutputEncoding::Utf8Bom => Cow::Owned([&[0xEF, 0xBB, 0xBF], content.as_bytes()].concat()),
+        cli::OutputEncoding::Utf16le => {
+            let mut bytes = vec![0xFF, 0xFE];
+            bytes.extend(content.encode_utf16().flat_map(u16::to_le_bytes));
+            Cow::Owned(bytes)
+        }
+    }
+}
+
+/// Set by `--quiet`; suppresses everything printed through `status!`.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Set when a report couldn't be serialized as requested and a fallback was
+/// written instead; the run still finishes but exits with status 1.
+static OUTPUT_DEGRADED: AtomicBool = AtomicBool::new(false);
+
+/// Prints a progress/info line to stdout unless `--quiet` is set.
+/// Results and errors should use `println!`/`eprintln!` directly.
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if !QUIET.load(Ordering::Relaxed) {
+            println!($($arg)*);
+        }
+    };
+}
+
+/// Small fixture used by `--mock`; pairs with `http::mock_response`.
+const MOCK_SITES: &str = include_str!("../data/mock_sites.json");
+
+fn load_sites_data(local: bool, mock: bool, dedupe: bool, strict: bool) -> Result<SitesData, Box<dyn std::error::Error>> {
+    let (mut data, source) = if mock {
+        (SitesData::load_from_json(MOCK_SITES, strict)?, "mock")
+    } else if local {
         let path = "data/sites.json";
-        if std::path::Path::new(path).exists() {
-            SitesData::load_from_file(path)?
-        } else {
-            return Err(format!("Error: Local data file not found: {}\nUse --local with a local sites.json or remove --local to fetch from GitHub.", path).into());
+        match [path, "data/sites.json.gz"].into_iter().find(|p| std::path::Path::new(p).exists()) {
+            Some(found) => (SitesData::load_from_file(found, strict)?, found),
+            None => {
+                return Err(format!("Error: Local data file not found: {} (or {}.gz)\nUse --local with a local sites.json or remove --local to fetch from GitHub.", path, path).into());
+            }
         }
     } else {
         let url = "https://raw.githubusercontent.com/sherlock-project/sherlock/master/sherlock_project/resources/data.json";
@@ -45,29 +127,85 @@ fn load_sites_data(local: bool) -> Result<HashMap<String, data::SiteInfo>, Box<d
         }
         
         let json = response.text()?;
-        SitesData::load_from_json(&json)?
+        (SitesData::load_from_json(&json, strict)?, url)
     };
-    
-    Ok(data.sites)
+    data.source = source.to_string();
+    if !data.malformed.is_empty() {
+        eprintln!(
+            "WARNING: Skipped malformed site data entries: {}; use --strict-data to stop on them instead.",
+            data.malformed.join(", ")
+        );
+    }
+    if !data.blank_error_msg.is_empty() {
+        eprintln!(
+            "WARNING: Ignoring empty errorMsg entries for: {}; sites left without one are detected by status code.",
+            data.blank_error_msg.join(", ")
+        );
+    }
+
+    if dedupe {
+        data.dedupe();
+    }
+    Ok(data)
 }
 
 fn handle_output(
-    report: &SearchReport,
+    report: &dyn Report,
     format: &OutputFormat,
     output_file: Option<&str>,
+    html_show_all: bool,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let compress = COMPRESS_ZSTD.load(Ordering::Relaxed) || output_file.is_some_and(|p| p.ends_with(".zst"));
     let content = match format {
         OutputFormat::Text => report.to_text(),
-        OutputFormat::Json => report.to_json()?,
+        OutputFormat::Json => {
+            let json = if COMPACT_JSON.load(Ordering::Relaxed) {
+                report.to_json_compact()
+            } else {
+                report.to_json()
+            };
+            match json {
+                // Highlighting only reaches a terminal; files always get plain JSON
+                Ok(json) if output_file.is_none() && !compress => output::colorize_json(&json),
+                Ok(json) => json,
+                Err(e) => {
+                    eprintln!("Error: Could not serialize report as JSON ({}); writing text format instead", e);
+                    OUTPUT_DEGRADED.store(true, Ordering::Relaxed);
+                    report.to_text()
+                }
+            }
+        }
         OutputFormat::Csv => report.to_csv(),
-        OutputFormat::Html => report.to_html(),
+        OutputFormat::Maltego => report.to_maltego()?,
+        OutputFormat::Bookmarks => report.to_bookmarks(),
+        OutputFormat::Urls => report.to_urls(),
+        OutputFormat::Html => report.to_html(html_show_all),
+        OutputFormat::Term => {
+            // 80 columns when stdout isn't a terminal (piped or redirected)
+            let width = terminal_size::terminal_size()
+                .map(|(terminal_size::Width(cols), _)| cols as usize)
+                .unwrap_or(80);
+            report.to_term(width)
+        }
+        OutputFormat::Msgpack => {
+            let bytes = report.to_msgpack()?;
+            let bytes = if compress { zstd::encode_all(&bytes[..], zstd::DEFAULT_COMPRESSION_LEVEL)? } else { bytes };
+            return write_bytes(output_file, &bytes);
+        }
     };
 
+    if compress {
+        let bytes = if output_file.is_some() { encode_output(&content) } else { Cow::Borrowed(content.as_bytes()) };
+        return write_bytes(output_file, &zstd::encode_all(&bytes[..], zstd::DEFAULT_COMPRESSION_LEVEL)?);
+    }
+
     match output_file {
         Some(path) => {
-            fs::write(path, &content)?;
-            println!("Results saved to: {}", path);
+            fs::write(path, encode_output(&content))?;
+            status!("Results saved to: {}", path);
         }
+        // Already newline-terminated; an extra blank line would reach the next tool
+        None if *format == OutputFormat::Urls => print!("{}", content),
         None => {
             println!("{}", content);
         }
@@ -76,28 +214,163 @@ fn handle_output(
     Ok(())
 }
 
+/// Writes a finished username report in one of the `--format`s to `output_file`,
+/// or to stdout without one.
+struct FormatSink<'a> {
+    format: &'a OutputFormat,
+    output_file: Option<&'a str>,
+    html_show_all: bool,
+}
+
+impl OutputSink for FormatSink<'_> {
+    fn finish(&mut self, report: &SearchReport) -> Result<(), Box<dyn std::error::Error>> {
+        handle_output(report, self.format, self.output_file, self.html_show_all)
+    }
+}
+
+/// Writes a binary report to `output_file`, or raw to stdout without one.
+fn write_bytes(output_file: Option<&str>, bytes: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    match output_file {
+        Some(path) => {
+            fs::write(path, bytes)?;
+            status!("Results saved to: {}", path);
+        }
+        None => std::io::stdout().write_all(bytes)?,
+    }
+    Ok(())
+}
+
+/// Makes a username safe to use as a file name by replacing path separators and
+/// other characters that are reserved on common filesystems.
+fn sanitize_filename(username: &str) -> String {
+    let cleaned: String = username
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    match cleaned.trim_matches('.') {
+        "" => "_".to_string(),
+        _ => cleaned,
+    }
+}
+
+/// The sites in any of the `names` presets, and the rest as `Excluded` skips.
+/// Fails with the first name that isn't a preset.
+fn select_preset_sites(
+    sites: HashMap<String, data::SiteInfo>,
+    presets: &presets::Presets,
+    names: &[String],
+) -> Result<(HashMap<String, data::SiteInfo>, Vec<SkippedSite>), String> {
+    if let Some(unknown) = names.iter().find(|n| !presets.contains_key(&n.to_lowercase())) {
+        return Err(unknown.clone());
+    }
+    let (selected, excluded): (HashMap<_, _>, HashMap<_, _>) =
+        sites.into_iter().partition(|(name, _)| presets::preset_contains(presets, names, name));
+    let skipped = excluded.keys().map(|name| SkippedSite::new(name, SkipReason::Excluded)).collect();
+    Ok((selected, skipped))
+}
+
+/// The non-blank lines of a `--file`, trimmed, with their 1-based line numbers.
+fn listed_usernames(content: &str) -> Vec<(usize, String)> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(i, s)| (i + 1, s.trim().to_string()))
+        .filter(|(_, s)| !s.is_empty())
+        .collect()
+}
+
+/// Drops repeated usernames, keeping each one's first position. Returns how many
+/// were dropped.
+fn dedupe_usernames(users: &mut Vec<String>) -> usize {
+    let listed = users.len();
+    let mut seen = std::collections::HashSet::new();
+    users.retain(|u| seen.insert(u.clone()));
+    listed - users.len()
+}
+
+/// Each name's variations in `generate_variations` order, once each, with its
+/// source: `argument` for the names themselves, `variation of <name>` otherwise.
+fn variation_usernames(names: &[String], argument: &str) -> Vec<(String, String)> {
+    let mut seen = std::collections::HashSet::new();
+    let mut usernames = vec![];
+    for name in names {
+        for variation in generate_variations(name) {
+            if !seen.insert(variation.clone()) {
+                continue;
+            }
+            let source = if variation == *name { argument.to_string() } else { format!("variation of {}", name) };
+            usernames.push((variation, source));
+        }
+    }
+    usernames
+}
+
+/// Whether `username` is 1 to `max_len` characters long. Counted in characters,
+/// not bytes, so accented or emoji usernames get the same limit as ASCII ones.
+fn username_len_ok(username: &str, max_len: usize) -> bool {
+    !username.is_empty() && username.chars().count() <= max_len
+}
+
+/// The first character of `username` that no site would accept: whitespace or a
+/// control character, or with `charset` anything the class doesn't match.
+/// Per-site `regexCheck` still decides for the characters that pass.
+fn disallowed_char(username: &str, charset: Option<&regex::Regex>) -> Option<char> {
+    username.chars().find(|c| {
+        c.is_whitespace()
+            || c.is_control()
+            || charset.is_some_and(|re| !re.is_match(c.encode_utf8(&mut [0; 4])))
+    })
+}
+
+/// Reads a saved report file, decompressing it first if it was written with
+/// `--compress zstd` and converting a BOM-marked `--output-encoding` to plain UTF-8.
+fn read_report_file(path: &str) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let mut bytes = fs::read(path)?;
+    if bytes.starts_with(&ZSTD_MAGIC) {
+        bytes = zstd::decode_all(&bytes[..])?;
+    }
+    if let Some((encoding, _)) = encoding_rs::Encoding::for_bom(&bytes) {
+        let (text, _, _) = encoding.decode(&bytes);
+        return Ok(text.into_owned().into_bytes());
+    }
+    Ok(bytes)
+}
+
+/// Parses a username report, either a `--format msgpack` archive or `--format json`.
+fn parse_search_report(bytes: &[u8]) -> Result<SearchReport, Box<dyn std::error::Error>> {
+    match SearchReport::from_msgpack(bytes) {
+        Ok(report) => Ok(report),
+        Err(_) => Ok(serde_json::from_slice(bytes)?),
+    }
+}
+
+/// Reads a saved username report in any format `parse_search_report` accepts.
+fn load_search_report(path: &str) -> Result<SearchReport, Box<dyn std::error::Error>> {
+    parse_search_report(&read_report_file(path)?)
+}
+
+#[cfg(feature = "email")]
+/// Checks `email` against every email service. `hibp_api_key` is sent to services
+/// that declare an `api_key_header`; without it those services are reported unknown.
 async fn run_email_search(
     email: &str,
-    timeout: u64,
+    http_client: http::HttpClient,
     max_concurrent: usize,
-    proxy: Option<&str>,
-    tor: bool,
-    rotate_ua: bool,
-) -> Result<Vec<QueryResult>, Box<dyn std::error::Error>> {
-    use crate::email::get_email_services;
-    use crate::http::HttpClient;
+    hibp_api_key: Option<&str>,
+) -> Result<(Vec<QueryResult>, BTreeMap<String, usize>), Box<dyn std::error::Error>> {
+    use crate::email::{get_email_services, parse_breach_count};
+    use crate::http::RequestOptions;
     use tokio::sync::Semaphore;
     use std::sync::Arc;
 
-    let services = get_email_services();
+    // Sorted so services are started in the same order every run
+    let mut services: Vec<_> = get_email_services().into_iter().collect();
+    services.sort_by(|a, b| a.0.cmp(&b.0));
     let semaphore = Arc::new(Semaphore::new(max_concurrent));
-    
-    let mut http_client = HttpClient::new(timeout, rotate_ua)?;
-    if tor {
-        http_client = http_client.with_tor();
-    } else if let Some(p) = proxy {
-        http_client = http_client.with_proxy(p.to_string());
-    }
 
     let mut handles = Vec::new();
 
@@ -105,12 +378,29 @@ async fn run_email_search(
         let permit = semaphore.clone().acquire_owned().await.unwrap();
         let email = email.to_string();
         let http_client = http_client.clone();
+        let api_key = hibp_api_key.map(|k| k.to_string());
 
         let handle = tokio::spawn(async move {
+            let _permit = permit;
             let url = service_info.url.replace("{}", &email);
+
+            let headers: Vec<(&str, &str)> = match (&service_info.api_key_header, &api_key) {
+                (Some(header), Some(key)) => vec![(header.as_str(), key.as_str())],
+                (Some(_), None) => {
+                    let reason = "requires an API key (--hibp-api-key or WATSON_HIBP_KEY)";
+                    return Some((QueryResult::unknown(&email, &service_name, &service_info.url_main, &url, reason), None));
+                }
+                _ => vec![],
+            };
+            let opts = RequestOptions {
+                headers: &headers,
+                ..Default::default()
+            };
             
+            let mut breaches = None;
+
             let start = std::time::Instant::now();
-            let result = http_client.get(&url).await;
+            let result = http_client.get(&url, &opts).await;
             let elapsed = start.elapsed().as_millis() as u64;
 
             match result {
@@ -119,9 +409,15 @@ async fn run_email_search(
                     let http_status = status.as_u16();
 
                     let claimed = match service_info.error_type {
+                        email::EmailErrorType::StatusCode if service_info.counts_breaches && status == reqwest::StatusCode::OK => {
+                            if let Ok(text) = http::read_body_text(response).await {
+                                breaches = parse_breach_count(&text);
+                            }
+                            true
+                        }
                         email::EmailErrorType::StatusCode => status == reqwest::StatusCode::OK,
                         email::EmailErrorType::Message => {
-                            if let Ok(text) = response.text().await {
+                            if let Ok(text) = http::read_body_text(response).await {
                                 if let Some(ref err_msg) = service_info.error_msg {
                                     !text.contains(err_msg)
                                 } else {
@@ -139,20 +435,22 @@ async fn run_email_search(
                         QueryResult::available(&email, &service_name, &service_info.url_main, &url)
                     };
 
-                    Some(QueryResult {
+                    let query_result = QueryResult {
                         http_status: Some(http_status),
                         response_time_ms: Some(elapsed),
                         ..query_result
-                    })
+                    };
+                    Some((query_result, breaches))
                 }
                 Err(e) => {
-                    Some(QueryResult::error(
+                    let query_result = QueryResult::error(
                         &email,
                         &service_name,
                         &service_info.url_main,
                         &url,
                         &e.to_string(),
-                    ))
+                    );
+                    Some((query_result, None))
                 }
             }
         });
@@ -160,18 +458,34 @@ async fn run_email_search(
     }
 
     let mut results = Vec::new();
+    let mut breach_counts = BTreeMap::new();
     for handle in handles {
-        if let Ok(Some(result)) = handle.await {
+        if let Ok(Some((result, breaches))) = handle.await {
+            if let Some(count) = breaches {
+                breach_counts.insert(result.site_name.clone(), count);
+            }
             results.push(result);
         }
     }
 
-    Ok(results)
+    Ok((results, breach_counts))
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+    // A URL list on stdout is meant for piping, so keep progress lines out of it
+    let urls_to_stdout = cli.format == OutputFormat::Urls && cli.output.is_none() && cli.output_dir.is_none();
+    // Monitoring output is only the new claims, for cron mail and alerting
+    QUIET.store(cli.quiet || urls_to_stdout || cli.baseline.is_some(), Ordering::Relaxed);
+    COMPACT_JSON.store(cli.compact_json, Ordering::Relaxed);
+    COMPRESS_ZSTD.store(cli.compress == Some(cli::Compression::Zstd), Ordering::Relaxed);
+    let _ = OUTPUT_ENCODING.set(cli.output_encoding);
+    colored::control::set_override(color_enabled(
+        cli.color,
+        std::io::stdout().is_terminal(),
+        std::env::var_os("NO_COLOR").is_some(),
+    ));
 
     if cli.timeout < 1 || cli.timeout > 300 {
         eprintln!("Error: Timeout must be between 1 and 300 seconds.");
@@ -183,14 +497,89 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         return Ok(());
     }
 
+    if cli.scrape_concurrent < 1 || cli.scrape_concurrent > 100 {
+        eprintln!("Error: Scrape concurrent must be between 1 and 100.");
+        return Ok(());
+    }
+
+    if cli.quick && cli.quick_hits == 0 {
+        eprintln!("Error: --quick-hits must be at least 1.");
+        return Ok(());
+    }
+
+    if cli.max_errors == Some(0) {
+        eprintln!("Error: --max-errors must be at least 1.");
+        return Ok(());
+    }
+
     if cli.tor && cli.proxy.is_some() {
         eprintln!("Error: Cannot use both --tor and --proxy at the same time.");
         return Ok(());
     }
 
+    if let Some(ref scrape_proxy) = cli.scrape_proxy {
+        if let Err(e) = http::validate_proxy(scrape_proxy) {
+            eprintln!("Error: Invalid --scrape-proxy: {}", e);
+            return Ok(());
+        }
+    }
+
+    // Subcommands that map onto the legacy flags just fill them in; `search -u NAME`
+    // already has the flag set
+    match cli.command {
+        Some(cli::Command::Search { username: Some(ref username) }) => cli.username = Some(username.clone()),
+        Some(cli::Command::Email { address: Some(ref address) }) => cli.email = Some(address.clone()),
+        Some(cli::Command::ListSites) => cli.list_sites = true,
+        Some(cli::Command::Completions { shell }) => {
+            clap_complete::generate(shell, &mut Cli::command(), "watson", &mut std::io::stdout());
+            return Ok(());
+        }
+        Some(cli::Command::Diff { ref old, ref new }) => {
+            let old = load_search_report(old)?;
+            let new = load_search_report(new)?;
+            let diff = ReportDiff::new(&old, &new);
+            print!("{}", if cli.format == OutputFormat::Html { diff.to_html() } else { diff.to_text() });
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    if let Some(cli::Command::Load { ref file }) = cli.command {
+        let bytes = read_report_file(file)?;
+        let format = if cli.format == OutputFormat::Msgpack { OutputFormat::Text } else { cli.format };
+        // Archives may hold either report type
+        match parse_search_report(&bytes) {
+            Ok(report) => handle_output(&report, &format, cli.output.as_deref(), cli.include_available_in_html)?,
+            #[cfg(feature = "email")]
+            Err(_) => {
+                let report = EmailReport::from_msgpack(&bytes)?;
+                handle_output(&report, &format, cli.output.as_deref(), cli.include_available_in_html)?;
+            }
+            #[cfg(not(feature = "email"))]
+            Err(e) => return Err(e),
+        }
+        return Ok(());
+    }
+
+    // `search`/`email` positionals fill these in after parsing, so clap can't reject
+    // the combination
+    if cli.retry_errors.is_some() && (cli.username.is_some() || cli.email.is_some()) {
+        eprintln!("Error: --retry-errors takes the username from the saved report; don't combine it with a username or email.");
+        return Ok(());
+    }
+
+    if cli.list_presets {
+        let presets = presets::load_presets(cli.presets_file.as_deref())?;
+        status!("\n=== Available Presets ({} total) ===\n", presets.len());
+        for (name, sites) in &presets {
+            println!("{} ({} sites): {}", name, sites.len(), sites.join(", "));
+        }
+        return Ok(());
+    }
+
     if cli.list_sites {
-        let sites = load_sites_data(cli.local)?;
-        println!("\n=== Supported Sites ({} total) ===\n", sites.len());
+        let sites = load_sites_data(cli.local, cli.mock, !cli.no_dedupe, cli.strict_data)?.sites;
+        status!("\n=== Supported Sites ({} total) ===\n", sites.len());
         for (name, info) in &sites {
             if !cli.nsfw && info.is_nsfw.unwrap_or(false) {
                 continue;
@@ -200,41 +589,163 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         return Ok(());
     }
 
-    if cli.verbose {
-        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"))
-            .format(|buf, record| {
-                writeln!(
-                    buf,
-                    "[{} {}] {}",
-                    chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-                    record.level(),
-                    record.args()
-                )
-            })
+    // --explain wants the per-request debug lines alongside its summary
+    let verbosity = if cli.explain.is_some() { cli.verbose.max(2) } else { cli.verbose };
+    if verbosity > 0 {
+        let level = match verbosity {
+            1 => "info",
+            2 => "debug",
+            _ => "trace",
+        };
+        // RUST_LOG, when set, overrides the level picked by -v
+        let filter = tracing_subscriber::EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(format!("warn,watson={}", level)));
+        tracing_subscriber::fmt()
+            .with_env_filter(filter)
+            .with_writer(std::io::stderr)
             .init();
     }
 
+    if let Some(cli::Command::Replay { ref file, ref username }) = cli.command {
+        let har = match replay::Har::load(file) {
+            Ok(har) => har,
+            Err(e) => {
+                eprintln!("Error: Could not read HAR file {}: {}", file, e);
+                return Ok(());
+            }
+        };
+        let sites = load_sites_data(cli.local, cli.mock, !cli.no_dedupe, cli.strict_data)?.sites;
+        let config = engine::CheckConfig {
+            forbidden_is_claimed: cli.forbidden_is_claimed,
+            ..Default::default()
+        };
+        let replayed = replay::replay(&har, &sites, &config, username.as_deref());
+        if replayed.is_empty() {
+            eprintln!("Error: No request in {} matches a known site's profile URL.", file);
+            return Ok(());
+        }
+        for (username, results) in replayed {
+            let report = SearchReport::new(username, results, false);
+            handle_output(&report, &cli.format, cli.output.as_deref(), cli.include_available_in_html)?;
+        }
+        return Ok(());
+    }
+
+    let secrets = match secrets::Secrets::resolve(
+        cli.hibp_api_key.as_deref(),
+        cli.proxy_pass.as_deref(),
+        cli.secrets_file.as_deref(),
+    ) {
+        Ok(secrets) => secrets,
+        Err(e) => {
+            eprintln!("Error: Could not read secrets file: {}", e);
+            return Ok(());
+        }
+    };
+    let proxy_auth = match (&cli.proxy_user, &secrets.proxy_pass) {
+        (Some(user), Some(pass)) => Some((user.clone(), pass.clone())),
+        (Some(_), None) => {
+            eprintln!("Error: --proxy-user needs a password via --proxy-pass, WATSON_PROXY_PASS or --secrets-file.");
+            return Ok(());
+        }
+        _ => None,
+    };
+    let ca_certs = match http::load_pem_certificates(&cli.ca_cert) {
+        Ok(certs) => certs,
+        Err(e) => {
+            eprintln!("Error: Could not load CA certificate {}.", e);
+            return Ok(());
+        }
+    };
+
+    #[cfg(feature = "server")]
+    if let Some(cli::Command::Serve { port, max_scans }) = cli.command {
+        if max_scans < 1 {
+            eprintln!("Error: --max-scans must be at least 1.");
+            return Ok(());
+        }
+        let site_data = load_sites_data(cli.local, cli.mock, !cli.no_dedupe, cli.strict_data)?;
+        let sites = site_data.sites;
+        let metadata = ScanMetadata::new(&site_data.source, &site_data.hash, cli.max_concurrent)
+            .with_tor(cli.tor)
+            .with_proxies(if cli.tor { vec![] } else { cli.proxy.iter().map(|p| http::split_proxy_credentials(p).0).collect() })
+            .with_sites_considered(sites.len());
+        // A long-lived server mustn't keep skipping a site after one failure
+        let mut engine = SearchEngine::new(cli.timeout, cli.max_concurrent, cli.nsfw, cli.rotate_ua)?
+            .with_negative_cache(false)
+            .with_forbidden_is_claimed(cli.forbidden_is_claimed)
+            .with_timestamps(cli.timestamps);
+        if cli.tor {
+            engine = engine.with_tor();
+        } else if let Some(ref proxy) = cli.proxy {
+            engine = engine.with_proxy(proxy.clone())?;
+            if let Some((user, pass)) = proxy_auth {
+                engine = engine.with_proxy_auth(user, pass);
+            }
+        }
+        if cli.mock {
+            engine = engine.with_mock();
+        }
+        if !ca_certs.is_empty() {
+            engine = engine.with_root_certificates(ca_certs)?;
+        }
+        return server::serve(port, max_scans, engine, sites, metadata, cli.max_username_len).await;
+    }
+
+    #[cfg(not(feature = "email"))]
+    if cli.email.is_some() {
+        eprintln!("Error: This build has no email lookup; rebuild with the \"email\" feature.");
+        return Ok(());
+    }
+
+    #[cfg(not(feature = "scrape"))]
+    if cli.scrape_emails {
+        eprintln!("Error: This build has no profile scraping; rebuild with the \"scrape\" feature.");
+        return Ok(());
+    }
+
+    #[cfg(not(feature = "syslog"))]
+    if cli.syslog {
+        eprintln!("Error: This build has no syslog output; rebuild with the \"syslog\" feature.");
+        return Ok(());
+    }
+
+    #[cfg(feature = "email")]
     if let Some(email) = cli.email {
-        println!("\nSearching for email: {}", email);
-        
-        let results = run_email_search(
+        status!("\nSearching for email: {}", email);
+
+        let mut http_client = http::HttpClient::new(cli.timeout, cli.rotate_ua)?;
+        if cli.tor {
+            http_client = http_client.with_tor();
+        } else {
+            if let Some(ref proxy) = cli.proxy {
+                http_client = http_client.with_proxy(proxy.clone())?;
+            }
+            if let Some(ref proxy) = cli.http_proxy {
+                http_client = http_client.with_http_proxy(proxy.clone())?;
+            }
+            if let Some(ref proxy) = cli.https_proxy {
+                http_client = http_client.with_https_proxy(proxy.clone())?;
+            }
+            if let Some(ref hosts) = cli.no_proxy {
+                http_client = http_client.with_no_proxy(hosts.clone());
+            }
+            if let Some((user, pass)) = proxy_auth {
+                http_client = http_client.with_proxy_auth(user, pass);
+            }
+        }
+        if !ca_certs.is_empty() {
+            http_client = http_client.with_root_certificates(ca_certs)?;
+        }
+
+        let (results, breach_counts) = run_email_search(
             &email,
-            cli.timeout,
+            http_client,
             cli.max_concurrent,
-            cli.proxy.as_deref(),
-            cli.tor,
-            cli.rotate_ua,
+            secrets.hibp_api_key.as_deref(),
         ).await?;
 
-        let claimed_count = results.iter().filter(|r| r.is_claimed()).count();
-        
-        if cli.print_found {
-            for result in &results {
-                if result.is_claimed() {
-                    println!("[+] {}: {}", result.site_name, result.profile_url);
-                }
-            }
-        } else if cli.print_all {
+        if cli.print_all && !cli.print_found {
             for result in &results {
                 let status = if result.is_claimed() { "[+]" } else { "[-]" };
                 println!("{} {}: {}", status, result.site_name, result.profile_url);
@@ -247,48 +758,51 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
 
-        println!("\nFound {} results for {}", claimed_count, email);
+        let report = EmailReport::new(email.clone(), results, breach_counts, cli.tor);
 
-        let report = SearchReport::new(email.clone(), results, cli.tor);
-        
-        if let Some(ref output) = cli.output {
-            handle_output(&report, &cli.format, Some(output))?;
+        if cli.output.is_some() || cli.format != cli::OutputFormat::Text {
+            handle_output(&report, &cli.format, cli.output.as_deref(), cli.include_available_in_html)?;
         }
 
-        return Ok(());
+        status!("\nFound {} results for {}", report.found_count, email);
+        status!("Summary: {} (risk: {:?})", report.summary, report.risk);
+
+        return exit_status();
     }
 
-    let username = match &cli.username {
-        Some(u) => u.clone(),
-        None => {
-            println!("Watson - OSINT Username & Email Lookup Tool");
-            println!();
-            println!("Usage:");
-            println!("  watson -u <username>        Search for username");
-            println!("  watson -m <email>          Search for email");
-            println!();
-            println!("Options:");
-            println!("  -u, --username USERNAME    Username to search for");
-            println!("  -m, --email EMAIL          Email to search for");
-            println!("  -o, --output FILE          Output file path");
-            println!("  -f, --format FORMAT        Output format (text, json, csv, html)");
-            println!("  -p, --proxy URL            Proxy URL");
-            println!("  -t, --tor                  Use Tor for requests");
-            println!("  --timeout SECONDS          Request timeout (default: 60)");
-            println!("  --max-concurrent N         Max concurrent requests (default: 20)");
-            println!("  --nsfw                     Include NSFW sites");
-            println!("  -a, --print-all            Print all results");
-            println!("  -s, --print-found          Print only found results");
-            println!("  -l, --local                Use local data file");
-            println!("  -e, --site NAME            Search specific site");
-            println!("  -v, --verbose              Verbose output");
-            println!("  --list-sites               List supported sites");
-            return Ok(());
-        }
-    };
+    if cli.username.is_none() && cli.file.is_none() && cli.retry_errors.is_none() && !cli.self_test {
+        println!("Watson - OSINT Username & Email Lookup Tool");
+        println!();
+        println!("Usage:");
+        println!("  watson search <username>   Search for username");
+        println!("  watson email <email>       Search for email");
+        println!("  watson list-sites          List supported sites");
+        println!("  watson diff <old> <new>    Compare two saved reports");
+        println!("  watson completions <shell> Print shell completions");
+        println!();
+        println!("Options:");
+        println!("  -F, --file FILE            Search every username in FILE (one per line)");
+        println!("  -o, --output FILE          Output file path");
+        println!("  -f, --format FORMAT        Output format (text, json, csv, html, bookmarks, urls)");
+        println!("  -p, --proxy URL            Proxy URL");
+        println!("  --paste                    Upload the JSON report and print its URL");
+        println!("  --retry-errors FILE        Re-check only the errored sites of a saved report");
+        println!("  --scrape-proxy URL         Proxy for --emails scraping only");
+        println!("  -t, --tor                  Use Tor for requests");
+        println!("  --timeout SECONDS          Request timeout (default: 60)");
+        println!("  --max-concurrent N         Max concurrent requests (default: 20)");
+        println!("  --nsfw                     Include NSFW sites");
+        println!("  -a, --print-all            Print all results");
+        println!("  -s, --print-found          Print only found results");
+        println!("  -l, --local                Use local data file");
+        println!("  -e, --site NAME            Search specific site");
+        println!("  -v, --verbose              Verbose output");
+        println!("  --self-test                Check every site's detection with its known usernames");
+        return Ok(());
+    }
 
-    if cli.username.is_none() && cli.email.is_none() && cli.file.is_none() {
-        eprintln!("Error: Please specify either --username, --file, or --email");
+    if cli.username.is_none() && cli.email.is_none() && cli.file.is_none() && cli.retry_errors.is_none() && !cli.self_test {
+        eprintln!("Error: Please specify a username (watson search <username>), --file, or an email (watson email <email>)");
         eprintln!("Use watson --help for usage information");
         return Ok(());
     }
@@ -300,20 +814,41 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Determine usernames to search
     let mut usernames_to_search: Vec<String> = vec![];
+    // Where each username came from, for --include-source-column
+    let mut username_sources: HashMap<String, String> = HashMap::new();
 
     if let Some(ref file_path) = cli.file {
         // Load usernames from file
         match fs::read_to_string(file_path) {
             Ok(content) => {
-                let users: Vec<String> = content
-                    .lines()
-                    .map(|s| s.trim().to_string())
-                    .filter(|s| !s.is_empty())
+                let listed = listed_usernames(&content);
+                for (line, user) in &listed {
+                    username_sources.entry(user.clone()).or_insert_with(|| format!("{}:{}", file_path, line));
+                }
+                let mut users: Vec<String> = listed
+                    .into_iter()
+                    .filter(|(line, user)| {
+                        let fits = username_len_ok(user, cli.max_username_len);
+                        if !fits {
+                            eprintln!(
+                                "Error: Username '{}' on line {} must be between 1 and {} characters; skipping it.",
+                                user, line, cli.max_username_len
+                            );
+                        }
+                        fits
+                    })
+                    .map(|(_, user)| user)
                     .collect();
                 if users.is_empty() {
                     eprintln!("Error: No usernames found in file");
                     return Ok(());
                 }
+                if !cli.allow_duplicates {
+                    let removed = dedupe_usernames(&mut users);
+                    if removed > 0 {
+                        status!("Skipping {} duplicate usernames in {}", removed, file_path);
+                    }
+                }
                 usernames_to_search = users;
             }
             Err(e) => {
@@ -322,19 +857,78 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
     } else if let Some(ref username) = cli.username {
+        let expanded = match variations::expand(username, variations::MAX_EXPANSION) {
+            Ok(names) => names,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return Ok(());
+            }
+        };
+        if expanded.len() > 1 {
+            status!("Expanded '{}' to {} usernames", username, expanded.len());
+        }
+        if let Some(name) = expanded.iter().find(|n| !username_len_ok(n, cli.max_username_len)) {
+            eprintln!("Error: Username '{}' must be between 1 and {} characters", name, cli.max_username_len);
+            return Ok(());
+        }
+        let argument = if expanded.len() > 1 { format!("-u {}", username) } else { "-u".to_string() };
         if cli.variations {
-            println!("Generating username variations...");
-            usernames_to_search = generate_variations(username);
+            status!("Generating username variations...");
+            usernames_to_search = vec![];
+            for (variation, source) in variation_usernames(&expanded, &argument) {
+                username_sources.insert(variation.clone(), source);
+                usernames_to_search.push(variation);
+            }
         } else {
-            usernames_to_search = vec![username.clone()];
+            for name in &expanded {
+                username_sources.insert(name.clone(), argument.clone());
+            }
+            usernames_to_search = expanded;
         }
     }
 
-    if let Some(ref username) = cli.username {
-        if username.is_empty() || username.len() > 50 {
-            eprintln!("Error: Username must be between 1 and 50 characters");
-            return Ok(());
+    let retry_prior = match cli.retry_errors {
+        Some(ref path) => {
+            let prior = load_search_report(path)?;
+            username_sources.insert(prior.username.clone(), format!("retry of {}", path));
+            usernames_to_search = vec![prior.username.clone()];
+            Some(prior)
         }
+        None => None,
+    };
+
+
+    let charset = match cli.username_charset {
+        Some(ref class) => match regex::Regex::new(&format!("^(?:{})$", class)) {
+            Ok(re) => Some(re),
+            Err(e) => {
+                eprintln!("Error: Invalid --username-charset: {}", e);
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+    usernames_to_search.retain(|username| match disallowed_char(username, charset.as_ref()) {
+        Some(c) if cli.allow_invalid_chars => {
+            eprintln!("WARNING: Username {:?} contains {:?}, which most sites reject; searching anyway.", username, c);
+            true
+        }
+        Some(c) => {
+            eprintln!("Error: Username {:?} contains {:?}, which no site accepts; skipping it.", username, c);
+            false
+        }
+        None => true,
+    });
+    if usernames_to_search.is_empty() && cli.email.is_none() && !cli.self_test {
+        return Ok(());
+    }
+
+    if cli.preview {
+        for variation in &usernames_to_search {
+            println!("{}", variation);
+        }
+        status!("\n{} variations", usernames_to_search.len());
+        return Ok(());
     }
 
     if let Some(ref email) = cli.email {
@@ -345,21 +939,123 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 
     info!("Loading sites data...");
-    let sites = load_sites_data(cli.local)?;
+    // Deduped once --site/--preset have picked the sites
+    let site_data = load_sites_data(cli.local, cli.mock, false, cli.strict_data)?;
+    let mut sites = site_data.sites;
+    let presets = presets::load_presets(cli.presets_file.as_deref())?;
+
+    // Sites without tags take their category from the preset that lists them
+    for (name, info) in sites.iter_mut() {
+        if info.tags.is_none() {
+            info.tags = presets::preset_for_site(&presets, name).map(|p| vec![p.to_string()]);
+        }
+    }
     
-    let site_filter = cli.site.clone();
-    let filtered_sites: HashMap<String, data::SiteInfo> = if let Some(ref filter) = site_filter {
+    if let Some(ref wanted) = cli.explain {
+        let Some(name) = sites.keys().find(|n| n.eq_ignore_ascii_case(wanted)).cloned() else {
+            eprintln!("Error: Unknown site '{}'. Use --list-sites to see supported sites.", wanted);
+            return Ok(());
+        };
+        sites.retain(|n, _| *n == name);
+    }
+
+    let mut pre_skipped: Vec<SkippedSite> = vec![];
+
+    let preset_names = if cli.quick { Some(vec!["quick".to_string()]) } else { cli.preset.clone() };
+    let sites: HashMap<String, data::SiteInfo> = if let Some(ref preset_names) = preset_names {
+        match select_preset_sites(sites, &presets, preset_names) {
+            Ok((selected, excluded)) => {
+                pre_skipped.extend(excluded);
+                selected
+            }
+            Err(unknown) => {
+                eprintln!("Error: Unknown preset '{}'. Use --list-presets to see available presets.", unknown);
+                return Ok(());
+            }
+        }
+    } else {
         sites
+    };
+
+    let site_filter = cli.site.clone();
+    let mut filtered_sites: HashMap<String, data::SiteInfo> = if let Some(ref filter) = site_filter {
+        let (selected, excluded): (HashMap<_, _>, HashMap<_, _>) = sites
             .into_iter()
-            .filter(|(name, _)| filter.iter().any(|f| name.to_lowercase().contains(&f.to_lowercase())))
-            .collect()
+            .partition(|(name, _)| filter.iter().any(|f| name.to_lowercase().contains(&f.to_lowercase())));
+        pre_skipped.extend(excluded.keys().map(|name| SkippedSite::new(name, SkipReason::Excluded)));
+        selected
     } else {
         sites
     };
+    if !cli.no_dedupe {
+        data::dedupe_sites(&mut filtered_sites);
+    }
 
     info!("Found {} sites to check", filtered_sites.len());
 
-    let mut engine = SearchEngine::new(cli.timeout, cli.max_concurrent, cli.nsfw, cli.rotate_ua)?;
+    let total_checks = usernames_to_search.len() * filtered_sites.len();
+    if cli.variations && total_checks > variations::REQUEST_WARN_THRESHOLD {
+        eprintln!(
+            "WARNING: {} variations x {} sites = {} checks; narrow with --site/--preset or check the list with --preview.",
+            usernames_to_search.len(),
+            filtered_sites.len(),
+            total_checks
+        );
+    }
+
+    let insecure_sites: Vec<&str> = filtered_sites
+        .iter()
+        .filter(|(_, info)| info.insecure.unwrap_or(false))
+        .map(|(name, _)| name.as_str())
+        .collect();
+    if !insecure_sites.is_empty() && !cli.insecure {
+        eprintln!(
+            "WARNING: TLS certificates will NOT be verified for: {}",
+            insecure_sites.join(", ")
+        );
+    }
+
+    // A site asked for by name is explained even if it's NSFW
+    let include_nsfw = cli.nsfw || cli.explain.is_some();
+    let mut engine = SearchEngine::new(cli.timeout, cli.max_concurrent, include_nsfw, cli.rotate_ua)?
+        .with_forbidden_is_claimed(cli.forbidden_is_claimed)
+        .with_auto_concurrency(cli.auto_concurrency)
+        .with_negative_cache(!cli.no_negative_cache)
+        .with_timestamps(cli.timestamps)
+        .with_first_match(cli.first_match);
+
+    if let Some(per_host) = cli.max_per_host {
+        info!("Limiting to {} concurrent requests per host", per_host);
+        engine = engine.with_max_per_host(per_host);
+    }
+
+    if cli.adaptive_timeout {
+        info!("Adapting request timeouts to per-host latency (max {}s)", cli.timeout);
+        engine = engine.with_adaptive_timeout(cli.timeout);
+    }
+
+    if cli.quick && !cli.first_match {
+        engine = engine.with_stop_after_claims(cli.quick_hits);
+    }
+
+    if let Some(max_errors) = cli.max_errors {
+        engine = engine.with_max_errors(max_errors);
+    }
+
+    if let Some(budget_ms) = cli.time_budget_per_site {
+        info!("Per-site time budget: {}ms", budget_ms);
+        engine = engine.with_site_budget(budget_ms);
+    }
+
+    if let Some(body_ms) = cli.body_timeout {
+        info!("Body read timeout: {}ms", body_ms);
+        engine = engine.with_body_timeout(body_ms);
+    }
+
+    if let Some(range_bytes) = cli.range_probe {
+        info!("Using Range requests: first {} bytes", range_bytes);
+        engine = engine.with_range_probe(range_bytes);
+    }
 
     if let Some(rate_limit) = cli.rate_limit {
         if rate_limit > 0 {
@@ -371,17 +1067,153 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     if cli.tor {
         info!("Using Tor for requests");
         engine = engine.with_tor();
-    } else if let Some(ref proxy) = cli.proxy {
-        info!("Using proxy: {}", proxy);
-        engine = engine.with_proxy(proxy.clone());
+    } else {
+        if let Some(ref proxy) = cli.proxy {
+            info!("Using proxy: {}", http::split_proxy_credentials(proxy).0);
+            engine = engine.with_proxy(proxy.clone())?;
+        }
+        if let Some(ref proxy) = cli.http_proxy {
+            info!("Using HTTP proxy: {}", http::split_proxy_credentials(proxy).0);
+            engine = engine.with_http_proxy(proxy.clone())?;
+        }
+        if let Some(ref proxy) = cli.https_proxy {
+            info!("Using HTTPS proxy: {}", http::split_proxy_credentials(proxy).0);
+            engine = engine.with_https_proxy(proxy.clone())?;
+        }
+        if let Some(ref hosts) = cli.no_proxy {
+            info!("Bypassing the proxy for: {}", hosts);
+            engine = engine.with_no_proxy(hosts.clone());
+        }
+        if let Some((user, pass)) = proxy_auth {
+            engine = engine.with_proxy_auth(user, pass);
+        }
+    }
+
+    if cli.mock {
+        info!("Mock mode: no network requests will be made");
+        engine = engine.with_mock();
+    }
+
+    if cli.insecure {
+        eprintln!("WARNING: --insecure is set; TLS certificates will NOT be verified for any site.");
+        engine = engine.with_insecure();
+    }
+
+    if !ca_certs.is_empty() {
+        info!("Trusting {} extra CA certificate(s)", ca_certs.len());
+        engine = engine.with_root_certificates(ca_certs)?;
+    }
+
+    if let Some(ref referer) = cli.referer {
+        info!("Using default referer: {}", referer);
+        engine = engine.with_referer(referer.clone());
     }
 
     let tor_used = engine.is_using_tor();
+    let proxies: Vec<String> = if tor_used {
+        vec![]
+    } else {
+        [&cli.proxy, &cli.http_proxy, &cli.https_proxy]
+            .into_iter()
+            .flatten()
+            .map(|p| http::split_proxy_credentials(p).0)
+            .collect()
+    };
+    let metadata = ScanMetadata::new(&site_data.source, &site_data.hash, cli.max_concurrent)
+        .with_tor(tor_used)
+        .with_proxies(proxies)
+        .with_sites_considered(filtered_sites.len());
+    pre_skipped.extend(engine.nsfw_skipped(&filtered_sites));
+    let token_skipped = engine.token_skipped(&filtered_sites);
+    for skipped in &token_skipped {
+        let var = filtered_sites[&skipped.site_name].auth_token_env.as_deref().unwrap_or_default();
+        eprintln!("Note: Skipping {}: set {} to an API token to check it.", skipped.site_name, var);
+    }
+    pre_skipped.extend(token_skipped);
+
+    if cli.self_test {
+        let testable: HashMap<String, data::SiteInfo> = filtered_sites
+            .iter()
+            .filter(|(name, _)| !pre_skipped.iter().any(|s| s.site_name == **name))
+            .map(|(name, info)| (name.clone(), info.clone()))
+            .collect();
+        status!("\nSelf-testing {} sites...", testable.len());
+        let tests = selftest::run(&engine, &testable, cli.max_concurrent).await;
+        let untested = testable.len() - tests.len();
+        for test in tests.iter().filter(|t| t.is_broken()) {
+            println!("[BROKEN] {}: {}", test.site_name, test.failures.join("; "));
+        }
+        for test in tests.iter().filter(|t| !t.is_broken() && !t.passed()) {
+            println!("[?] {}: {}", test.site_name, test.inconclusive.join("; "));
+        }
+        let broken = tests.iter().filter(|t| t.is_broken()).count();
+        let passed = tests.iter().filter(|t| t.passed()).count();
+        status!(
+            "\nSelf-test: {} passed, {} broken, {} inconclusive, {} without known usernames",
+            passed,
+            broken,
+            tests.len() - passed - broken,
+            untested
+        );
+        if broken > 0 {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    if cli.explain.is_some() {
+        for (name, info) in &filtered_sites {
+            let headers = engine.http_client().describe_headers(info.referer.as_deref());
+            for username in &usernames_to_search {
+                let results = engine.search_username(username, &filtered_sites).await;
+                print!("{}", explain::explain(username, name, info, headers.clone(), cli.range_probe, results.first()));
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(prior) = retry_prior {
+        let retry_sites = retry_sites(&prior, filtered_sites);
+        status!("Re-checking {} errored sites for {}", retry_sites.len(), prior.username);
+        let traffic_before = engine.traffic();
+        let results = engine.search_username(&prior.username, &retry_sites).await;
+        let report = prior
+            .with_retried(results, engine.traffic().since(traffic_before))
+            .with_metadata(metadata.restarted())
+            .with_group_by_category(cli.group_by_category);
+
+        print!("{}", report.found_accounts());
+        status!("\n{} sites still erroring", report.error_count);
+        if cli.in_place {
+            // Write back in the prior report's own format
+            let path = cli.retry_errors.as_deref().unwrap_or_default();
+            let format = if path.trim_end_matches(".zst").ends_with(".msgpack") {
+                OutputFormat::Msgpack
+            } else {
+                OutputFormat::Json
+            };
+            handle_output(&report, &format, Some(path), cli.include_available_in_html)?;
+        } else if cli.output.is_some() || cli.format != OutputFormat::Text {
+            handle_output(&report, &cli.format, cli.output.as_deref(), cli.include_available_in_html)?;
+        }
+        return exit_status();
+    }
+
+    #[cfg(feature = "tui")]
+    if cli.tui {
+        if let Some(reason) = tui::unsupported_reason() {
+            eprintln!("Note: --tui needs an interactive terminal ({}); using plain output instead.", reason);
+            cli.tui = false;
+        }
+    }
+    #[cfg(not(feature = "tui"))]
+    if cli.tui {
+        eprintln!("Note: This build has no TUI (rebuild with the \"tui\" feature); using plain output instead.");
+    }
 
     // TUI mode
+    #[cfg(feature = "tui")]
     if cli.tui {
-        use engine::ProgressCallback;
-        
         let total_sites = filtered_sites.len();
         let tui_state = tui::TUIState::new(total_sites);
         
@@ -400,10 +1232,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         
         // Search for usernames
         for username in usernames_to_search {
-            println!("\nSearching for username: {}", username);
+            status!("\nSearching for username: {}", username);
+            let prior_errors = engine.negative_skipped(&filtered_sites);
+            let cooling_down = engine.cooldown_skipped(&filtered_sites);
+            let traffic_before = engine.traffic();
             let results = engine.search_username(&username, &filtered_sites).await;
-            let report = SearchReport::new(username.clone(), results, tor_used);
-            println!("Found {} results for {}", report.claimed_count, username);
+            let report = SearchReport::new(username.clone(), results, tor_used)
+                .with_skipped(pre_skipped.clone())
+                .with_skipped(prior_errors)
+                .with_skipped(cooling_down)
+                .with_traffic(engine.traffic().since(traffic_before));
+            status!("Found {} results for {}", report.claimed_count, username);
         }
         
         // Wait for TUI to finish
@@ -412,68 +1251,679 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         return Ok(());
     }
 
+    if let Some(ref dir) = cli.output_dir {
+        std::fs::create_dir_all(dir)?;
+    }
+
+    let old_report = match cli.diff_against {
+        Some(ref path) => Some(load_search_report(path)?),
+        None => None,
+    };
+
+    let mut baseline = match cli.baseline {
+        Some(ref path) => match Baseline::load(path) {
+            Ok(baseline) => Some(baseline.unwrap_or_default()),
+            Err(e) => {
+                eprintln!("Error: Could not read baseline {}: {}", path, e);
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+    let mut new_claims = 0;
+
+    #[cfg(feature = "syslog")]
+    let mut syslog_sink = if cli.syslog {
+        match syslogsink::SyslogSink::connect() {
+            Ok(sink) => Some(sink),
+            Err(e) => {
+                eprintln!("WARNING: Could not connect to syslog ({}); results will not be sent there.", e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let mut heatmap = cli.heatmap.as_ref().map(|_| VariationHeatmap::default());
+
+    let mut result_cache = match cli.cache_results {
+        Some(ref path) => match ResultCache::load(path) {
+            Ok(cache) => Some(cache),
+            Err(e) => {
+                eprintln!("Error: Could not read result cache {}: {}", path, e);
+                return Ok(());
+            }
+        },
+        None => None,
+    };
+
     // CLI mode (non-TUI)
     // Search for all usernames
     for username in usernames_to_search {
-        println!("\nSearching for username: {}", username);
+        status!("\nSearching for username: {}", username);
         
-        let results = engine.search_username(&username, &filtered_sites).await;
+        let prior_errors = engine.negative_skipped(&filtered_sites);
+        let cooling_down = engine.cooldown_skipped(&filtered_sites);
+        let traffic_before = engine.traffic();
+        let scan_metadata = metadata.restarted();
+        let spinner = if cli.spinner { spinner::Spinner::start(engine.progress_handle()) } else { None };
+        let results = match result_cache {
+            Some(ref mut cache) => {
+                let mut cached = vec![];
+                let mut to_check = HashMap::new();
+                for (name, info) in &filtered_sites {
+                    match cache.fresh(&username, name, cli.result_ttl).filter(|_| !cli.force) {
+                        Some(result) => cached.push(result),
+                        None => {
+                            to_check.insert(name.clone(), info.clone());
+                        }
+                    }
+                }
+                if !cached.is_empty() {
+                    status!("{} sites served from the result cache", cached.len());
+                }
+                let mut results = engine.search_username(&username, &to_check).await;
+                cache.store(&results);
+                results.extend(cached);
+                results
+            }
+            None => engine.search_username(&username, &filtered_sites).await,
+        };
+        if let Some(spinner) = spinner {
+            spinner.finish().await;
+        }
+        let results = match username_sources.get(&username).filter(|_| cli.include_source_column) {
+            Some(source) => results
+                .into_iter()
+                .map(|r| QueryResult { source: Some(source.clone()), ..r })
+                .collect(),
+            None => results,
+        };
+
+        let output = match cli.output_dir {
+            Some(ref dir) => Some(
+                std::path::Path::new(dir)
+                    .join(format!(
+                        "{}.{}{}",
+                        sanitize_filename(&username),
+                        cli.format.extension(),
+                        if cli.compress.is_some() { ".zst" } else { "" }
+                    ))
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+            None => cli.output.clone(),
+        };
         
-        let report = SearchReport::new(username.clone(), results, tor_used);
+        let report = SearchReport::new(username.clone(), results, tor_used)
+            .with_skipped(pre_skipped.clone())
+            .with_skipped(prior_errors)
+            .with_skipped(cooling_down)
+            .with_capped(filtered_sites.keys())
+            .with_traffic(engine.traffic().since(traffic_before))
+            .with_aborted(engine.aborted())
+            .with_metadata(scan_metadata)
+            .with_group_by_category(cli.group_by_category);
 
-    if cli.scrape_emails {
+        if let Some(ref mut heatmap) = heatmap {
+            heatmap.add(&report);
+        }
+
+    #[cfg(feature = "scrape")]
+    let report = if cli.scrape_emails {
         let claimed_results: Vec<(String, String)> = report.results
             .iter()
             .filter(|r| r.is_claimed())
             .map(|r| (r.site_name.clone(), r.profile_url.clone()))
             .collect();
 
+        let mut email_results = Vec::new();
         if !claimed_results.is_empty() {
-            println!("\nScraping profiles for emails...");
-            let email_results = scrape_emails_from_results(claimed_results, cli.timeout, cli.rotate_ua).await;
-
-            let mut emails_found = false;
-            for (site_name, profile_url, emails) in email_results {
-                if let Some(email_list) = emails {
-                    if !email_list.is_empty() {
-                        emails_found = true;
-                        for email in email_list {
-                            println!("[+] {}: {} -> Email: {}", site_name, profile_url, email);
-                        }
+            status!("\nScraping profiles for emails...");
+            email_results = scrape_emails_from_results(
+                claimed_results,
+                scrape_client(&engine, cli.scrape_proxy.as_deref())?,
+                cli.scrape_concurrent,
+                cli.scrape_allow_domains.clone().unwrap_or_default(),
+            )
+            .await;
+
+            let emails_found = email_results.iter().any(|(_, _, emails)| emails.as_ref().is_some_and(|e| !e.is_empty()));
+            if !emails_found {
+                status!("No emails found on profiles.");
+            } else if cli.dedupe_emails {
+                for scraped in output::dedupe_emails(&email_results) {
+                    println!("[+] Email: {} ({})", scraped.email, scraped.sites.join(", "));
+                }
+            } else {
+                for (site_name, profile_url, emails) in &email_results {
+                    for email in emails.iter().flatten() {
+                        println!("[+] {}: {} -> Email: {}", site_name, profile_url, email);
                     }
                 }
             }
+        }
+        if cli.dedupe_emails {
+            report.with_scraped_emails(output::dedupe_emails(&email_results))
+        } else {
+            report
+        }
+    } else {
+        report
+    };
+    
+    #[cfg(feature = "tls")]
+    let report = if cli.capture_tls && (cli.tor || cli.proxy.is_some() || cli.http_proxy.is_some() || cli.https_proxy.is_some()) {
+        eprintln!("Note: Skipping --capture-tls: its handshakes connect directly and would bypass --proxy/--tor.");
+        report
+    } else if cli.capture_tls {
+        let claimed_urls: Vec<String> = report.results.iter().filter(|r| r.is_claimed()).map(|r| r.profile_url.clone()).collect();
+        status!("\nCapturing TLS certificates of claimed hosts...");
+        let certificates = tls::capture_certificates(&claimed_urls, cli.timeout, cli.max_concurrent).await;
+        report.with_tls_certificates(certificates)
+    } else {
+        report
+    };
 
-            if !emails_found {
-                println!("No emails found on profiles.");
+    #[cfg(feature = "syslog")]
+    if let Some(ref mut sink) = syslog_sink {
+        sink.send_report(&report);
+    }
+
+    let report = match cli.top_fastest {
+        Some(n) => report.with_top_fastest(n),
+        None => report,
+    };
+
+    if let Some(ref old) = old_report {
+        let diff = ReportDiff::new(old, &report);
+        let content = if cli.format == OutputFormat::Html { diff.to_html() } else { diff.to_text() };
+        match output {
+            Some(ref path) => {
+                fs::write(path, encode_output(&content))?;
+                status!("Changes saved to: {}", path);
             }
+            None => print!("{}", content),
+        }
+    } else if let Some(ref mut baseline) = baseline {
+        for (site, url) in baseline.update(&username, &report.results) {
+            println!("[+] {}: {} (new for {})", site, url, username);
+            new_claims += 1;
+        }
+    } else if cli.print_found {
+        print!("{}", report.found_accounts());
+    } else if cli.print_all || output.is_some() || cli.format != cli::OutputFormat::Text {
+        report.write_to(&mut FormatSink {
+            format: &cli.format,
+            output_file: output.as_deref(),
+            html_show_all: cli.include_available_in_html,
+        })?;
+    } else {
+        print!("{}", report.found_accounts());
+    }
+
+    status!("\nFound {} results for {}", report.claimed_count, username);
+    status!("Reachable: {}", report.reachability_summary());
+    if !report.error_reasons.is_empty() {
+        status!("Error reasons: {}", report.error_reason_summary());
+    }
+    status!("Traffic: {}", report.traffic_summary());
+    if report.nothing_reachable() {
+        eprintln!("WARNING: No site responded; check your network, proxy or Tor connection before trusting these results.");
+    }
+    if cli.quick {
+        let checked = report.total_sites;
+        let verdict = if report.claimed_count >= cli.quick_hits.min(checked.max(1)) {
+            "is taken"
+        } else if report.claimed_count > 0 {
+            "is partly taken"
+        } else {
+            "looks free"
+        };
+        println!(
+            "Verdict: {} {} (claimed on {} of {} major sites checked)",
+            username, verdict, report.claimed_count, checked
+        );
+    }
+    if report.aborted {
+        eprintln!(
+            "Error: Scan for {} aborted after {} errors (--max-errors); only {} sites were checked, the rest are unknown rather than not found.",
+            username,
+            report.error_count + report.results.iter().filter(|r| r.status == engine::QueryStatus::TimedOut).count(),
+            report.total_sites
+        );
+    }
+
+    if cli.suggest {
+        let top = suggest::top_sites(&filtered_sites);
+        let baseline: Vec<_> = report.results.iter().filter(|r| top.contains_key(&r.site_name)).cloned().collect();
+        status!("\nLooking for an available variation of {} on {} top sites...", username, top.len());
+        match suggest::suggest(&engine, &username, &top, &baseline).await {
+            Some(s) => println!("Suggestion: {} (available on {} of {} top sites)", s.username, s.available, s.checked),
+            None => println!("Suggestion: no variation of {} is available on more top sites", username),
         }
     }
     
-    if cli.print_found {
-        for result in &report.results {
-            if result.is_claimed() {
-                println!("[+] {}: {}", result.site_name, result.profile_url);
+    if let (Some(ref output), None) = (&output, &old_report) {
+        report.write_to(&mut FormatSink {
+            format: &cli.format,
+            output_file: Some(output),
+            html_show_all: cli.include_available_in_html,
+        })?;
+    }
+
+    if cli.paste {
+        match report.to_json() {
+            Ok(json) => match paste::upload(engine.http_client(), &cli.paste_url, json.clone()).await {
+                Ok(url) => println!("Report pasted: {}", url),
+                Err(e) => {
+                    let path = format!("watson_{}.json", sanitize_filename(&username));
+                    eprintln!("Error: Paste upload failed ({}); saving the report locally instead", e);
+                    fs::write(&path, &json)?;
+                    status!("Results saved to: {}", path);
+                }
+            },
+            Err(e) => {
+                eprintln!("Error: Could not serialize report as JSON for --paste ({})", e);
+                OUTPUT_DEGRADED.store(true, Ordering::Relaxed);
             }
         }
-    } else if cli.print_all {
-        handle_output(&report, &cli.format, cli.output.as_deref())?;
-    } else if cli.output.is_some() || cli.format != cli::OutputFormat::Text {
-        handle_output(&report, &cli.format, cli.output.as_deref())?;
-    } else {
-        for result in &report.results {
-            if result.is_claimed() {
-                println!("[+] {}: {}", result.site_name, result.profile_url);
-            }
+    }
+    }
+
+    if let (Some(cache), Some(path)) = (result_cache, &cli.cache_results) {
+        if let Err(e) = cache.save(path) {
+            eprintln!("Error: Could not write result cache {}: {}", path, e);
         }
     }
 
-    println!("\nFound {} results for {}", report.claimed_count, username);
-    
-    if let Some(ref output) = cli.output {
-        handle_output(&report, &cli.format, Some(output))?;
+    if let (Some(heatmap), Some(path)) = (heatmap, &cli.heatmap) {
+        fs::write(path, heatmap.to_html())?;
+        status!("Heatmap saved to: {}", path);
     }
+
+    if let (Some(baseline), Some(path)) = (baseline, &cli.baseline) {
+        if let Err(e) = baseline.save(path) {
+            eprintln!("Error: Could not write baseline {}: {}", path, e);
+        }
+        if new_claims > 0 {
+            std::process::exit(1);
+        }
     }
 
+    exit_status()
+}
+
+/// The engine's client for scraping, switched to `scrape_proxy` when one is set
+/// so scraping and detection can take different routes.
+#[cfg(feature = "scrape")]
+fn scrape_client(engine: &SearchEngine, scrape_proxy: Option<&str>) -> Result<http::HttpClient, Box<dyn std::error::Error>> {
+    match scrape_proxy {
+        Some(proxy) => engine.http_client().clone().with_proxy(proxy.to_string()),
+        None => Ok(engine.http_client().clone()),
+    }
+}
+
+/// The sites `prior` recorded as errors, out of `sites`.
+fn retry_sites(prior: &SearchReport, sites: HashMap<String, data::SiteInfo>) -> HashMap<String, data::SiteInfo> {
+    let error_sites = prior.error_sites();
+    sites.into_iter().filter(|(name, _)| error_sites.contains(&name.as_str())).collect()
+}
+
+/// Whether `--color` turns color on: with `auto`, only when stdout is a terminal
+/// and `NO_COLOR` isn't set, so piped output never carries escape codes.
+fn color_enabled(choice: cli::ColorChoice, stdout_is_terminal: bool, no_color: bool) -> bool {
+    match choice {
+        cli::ColorChoice::Always => true,
+        cli::ColorChoice::Never => false,
+        cli::ColorChoice::Auto => stdout_is_terminal && !no_color,
+    }
+}
+
+/// Exits with `degraded_exit_code` if there is one, else returns `Ok`.
+fn exit_status() -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(code) = degraded_exit_code() {
+        std::process::exit(code);
+    }
     Ok(())
 }
+
+/// 1 if any report fell back to another format.
+fn degraded_exit_code() -> Option<i32> {
+    OUTPUT_DEGRADED.load(Ordering::Relaxed).then_some(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use engine::QueryResult;
+
+    fn site() -> data::SiteInfo {
+        serde_json::from_value(serde_json::json!({
+            "url": "https://example.com/{}",
+            "urlMain": "https://example.com/",
+            "errorType": "status_code",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn retry_rechecks_only_errored_sites() {
+        let prior = SearchReport::new(
+            "alice".to_string(),
+            vec![
+                QueryResult::error("alice", "Down", "", "", "connection refused"),
+                QueryResult::claimed("alice", "Up", "", ""),
+                QueryResult::available("alice", "Free", "", ""),
+                QueryResult::error("alice", "Flaky", "", "", "timed out"),
+            ],
+            false,
+        );
+        let sites = ["Down", "Up", "Free", "Flaky", "New"].map(|name| (name.to_string(), site())).into();
+        let mut names: Vec<String> = retry_sites(&prior, sites).into_keys().collect();
+        names.sort();
+        assert_eq!(names, ["Down", "Flaky"]);
+
+        let retried = prior.with_retried(vec![QueryResult::claimed("alice", "Down", "", "")], Default::default());
+        assert_eq!((retried.claimed_count, retried.error_count, retried.available_count), (2, 1, 1));
+    }
+
+    static RETRY_REQUESTS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+
+    #[tokio::test]
+    async fn retry_requests_only_errored_sites() {
+        let addr = testutil::serve(|request| {
+            RETRY_REQUESTS.lock().unwrap().push(testutil::path_of(request).to_string());
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+        })
+        .await;
+        let prior = SearchReport::new(
+            "alice".to_string(),
+            vec![
+                QueryResult::error("alice", "Down", "", "", "connection refused"),
+                QueryResult::claimed("alice", "Up", "", ""),
+                QueryResult::error("alice", "Flaky", "", "", "timed out"),
+            ],
+            false,
+        );
+        let sites = ["Down", "Up", "Flaky"]
+            .map(|name| {
+                let info = serde_json::from_value(serde_json::json!({
+                    "url": format!("http://{}/{}/{{}}", addr, name),
+                    "urlMain": format!("http://{}/", addr),
+                    "errorType": "status_code",
+                }))
+                .unwrap();
+                (name.to_string(), info)
+            })
+            .into();
+
+        let engine = engine::SearchEngine::new(5, 4, false, false).unwrap();
+        let results = engine.search_username("alice", &retry_sites(&prior, sites)).await;
+        assert_eq!(results.len(), 2);
+
+        let mut requested = RETRY_REQUESTS.lock().unwrap().clone();
+        requested.sort();
+        assert_eq!(requested, ["/Down/alice", "/Flaky/alice"]);
+    }
+
+    #[tokio::test]
+    async fn mock_scan_gives_the_fixed_report() {
+        let sites = load_sites_data(false, true, true, true).unwrap().sites;
+        let engine = engine::SearchEngine::new(5, 4, false, false).unwrap().with_mock();
+        let report = SearchReport::new("alice".to_string(), engine.search_username("alice", &sites).await, false);
+
+        let named = |status: engine::QueryStatus| -> Vec<String> {
+            report.results.iter().filter(|r| r.status == status).map(|r| r.site_name.clone()).collect()
+        };
+        assert_eq!(named(engine::QueryStatus::Claimed), ["MockClaimed", "MockJsonClaimed", "MockMessageClaimed"]);
+        assert_eq!(named(engine::QueryStatus::Available), ["MockAvailable", "MockJsonAvailable", "MockMessageAvailable"]);
+        assert_eq!(named(engine::QueryStatus::Unknown), ["MockBlocked"]);
+        // No redirects offline: each check ends on the URL it probed
+        for result in &report.results {
+            assert_eq!(result.final_url.as_deref(), Some(result.profile_url.as_str()), "{}", result.site_name);
+        }
+        assert_eq!(engine.traffic().requests, 7);
+    }
+
+    #[test]
+    fn zstd_report_files_read_back() {
+        let report = SearchReport::new(
+            "alice".to_string(),
+            vec![
+                QueryResult::claimed("alice", "Up", "https://up.example/", "https://up.example/alice"),
+                QueryResult::error("alice", "Down", "https://down.example/", "https://down.example/alice", "timed out"),
+            ],
+            false,
+        );
+        let path = std::env::temp_dir().join(format!("watson-test-{}.json.zst", std::process::id()));
+        let path = path.to_str().unwrap();
+        handle_output(&report, &OutputFormat::Json, Some(path), false).unwrap();
+        let written = fs::read(path).unwrap();
+        let read = load_search_report(path);
+        fs::remove_file(path).unwrap();
+
+        assert!(written.starts_with(&ZSTD_MAGIC));
+        assert_eq!(read.unwrap().to_json().unwrap(), report.to_json().unwrap());
+    }
+
+    /// A report whose JSON serialization always fails.
+    struct Unserializable(SearchReport);
+
+    impl Report for Unserializable {
+        fn to_text(&self) -> String {
+            self.0.to_text()
+        }
+        fn to_json(&self) -> Result<String, serde_json::Error> {
+            Err(serde::ser::Error::custom("unserializable"))
+        }
+        fn to_json_compact(&self) -> Result<String, serde_json::Error> {
+            self.to_json()
+        }
+        fn to_csv(&self) -> String {
+            self.0.to_csv()
+        }
+        fn to_html(&self, show_all: bool) -> String {
+            self.0.to_html(show_all)
+        }
+        fn to_term(&self, width: usize) -> String {
+            self.0.to_term(width)
+        }
+        fn to_msgpack(&self) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+            self.0.to_msgpack()
+        }
+        fn to_maltego(&self) -> Result<String, csv::Error> {
+            self.0.to_maltego()
+        }
+        fn to_bookmarks(&self) -> String {
+            self.0.to_bookmarks()
+        }
+        fn to_urls(&self) -> String {
+            self.0.to_urls()
+        }
+    }
+
+    #[test]
+    fn failed_json_falls_back_to_text_and_fails_the_exit_status() {
+        let report = SearchReport::new("alice".to_string(), vec![QueryResult::claimed("alice", "Up", "", "")], false);
+        let path = std::env::temp_dir().join(format!("watson-test-{}-degraded.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        assert_eq!(degraded_exit_code(), None);
+
+        handle_output(&Unserializable(report.clone()), &OutputFormat::Json, Some(path), false).unwrap();
+        let written = fs::read_to_string(path);
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(written.unwrap(), report.to_text());
+        assert_eq!(degraded_exit_code(), Some(1));
+        OUTPUT_DEGRADED.store(false, Ordering::Relaxed);
+    }
+
+    #[test]
+    fn dev_preset_keeps_only_its_sites() {
+        let sites = load_sites_data(true, false, false, true).unwrap().sites;
+        let presets = presets::load_presets(None).unwrap();
+        let total = sites.len();
+
+        let (selected, skipped) = select_preset_sites(sites.clone(), &presets, &["DEV".to_string()]).unwrap();
+        let dev = &presets["dev"];
+        assert!(selected.contains_key("GitHub"));
+        assert!(selected.keys().all(|name| dev.iter().any(|s| s.eq_ignore_ascii_case(name))));
+        assert_eq!(selected.len() + skipped.len(), total);
+        assert!(skipped.iter().all(|s| s.reason == SkipReason::Excluded));
+        assert!(skipped.iter().any(|s| s.site_name == "Instagram"));
+
+        assert_eq!(select_preset_sites(sites, &presets, &["dev".to_string(), "nope".to_string()]).unwrap_err(), "nope");
+    }
+
+    #[test]
+    fn username_length_counts_characters_not_bytes() {
+        // 13 characters, 52 bytes
+        let emoji = "\u{1F50D}".repeat(13);
+        assert!(emoji.len() > 50);
+        assert!(username_len_ok(&emoji, 50));
+        assert!(!username_len_ok(&"\u{1F50D}".repeat(51), 50));
+        assert!(username_len_ok(&"a".repeat(50), 50));
+        assert!(!username_len_ok("", 50));
+    }
+
+    #[test]
+    fn piped_json_has_no_color_codes() {
+        assert!(!color_enabled(cli::ColorChoice::Auto, false, false));
+        assert!(!color_enabled(cli::ColorChoice::Auto, true, true));
+        assert!(!color_enabled(cli::ColorChoice::Never, true, false));
+        assert!(color_enabled(cli::ColorChoice::Auto, true, false));
+        assert!(color_enabled(cli::ColorChoice::Always, false, false));
+
+        let report = SearchReport::new("alice".to_string(), vec![QueryResult::claimed("alice", "Up", "", "")], false);
+        let json = report.to_json().unwrap();
+        colored::control::set_override(color_enabled(cli::ColorChoice::Auto, false, false));
+        let piped = output::colorize_json(&json);
+        colored::control::unset_override();
+        assert_eq!(piped, json);
+        assert!(!piped.contains('\x1b'));
+    }
+
+    #[test]
+    fn preview_lists_the_generated_set_once() {
+        let names = vec!["John.Doe".to_string(), "john.doe".to_string()];
+        let previewed = variation_usernames(&names, "-u");
+        let generated: std::collections::HashSet<String> = names.iter().flat_map(|n| generate_variations(n)).collect();
+        assert_eq!(previewed.len(), generated.len());
+        assert!(previewed.iter().all(|(name, _)| generated.contains(name)));
+        assert_eq!(previewed[..14].iter().map(|(name, _)| name.clone()).collect::<Vec<_>>(), generate_variations("John.Doe"));
+    }
+
+    #[test]
+    fn file_duplicates_are_searched_once_in_first_seen_order() {
+        let path = std::env::temp_dir().join(format!("watson-test-{}-users.txt", std::process::id()));
+        std::fs::write(&path, "bob\nalice\n\n  bob  \ncarol\nalice\n").unwrap();
+        let listed = listed_usernames(&std::fs::read_to_string(&path).unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(listed[2], (4, "bob".to_string()));
+
+        let mut users: Vec<String> = listed.into_iter().map(|(_, user)| user).collect();
+        assert_eq!(dedupe_usernames(&mut users), 2);
+        assert_eq!(users, ["bob", "alice", "carol"]);
+        assert_eq!(dedupe_usernames(&mut users), 0);
+    }
+
+    #[test]
+    fn newlines_and_spaces_are_disallowed_in_usernames() {
+        assert_eq!(disallowed_char("alice\nbob", None), Some('\n'));
+        assert_eq!(disallowed_char("alice bob", None), Some(' '));
+        assert_eq!(disallowed_char("al\u{7f}ice", None), Some('\u{7f}'));
+        assert_eq!(disallowed_char("alice.bob-\u{e9}", None), None);
+
+        let charset = regex::Regex::new("^(?:[a-z0-9_])$").unwrap();
+        assert_eq!(disallowed_char("alice_1", Some(&charset)), None);
+        assert_eq!(disallowed_char("alice.bob", Some(&charset)), Some('.'));
+    }
+
+    #[test]
+    fn file_names_lose_separators_and_reserved_characters() {
+        assert_eq!(sanitize_filename("alice"), "alice");
+        assert_eq!(sanitize_filename("../etc/passwd"), ".._etc_passwd");
+        assert_eq!(sanitize_filename("a\\b:c*d?e\"f<g>h|i"), "a_b_c_d_e_f_g_h_i");
+        assert_eq!(sanitize_filename("tab\there"), "tab_here");
+        assert_eq!(sanitize_filename(".."), "_");
+        assert_eq!(sanitize_filename("j\u{f6}rg"), "j\u{f6}rg");
+    }
+
+    static QUICK_REQUESTS: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+
+    #[tokio::test]
+    async fn quick_checks_only_its_preset_and_stops_at_the_hits() {
+        let sites = load_sites_data(true, false, false, true).unwrap().sites;
+        let presets = presets::load_presets(None).unwrap();
+        let (quick, skipped) = select_preset_sites(sites, &presets, &["quick".to_string()]).unwrap();
+        assert!(quick.contains_key("GitHub"));
+        assert!(quick.keys().all(|name| presets["quick"].contains(name)));
+        assert!(!skipped.is_empty());
+
+        // Every quick site claims the username; three hits end the scan
+        let addr = testutil::serve(|_| {
+            QUICK_REQUESTS.fetch_add(1, Ordering::SeqCst);
+            "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+        })
+        .await;
+        let local: HashMap<String, data::SiteInfo> = quick
+            .keys()
+            .map(|name| {
+                let site = serde_json::json!({
+                    "url": format!("http://{}/{{}}", addr),
+                    "urlMain": format!("http://{}/", addr),
+                    "errorType": "status_code",
+                });
+                (name.clone(), serde_json::from_value(site).unwrap())
+            })
+            .collect();
+        let engine = SearchEngine::new(5, 1, false, false).unwrap().with_stop_after_claims(3);
+        let results = engine.search_username("alice", &local).await;
+        assert_eq!(results.len(), 3);
+        assert_eq!(QUICK_REQUESTS.load(Ordering::SeqCst), 3);
+        assert!(results.iter().all(|r| r.is_claimed() && quick.contains_key(&r.site_name)));
+    }
+
+    #[cfg(feature = "scrape")]
+    static MAIN_PROXY_REQUESTS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+    #[cfg(feature = "scrape")]
+    static SCRAPE_PROXY_REQUESTS: std::sync::Mutex<Vec<String>> = std::sync::Mutex::new(Vec::new());
+    #[cfg(feature = "scrape")]
+    const PROXIED_PROFILE: &str = "HTTP/1.1 200 OK\r\nContent-Length: 25\r\nConnection: close\r\n\r\n<p>alice@mail.example</p>";
+
+    #[cfg(feature = "scrape")]
+    #[tokio::test]
+    async fn scraping_uses_the_scrape_proxy_and_detection_the_main_one() {
+        let main_proxy = testutil::serve(|request| {
+            MAIN_PROXY_REQUESTS.lock().unwrap().push(testutil::path_of(request).to_string());
+            PROXIED_PROFILE.to_string()
+        })
+        .await;
+        let scrape_proxy = testutil::serve(|request| {
+            SCRAPE_PROXY_REQUESTS.lock().unwrap().push(testutil::path_of(request).to_string());
+            PROXIED_PROFILE.to_string()
+        })
+        .await;
+        let site: data::SiteInfo = serde_json::from_value(serde_json::json!({
+            "url": "http://profiles.example/{}",
+            "urlMain": "http://profiles.example/",
+            "errorType": "status_code",
+        }))
+        .unwrap();
+        let sites = HashMap::from([("Profiles".to_string(), site)]);
+
+        let engine = SearchEngine::new(5, 1, false, false).unwrap().with_proxy(format!("http://{}", main_proxy)).unwrap();
+        let results = engine.search_username("alice", &sites).await;
+        assert!(results[0].is_claimed());
+
+        let client = scrape_client(&engine, Some(&format!("http://{}", scrape_proxy))).unwrap();
+        let profiles = vec![("Profiles".to_string(), results[0].profile_url.clone())];
+        let scraped = scrape_emails_from_results(profiles, client, 1, vec![]).await;
+        assert_eq!(scraped[0].2, Some(vec!["alice@mail.example".to_string()]));
+
+        assert_eq!(*MAIN_PROXY_REQUESTS.lock().unwrap(), ["http://profiles.example/alice"]);
+        assert_eq!(*SCRAPE_PROXY_REQUESTS.lock().unwrap(), ["http://profiles.example/alice"]);
+    }
+}
@@ -1,27 +1,186 @@
-use crate::engine::QueryResult;
+use crate::engine::{QueryResult, QueryStatus, SkipReason, SkippedSite};
+use crate::http::TrafficStats;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum OutputFormat {
-    Text,
-    Json,
-    Csv,
-    Html,
+/// How a scan was run, recorded in each report for reproducibility.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanMetadata {
+    pub watson_version: String,
+    pub started_at: DateTime<Utc>,
+    /// File path, URL or `mock`.
+    pub site_data_source: String,
+    /// CRC-32 of the site data as loaded.
+    pub site_data_hash: String,
+    pub max_concurrent: usize,
+    pub tor_used: bool,
+    /// Proxy URLs in use, without credentials.
+    pub proxies: Vec<String>,
+    /// Sites left after `--site`/`--preset` filtering, before NSFW and other skips.
+    pub sites_considered: usize,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl ScanMetadata {
+    pub fn new(site_data_source: &str, site_data_hash: &str, max_concurrent: usize) -> Self {
+        Self {
+            watson_version: env!("CARGO_PKG_VERSION").to_string(),
+            started_at: Utc::now(),
+            site_data_source: site_data_source.to_string(),
+            site_data_hash: site_data_hash.to_string(),
+            max_concurrent,
+            tor_used: false,
+            proxies: vec![],
+            sites_considered: 0,
+        }
+    }
+
+    pub fn with_tor(mut self, tor_used: bool) -> Self {
+        self.tor_used = tor_used;
+        self
+    }
+
+    pub fn with_proxies(mut self, proxies: Vec<String>) -> Self {
+        self.proxies = proxies;
+        self
+    }
+
+    pub fn with_sites_considered(mut self, sites_considered: usize) -> Self {
+        self.sites_considered = sites_considered;
+        self
+    }
+
+    /// A copy stamped with the current time, for the next username's scan.
+    pub fn restarted(&self) -> Self {
+        Self {
+            started_at: Utc::now(),
+            ..self.clone()
+        }
+    }
+}
+
+/// An email scraped from one or more found profiles, for `--dedupe-emails`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ScrapedEmail {
+    pub email: String,
+    /// Sites whose profile showed it, sorted.
+    pub sites: Vec<String>,
+}
+
+/// Folds per-profile scrape results into one entry per email, each listing every
+/// site it was found on. Sorted by email.
+#[cfg_attr(not(feature = "scrape"), allow(dead_code))]
+pub fn dedupe_emails(found: &[(String, String, Option<Vec<String>>)]) -> Vec<ScrapedEmail> {
+    let mut by_email: BTreeMap<&str, Vec<String>> = BTreeMap::new();
+    for (site_name, _, emails) in found {
+        for email in emails.iter().flatten() {
+            by_email.entry(email).or_default().push(site_name.clone());
+        }
+    }
+    by_email
+        .into_iter()
+        .map(|(email, mut sites)| {
+            sites.sort();
+            sites.dedup();
+            ScrapedEmail { email: email.to_string(), sites }
+        })
+        .collect()
+}
+
+/// The certificate a claimed site's host presented, from `--capture-tls`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsCertificate {
+    pub host: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub common_name: Option<String>,
+    /// DNS names and IP addresses from the subjectAltName extension.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sans: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issuer: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub not_after: Option<DateTime<Utc>>,
+    /// Why nothing could be read, when the handshake or parsing failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl TlsCertificate {
+    #[cfg_attr(not(feature = "tls"), allow(dead_code))]
+    pub fn failed(host: &str, error: &str) -> Self {
+        Self {
+            host: host.to_string(),
+            common_name: None,
+            sans: Vec::new(),
+            issuer: None,
+            not_after: None,
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// How many checked sites used one detection method, and how many of them were claimed.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct DetectionStats {
+    pub checked: usize,
+    pub claimed: usize,
+    /// `claimed / checked`, from 0 to 1.
+    pub claimed_rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchReport {
     pub username: String,
     pub total_sites: usize,
     pub claimed_count: usize,
     pub available_count: usize,
     pub error_count: usize,
+    /// Inconclusive results: rate limited, blocked or timed out.
+    #[serde(default)]
+    pub unknown_count: usize,
+    /// Sites that answered with any HTTP response.
+    #[serde(default)]
+    pub reachable_count: usize,
+    /// Sites checked without getting a response (DNS, connect, timeout).
+    #[serde(default)]
+    pub unreachable_count: usize,
     pub results: Vec<QueryResult>,
+    /// Results per site `errorType`; results without one count as `unrecorded`.
+    #[serde(default)]
+    pub detection_methods: BTreeMap<String, DetectionStats>,
+    /// Failed and timed-out checks per `error_kind`; unclassified ones count as `other`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub error_reasons: BTreeMap<String, usize>,
+    pub skipped: Vec<SkippedSite>,
     pub tor_used: bool,
+    #[serde(default)]
+    pub traffic: TrafficStats,
+    /// The scan was stopped by `--max-errors`, so sites may be missing.
+    #[serde(default)]
+    pub aborted: bool,
+    /// Absent in reports saved before it was recorded.
+    #[serde(default)]
+    pub metadata: Option<ScanMetadata>,
+    /// Emails scraped from found profiles with `--dedupe-emails`, once each.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scraped_emails: Vec<ScrapedEmail>,
+    /// Certificates of claimed sites' hosts from `--capture-tls`, one per host.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tls_certificates: Vec<TlsCertificate>,
+    /// Section found accounts by category in text output.
+    #[serde(skip)]
+    pub group_by_category: bool,
+    /// Show only this many claimed results, fastest first (`--top-fastest`).
+    #[serde(skip)]
+    pub top_fastest: Option<usize>,
 }
 
 impl SearchReport {
-    pub fn new(username: String, results: Vec<QueryResult>, tor_used: bool) -> Self {
+    pub fn new(username: String, mut results: Vec<QueryResult>, tor_used: bool) -> Self {
+        // Sites come from a HashMap, so sort for stable output across runs
+        results.sort_by(|a, b| a.site_name.cmp(&b.site_name));
+
         let claimed_count = results.iter().filter(|r| r.is_claimed()).count();
         let available_count = results
             .iter()
@@ -31,6 +190,40 @@ impl SearchReport {
             .iter()
             .filter(|r| r.status == crate::engine::QueryStatus::Error)
             .count();
+        let unknown_count = results
+            .iter()
+            .filter(|r| matches!(r.status, QueryStatus::Unknown | QueryStatus::TimedOut))
+            .count();
+        let reachable_count = results.iter().filter(|r| r.http_status.is_some()).count();
+        // Illegal usernames were never requested, so they are neither
+        let unreachable_count = results
+            .iter()
+            .filter(|r| r.status != QueryStatus::Illegal && r.http_status.is_none())
+            .count();
+        let skipped = results
+            .iter()
+            .filter(|r| r.status == QueryStatus::Illegal)
+            .map(|r| SkippedSite::new(&r.site_name, SkipReason::Regex))
+            .collect();
+        let mut detection_methods: BTreeMap<String, DetectionStats> = BTreeMap::new();
+        for result in &results {
+            let method = result.detection.as_ref().map_or("unrecorded", |t| t.as_str());
+            let stats = detection_methods.entry(method.to_string()).or_default();
+            stats.checked += 1;
+            stats.claimed += usize::from(result.is_claimed());
+        }
+        for stats in detection_methods.values_mut() {
+            stats.claimed_rate = stats.claimed as f64 / stats.checked as f64;
+        }
+        let mut error_reasons: BTreeMap<String, usize> = BTreeMap::new();
+        for result in results.iter().filter(|r| matches!(r.status, QueryStatus::Error | QueryStatus::TimedOut)) {
+            let kind = match (result.error_kind, result.status) {
+                (Some(kind), _) => kind.as_str(),
+                (None, QueryStatus::TimedOut) => "timeout",
+                (None, _) => "other",
+            };
+            *error_reasons.entry(kind.to_string()).or_default() += 1;
+        }
 
         Self {
             username,
@@ -38,32 +231,309 @@ impl SearchReport {
             claimed_count,
             available_count,
             error_count,
+            unknown_count,
+            reachable_count,
+            unreachable_count,
             results,
+            detection_methods,
+            error_reasons,
+            skipped,
             tor_used,
+            traffic: TrafficStats::default(),
+            aborted: false,
+            metadata: None,
+            scraped_emails: Vec::new(),
+            tls_certificates: Vec::new(),
+            group_by_category: false,
+            top_fastest: None,
         }
     }
 
-    pub fn to_text(&self) -> String {
+    /// Reads a report written by `to_msgpack`.
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self, rmp_serde::decode::Error> {
+        rmp_serde::from_slice(bytes)
+    }
+
+    /// Adds sites that were filtered out before searching (NSFW, `--site`).
+    pub fn with_skipped(mut self, skipped: Vec<SkippedSite>) -> Self {
+        self.skipped.extend(skipped);
+        self.skipped.sort_by(|a, b| a.site_name.cmp(&b.site_name));
+        self
+    }
+
+    /// Marks sites out of `sites` that have neither a result nor another skip
+    /// reason as `Capped`: the scan stopped before reaching them.
+    pub fn with_capped<'a>(self, sites: impl IntoIterator<Item = &'a String>) -> Self {
+        let capped = sites
+            .into_iter()
+            .filter(|name| {
+                !self.results.iter().any(|r| r.site_name == **name)
+                    && !self.skipped.iter().any(|s| s.site_name == **name)
+            })
+            .map(|name| SkippedSite::new(name, SkipReason::Capped))
+            .collect();
+        self.with_skipped(capped)
+    }
+
+    pub fn with_traffic(mut self, traffic: TrafficStats) -> Self {
+        self.traffic = traffic;
+        self
+    }
+
+    pub fn with_aborted(mut self, aborted: bool) -> Self {
+        self.aborted = aborted;
+        self
+    }
+
+    pub fn with_metadata(mut self, metadata: ScanMetadata) -> Self {
+        self.metadata = Some(metadata);
+        self
+    }
+
+    #[cfg_attr(not(feature = "tls"), allow(dead_code))]
+    pub fn with_tls_certificates(mut self, certificates: Vec<TlsCertificate>) -> Self {
+        self.tls_certificates = certificates;
+        self
+    }
+
+    #[cfg_attr(not(feature = "scrape"), allow(dead_code))]
+    pub fn with_scraped_emails(mut self, emails: Vec<ScrapedEmail>) -> Self {
+        self.scraped_emails = emails;
+        self
+    }
+
+    /// `status_code 120 (4% claimed), message 80 (10% claimed)`, most used first.
+    pub fn detection_summary(&self) -> String {
+        let mut methods: Vec<(&String, &DetectionStats)> = self.detection_methods.iter().collect();
+        methods.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.checked));
+        methods
+            .iter()
+            .map(|(method, stats)| format!("{} {} ({:.0}% claimed)", method, stats.checked, stats.claimed_rate * 100.0))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// `12 timeout, 3 connect, 1 tls`, most common first.
+    pub fn error_reason_summary(&self) -> String {
+        let mut reasons: Vec<(&String, &usize)> = self.error_reasons.iter().collect();
+        reasons.sort_by_key(|(_, count)| std::cmp::Reverse(**count));
+        reasons
+            .iter()
+            .map(|(kind, count)| format!("{} {}", count, kind))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Sites that errored in this report, the candidates for `--retry-errors`.
+    pub fn error_sites(&self) -> Vec<&str> {
+        self.results
+            .iter()
+            .filter(|r| r.status == QueryStatus::Error)
+            .map(|r| r.site_name.as_str())
+            .collect()
+    }
+
+    /// Replaces each site's result with its re-checked one and recounts; sites not
+    /// re-checked keep their earlier result. `traffic` is added to the report's total.
+    pub fn with_retried(self, retried: Vec<QueryResult>, traffic: TrafficStats) -> Self {
+        let mut results = self.results;
+        for fresh in retried {
+            if let Some(old) = results.iter_mut().find(|r| r.site_name == fresh.site_name) {
+                *old = fresh;
+            }
+        }
+        // Regex skips are derived from the results again by `new`
+        let skipped = self
+            .skipped
+            .into_iter()
+            .filter(|s| s.reason != SkipReason::Regex)
+            .collect();
+        let traffic = TrafficStats {
+            requests: self.traffic.requests + traffic.requests,
+            bytes: self.traffic.bytes + traffic.bytes,
+        };
+        SearchReport::new(self.username, results, self.tor_used)
+            .with_skipped(skipped)
+            .with_traffic(traffic)
+    }
+
+    /// Prints and exports only the `n` claimed results with the lowest
+    /// `response_time_ms`. The report keeps every result and its counts; see
+    /// `shown_results`.
+    pub fn with_top_fastest(mut self, n: usize) -> Self {
+        self.top_fastest = Some(n);
+        self
+    }
+
+    /// The results the printers show: all of them, or with `with_top_fastest` the
+    /// `n` fastest claimed results, fastest first, ahead of the non-claimed ones.
+    /// Claimed results without a time sort last.
+    pub fn shown_results(&self) -> Cow<'_, [QueryResult]> {
+        let Some(n) = self.top_fastest else {
+            return Cow::Borrowed(&self.results);
+        };
+        let (mut claimed, others): (Vec<&QueryResult>, Vec<&QueryResult>) =
+            self.results.iter().partition(|r| r.is_claimed());
+        claimed.sort_by_key(|r| (r.response_time_ms.is_none(), r.response_time_ms));
+        claimed.truncate(n);
+        Cow::Owned(claimed.into_iter().chain(others).cloned().collect())
+    }
+
+    /// The report as JSON exports it: with `shown_results` in place of `results`.
+    fn exported(&self) -> Cow<'_, SearchReport> {
+        match self.top_fastest {
+            None => Cow::Borrowed(self),
+            Some(_) => Cow::Owned(SearchReport {
+                results: self.shown_results().into_owned(),
+                ..self.clone()
+            }),
+        }
+    }
+
+    pub fn with_group_by_category(mut self, group_by_category: bool) -> Self {
+        self.group_by_category = group_by_category;
+        self
+    }
+
+    /// `[+] site: url` lines for claimed results, under `== category ==` headers
+    /// when grouping is on (uncategorized sites last, as `other`).
+    pub fn found_accounts(&self) -> String {
+        let shown = self.shown_results();
+        let claimed = shown.iter().filter(|r| r.is_claimed());
+        let line = |r: &QueryResult| format!("[+] {}: {}\n", r.site_name, r.profile_url);
+        if !self.group_by_category {
+            return claimed.map(line).collect();
+        }
+
+        let mut groups: BTreeMap<&str, Vec<&QueryResult>> = BTreeMap::new();
+        let mut other = Vec::new();
+        for result in claimed {
+            match result.category {
+                Some(ref category) => groups.entry(category.as_str()).or_default().push(result),
+                None => other.push(result),
+            }
+        }
+
+        let mut output = String::new();
+        let other = (!other.is_empty()).then_some(("other", other));
+        for (category, results) in groups.into_iter().chain(other) {
+            output.push_str(&format!("== {} ==\n", category));
+            for result in results {
+                output.push_str(&line(result));
+            }
+        }
+        output
+    }
+
+    /// One-line reachability summary, e.g. "480 of 482 checked sites responded (2 unreachable)".
+    pub fn reachability_summary(&self) -> String {
+        format!(
+            "{} of {} checked sites responded ({} unreachable)",
+            self.reachable_count,
+            self.reachable_count + self.unreachable_count,
+            self.unreachable_count
+        )
+    }
+
+    /// Nothing answered at all, which points at the network or proxy rather
+    /// than the username.
+    pub fn nothing_reachable(&self) -> bool {
+        self.reachable_count == 0 && self.unreachable_count > 0
+    }
+
+    /// One-line traffic summary, e.g. "482 requests, ~14.2 MB".
+    pub fn traffic_summary(&self) -> String {
+        format!("{} requests, ~{}", self.traffic.requests, format_bytes(self.traffic.bytes))
+    }
+
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// Rendering shared by every report type so `handle_output` can treat them alike.
+pub trait Report {
+    fn to_text(&self) -> String;
+    fn to_json(&self) -> Result<String, serde_json::Error>;
+    /// `to_json` without indentation or newlines, for machine consumption.
+    fn to_json_compact(&self) -> Result<String, serde_json::Error>;
+    fn to_csv(&self) -> String;
+    fn to_html(&self, show_all: bool) -> String;
+    fn to_term(&self, width: usize) -> String;
+    fn to_msgpack(&self) -> Result<Vec<u8>, rmp_serde::encode::Error>;
+    fn to_maltego(&self) -> Result<String, csv::Error>;
+    fn to_bookmarks(&self) -> String;
+    fn to_urls(&self) -> String;
+}
+
+/// Where a username search's output goes, so formatting is decoupled from its
+/// destination. `write_result` sees each result in report order, then `finish`
+/// the complete report; sinks that render whole reports, like every `--format`,
+/// only need `finish`.
+pub trait OutputSink {
+    fn write_result(&mut self, _result: &QueryResult) -> Result<(), Box<dyn std::error::Error>> {
+        Ok(())
+    }
+
+    fn finish(&mut self, report: &SearchReport) -> Result<(), Box<dyn std::error::Error>>;
+}
+
+impl SearchReport {
+    /// Feeds every result and then the report itself to `sink`.
+    pub fn write_to(&self, sink: &mut dyn OutputSink) -> Result<(), Box<dyn std::error::Error>> {
+        for result in self.shown_results().iter() {
+            sink.write_result(result)?;
+        }
+        sink.finish(self)
+    }
+}
+
+impl Report for SearchReport {
+    fn to_text(&self) -> String {
         let mut output = format!("\n=== Watson Search Results for '{}' ===\n", self.username);
         output.push_str(&format!("Total sites checked: {}\n", self.total_sites));
         output.push_str(&format!("Found on: {} sites\n", self.claimed_count));
         output.push_str(&format!("Available on: {} sites\n", self.available_count));
         output.push_str(&format!("Errors: {}\n", self.error_count));
+        output.push_str(&format!("Unknown: {}\n", self.unknown_count));
+        output.push_str(&format!("Reachable: {}\n", self.reachability_summary()));
+        if !self.detection_methods.is_empty() {
+            output.push_str(&format!("Detection: {}\n", self.detection_summary()));
+        }
+        if !self.error_reasons.is_empty() {
+            output.push_str(&format!("Error reasons: {}\n", self.error_reason_summary()));
+        }
+        if self.aborted {
+            output.push_str("Aborted: too many errors; sites not reached are missing from these results\n");
+        }
+        if !self.skipped.is_empty() {
+            output.push_str(&format!("Skipped: {} sites\n", self.skipped.len()));
+        }
+        if let Some(n) = self.top_fastest {
+            output.push_str(&format!("Showing: the {} fastest found accounts\n", n.min(self.claimed_count)));
+        }
 
         if self.tor_used {
             output.push_str("Using Tor: Yes\n");
         }
+        if self.traffic.requests > 0 {
+            output.push_str(&format!("Traffic: {}\n", self.traffic_summary()));
+        }
 
         output.push_str("\n--- Found Accounts ---\n");
-
-        for result in &self.results {
-            if result.is_claimed() {
-                output.push_str(&format!(
-                    "[+] {}: {}\n",
-                    result.site_name, result.profile_url
-                ));
-            }
-        }
+        output.push_str(&self.found_accounts());
 
         output.push_str("\n--- Available Accounts ---\n");
 
@@ -73,23 +543,114 @@ impl SearchReport {
                     "[-] {}: {}\n",
                     result.site_name, result.profile_url
                 ));
+                if let Some(ref msg) = result.matched_error_msg {
+                    output.push_str(&format!("    matched: {}\n", msg));
+                }
+            }
+        }
+
+        if self.unknown_count > 0 {
+            output.push_str("\n--- Unknown (rate limited, blocked or timed out) ---\n");
+            for result in &self.results {
+                if matches!(result.status, QueryStatus::Unknown | QueryStatus::TimedOut) {
+                    output.push_str(&format!("[?] {}: {}\n", result.site_name, result.profile_url));
+                    if let Some(ref reason) = result.error_message {
+                        output.push_str(&format!("    reason: {}\n", reason));
+                    }
+                }
+            }
+        }
+
+        if !self.tls_certificates.is_empty() {
+            output.push_str("\n--- TLS Certificates ---\n");
+            for cert in &self.tls_certificates {
+                if let Some(ref error) = cert.error {
+                    output.push_str(&format!("[!] {}: {}\n", cert.host, error));
+                    continue;
+                }
+                output.push_str(&format!("[*] {}: CN {}\n", cert.host, cert.common_name.as_deref().unwrap_or("(none)")));
+                if !cert.sans.is_empty() {
+                    output.push_str(&format!("    SANs: {}\n", cert.sans.join(", ")));
+                }
+                if let Some(ref issuer) = cert.issuer {
+                    output.push_str(&format!("    issuer: {}\n", issuer));
+                }
+                if let Some(not_after) = cert.not_after {
+                    output.push_str(&format!("    expires: {}\n", not_after.format("%Y-%m-%d %H:%M UTC")));
+                }
             }
         }
 
         output
     }
 
-    pub fn to_json(&self) -> Result<String, serde_json::Error> {
-        serde_json::to_string_pretty(self)
+    /// Dense view: one column-aligned `site  status  time  url` line per claimed site,
+    /// each truncated to `width` characters.
+    fn to_term(&self, width: usize) -> String {
+        let shown = self.shown_results();
+        let claimed: Vec<&QueryResult> = shown.iter().filter(|r| r.is_claimed()).collect();
+
+        let site_width = claimed
+            .iter()
+            .map(|r| r.site_name.chars().count())
+            .max()
+            .unwrap_or(0);
+
+        let mut output = String::new();
+        for result in claimed {
+            let time = result
+                .response_time_ms
+                .map(|ms| format!("{}ms", ms))
+                .unwrap_or_else(|| "-".to_string());
+            let line = format!(
+                "{:<site_width$}  {:<9}  {:>7}  {}",
+                result.site_name,
+                format!("{:?}", result.status).to_lowercase(),
+                time,
+                result.profile_url,
+            );
+            output.push_str(&truncate_to_width(&line, width));
+            output.push('\n');
+        }
+
+        output
+    }
+
+    fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(&self.exported())
+    }
+
+    fn to_json_compact(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(&self.exported())
+    }
+
+    /// Archives keep every result, even with `--top-fastest`.
+    fn to_msgpack(&self) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        rmp_serde::to_vec_named(self)
+    }
+
+    fn to_maltego(&self) -> Result<String, csv::Error> {
+        maltego_csv("maltego.Alias", &self.username, &self.shown_results())
+    }
+
+    fn to_bookmarks(&self) -> String {
+        netscape_bookmarks(&self.username, &self.shown_results())
+    }
+
+    fn to_urls(&self) -> String {
+        claimed_urls(&self.shown_results())
     }
 
-    pub fn to_csv(&self) -> String {
+    fn to_csv(&self) -> String {
+        let shown = self.shown_results();
+        let with_source = shown.iter().any(|r| r.source.is_some());
         let mut output =
-            String::from("site_name,site_url,profile_url,status,http_status,response_time_ms\n");
+            String::from("site_name,site_url,profile_url,status,http_status,response_time_ms,final_url,checked_at,category");
+        output.push_str(if with_source { ",source\n" } else { "\n" });
 
-        for result in &self.results {
+        for result in shown.iter() {
             output.push_str(&format!(
-                "{},{},{},{},{},{}\n",
+                "{},{},{},{},{},{},{},{},{}",
                 result.site_name,
                 result.site_url,
                 result.profile_url,
@@ -102,13 +663,27 @@ impl SearchReport {
                     .response_time_ms
                     .map(|s| s.to_string())
                     .unwrap_or_default(),
+                csv_field(result.final_url.as_deref().unwrap_or_default()),
+                result
+                    .checked_at
+                    .map(|t| t.to_rfc3339())
+                    .unwrap_or_default(),
+                csv_field(result.category.as_deref().unwrap_or_default()),
             ));
+            if with_source {
+                output.push(',');
+                output.push_str(&csv_field(result.source.as_deref().unwrap_or_default()));
+            }
+            output.push('\n');
         }
 
         output
     }
 
-    pub fn to_html(&self) -> String {
+    /// Renders a self-contained HTML report. Claimed rows are listed first and the
+    /// results table is paginated client-side; unless `show_all` is set, non-claimed
+    /// rows start collapsed behind a toggle.
+    fn to_html(&self, show_all: bool) -> String {
         let mut html = String::from(
             r#"<!DOCTYPE html>
 <html>
@@ -129,8 +704,12 @@ impl SearchReport {
         .claimed { color: #28a745; font-weight: bold; }
         .available { color: #dc3545; }
         .error { color: #ffc107; }
+        .unknown { color: #6c757d; }
         .site-link { color: #007bff; text-decoration: none; }
         .site-link:hover { text-decoration: underline; }
+        .controls { display: flex; gap: 10px; align-items: center; margin-top: 20px; }
+        .controls button { padding: 6px 12px; border: 1px solid #007bff; background: white; color: #007bff; border-radius: 4px; cursor: pointer; }
+        .controls button:disabled { opacity: 0.4; cursor: default; }
     </style>
 </head>
 <body>
@@ -154,43 +733,65 @@ impl SearchReport {
                 <div class="stat-value">#ERRORS#</div>
                 <div class="stat-label">Errors</div>
             </div>
+            <div class="stat">
+                <div class="stat-value">#UNKNOWN#</div>
+                <div class="stat-label">Unknown</div>
+            </div>
         </div>
-        <table>
+        <div class="controls">
+            <button id="toggle-others" type="button"></button>
+            <button id="prev-page" type="button">&laquo; Prev</button>
+            <span id="page-info"></span>
+            <button id="next-page" type="button">Next &raquo;</button>
+        </div>
+        <table id="results">
             <thead>
                 <tr>
                     <th>Site</th>
+                    <th>Category</th>
                     <th>Profile URL</th>
                     <th>Status</th>
                     <th>HTTP Status</th>
                     <th>Response Time</th>
+                    <th>Final URL</th>
                 </tr>
             </thead>
             <tbody>
 "#,
         );
 
-        for result in &self.results {
+        // Claimed first; the sort is stable so each group keeps site-name order
+        let shown = self.shown_results();
+        let mut rows: Vec<&QueryResult> = shown.iter().collect();
+        rows.sort_by_key(|r| !r.is_claimed());
+
+        for result in rows {
             let status_class = match result.status {
                 crate::engine::QueryStatus::Claimed => "claimed",
                 crate::engine::QueryStatus::Available => "available",
                 crate::engine::QueryStatus::Error => "error",
+                crate::engine::QueryStatus::Unknown | crate::engine::QueryStatus::TimedOut => "unknown",
                 _ => "",
             };
 
             let status_text = format!("{:?}", result.status);
 
             html.push_str(&format!(
-                r#"                <tr>
+                r#"                <tr data-claimed="{}">
+                    <td>{}</td>
                     <td>{}</td>
                     <td><a href="{}" class="site-link" target="_blank">{}</a></td>
                     <td class="{}">{}</td>
                     <td>{}</td>
                     <td>{} ms</td>
+                    <td>{}</td>
                 </tr>
 "#,
-                result.site_name,
-                result.profile_url,
-                result.profile_url,
+                result.is_claimed(),
+                escape_html(&result.site_name),
+                escape_html(result.category.as_deref().unwrap_or("")),
+                escape_html(&result.profile_url),
+                escape_html(&result.profile_url),
                 status_class,
                 status_text,
                 result
@@ -201,24 +802,1328 @@ impl SearchReport {
                     .response_time_ms
                     .map(|s| s.to_string())
                     .unwrap_or_else(|| "N/A".to_string()),
+                escape_html(result.final_url.as_deref().unwrap_or("N/A")),
             ));
         }
 
         html.push_str(
             r#"            </tbody>
         </table>
-    </div>
+        <script>
+            (function () {
+                var PAGE_SIZE = 50;
+                var showAll = #SHOW_ALL#;
+                var page = 0;
+                var rows = Array.prototype.slice.call(document.querySelectorAll('#results tbody tr'));
+                var toggle = document.getElementById('toggle-others');
+                var prev = document.getElementById('prev-page');
+                var next = document.getElementById('next-page');
+                var info = document.getElementById('page-info');
+
+                function render() {
+                    var visible = rows.filter(function (r) {
+                        return showAll || r.getAttribute('data-claimed') === 'true';
+                    });
+                    var pages = Math.max(1, Math.ceil(visible.length / PAGE_SIZE));
+                    page = Math.min(page, pages - 1);
+                    rows.forEach(function (r) { r.style.display = 'none'; });
+                    visible.slice(page * PAGE_SIZE, (page + 1) * PAGE_SIZE).forEach(function (r) {
+                        r.style.display = '';
+                    });
+                    toggle.textContent = showAll ? 'Hide available/errors' : 'Show available/errors';
+                    info.textContent = 'Page ' + (page + 1) + ' of ' + pages;
+                    prev.disabled = page === 0;
+                    next.disabled = page >= pages - 1;
+                }
+
+                toggle.addEventListener('click', function () { showAll = !showAll; page = 0; render(); });
+                prev.addEventListener('click', function () { page--; render(); });
+                next.addEventListener('click', function () { page++; render(); });
+                render();
+            })();
+        </script>
+"#,
+        );
+
+        if !self.skipped.is_empty() {
+            html.push_str(
+                r#"        <h2>Skipped Sites</h2>
+        <table>
+            <thead>
+                <tr>
+                    <th>Site</th>
+                    <th>Reason</th>
+                </tr>
+            </thead>
+            <tbody>
+"#,
+            );
+            for skipped in &self.skipped {
+                html.push_str(&format!(
+                    r#"                <tr>
+                    <td>{}</td>
+                    <td>{}</td>
+                </tr>
+"#,
+                    escape_html(&skipped.site_name),
+                    skipped.reason,
+                ));
+            }
+            html.push_str(
+                r#"            </tbody>
+        </table>
+"#,
+            );
+        }
+
+        if let Some(ref meta) = self.metadata {
+            let proxies = if meta.proxies.is_empty() {
+                "none".to_string()
+            } else {
+                meta.proxies.join(", ")
+            };
+            let rows = [
+                ("Watson version", meta.watson_version.clone()),
+                ("Started", meta.started_at.to_rfc3339()),
+                ("Site data", format!("{} (crc32 {})", meta.site_data_source, meta.site_data_hash)),
+                ("Sites considered", meta.sites_considered.to_string()),
+                ("Max concurrent", meta.max_concurrent.to_string()),
+                ("Tor", if meta.tor_used { "yes" } else { "no" }.to_string()),
+                ("Proxies", proxies),
+            ];
+            html.push_str(
+                r#"        <h2>Scan Details</h2>
+        <table>
+            <tbody>
+"#,
+            );
+            for (label, value) in rows {
+                html.push_str(&format!(
+                    r#"                <tr>
+                    <th>{}</th>
+                    <td>{}</td>
+                </tr>
+"#,
+                    label,
+                    escape_html(&value),
+                ));
+            }
+            html.push_str(
+                r#"            </tbody>
+        </table>
+"#,
+            );
+        }
+
+        html.push_str(
+            r#"    </div>
 </body>
 </html>
 "#,
         );
 
-        html = html.replace("#USERNAME#", &self.username);
+        html = html.replace("#USERNAME#", &escape_html(&self.username));
         html = html.replace("#TOTAL#", &self.total_sites.to_string());
         html = html.replace("#CLAIMED#", &self.claimed_count.to_string());
         html = html.replace("#AVAILABLE#", &self.available_count.to_string());
         html = html.replace("#ERRORS#", &self.error_count.to_string());
+        html = html.replace("#UNKNOWN#", &self.unknown_count.to_string());
+        html = html.replace("#SHOW_ALL#", if show_all { "true" } else { "false" });
 
         html
     }
 }
+
+/// Claimed accounts compared between an earlier and a later username report.
+/// Each list holds `(site_name, profile_url)` pairs in site-name order.
+pub struct ReportDiff {
+    pub old_username: String,
+    pub new_username: String,
+    pub added: Vec<(String, String)>,
+    pub removed: Vec<(String, String)>,
+    pub unchanged: Vec<(String, String)>,
+}
+
+impl ReportDiff {
+    pub fn new(old: &SearchReport, new: &SearchReport) -> Self {
+        let claimed = |report: &SearchReport| -> BTreeMap<String, String> {
+            report
+                .results
+                .iter()
+                .filter(|r| r.is_claimed())
+                .map(|r| (r.site_name.clone(), r.profile_url.clone()))
+                .collect()
+        };
+        let before = claimed(old);
+        let after = claimed(new);
+
+        let pick = |from: &BTreeMap<String, String>, keep: &dyn Fn(&String) -> bool| {
+            from.iter()
+                .filter(|(site, _)| keep(site))
+                .map(|(site, url)| (site.clone(), url.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        Self {
+            old_username: old.username.clone(),
+            new_username: new.username.clone(),
+            added: pick(&after, &|site| !before.contains_key(site)),
+            removed: pick(&before, &|site| !after.contains_key(site)),
+            unchanged: pick(&after, &|site| before.contains_key(site)),
+        }
+    }
+
+    fn title(&self) -> String {
+        if self.old_username == self.new_username {
+            format!("Changes for '{}'", self.new_username)
+        } else {
+            format!("Changes from '{}' to '{}'", self.old_username, self.new_username)
+        }
+    }
+
+    /// Lists added (`[+]`) then removed (`[-]`) sites; unchanged ones are omitted.
+    pub fn to_text(&self) -> String {
+        let mut output = format!("\n=== {} ===\n", self.title());
+        for (site, url) in &self.added {
+            output.push_str(&format!("[+] {}: {}\n", site, url));
+        }
+        for (site, url) in &self.removed {
+            output.push_str(&format!("[-] {}: {}\n", site, url));
+        }
+        if self.added.is_empty() && self.removed.is_empty() {
+            output.push_str("No changes.\n");
+        }
+        output
+    }
+
+    /// Renders the comparison as a standalone page with added (green), removed (red)
+    /// and unchanged sections, styled like the search report.
+    pub fn to_html(&self) -> String {
+        let mut html = String::from(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="UTF-8">
+    <title>Watson Change Report</title>
+    <style>
+        body { font-family: Arial, sans-serif; margin: 20px; background: #f5f5f5; }
+        .container { max-width: 900px; margin: 0 auto; background: white; padding: 20px; border-radius: 8px; box-shadow: 0 2px 4px rgba(0,0,0,0.1); }
+        h1 { color: #333; border-bottom: 2px solid #007bff; padding-bottom: 10px; }
+        .stats { display: flex; gap: 20px; margin: 20px 0; }
+        .stat { padding: 15px 25px; background: #f8f9fa; border-radius: 5px; text-align: center; }
+        .stat-value { font-size: 24px; font-weight: bold; color: #007bff; }
+        .stat-label { color: #666; font-size: 12px; }
+        table { width: 100%; border-collapse: collapse; margin-top: 20px; }
+        th, td { padding: 12px; text-align: left; border-bottom: 1px solid #ddd; }
+        th { background: #007bff; color: white; }
+        .site-link { color: #007bff; text-decoration: none; }
+        .site-link:hover { text-decoration: underline; }
+        .added td { background: #e6f4ea; }
+        .added .stat-value, h2.added { color: #28a745; }
+        .removed td { background: #fdecea; }
+        .removed .stat-value, h2.removed { color: #dc3545; }
+        .unchanged .stat-value, h2.unchanged { color: #666; }
+    </style>
+</head>
+<body>
+    <div class="container">
+        <h1>Watson Change Report</h1>
+        <p><strong>#TITLE#</strong></p>
+        <div class="stats">
+            <div class="stat added">
+                <div class="stat-value">#ADDED#</div>
+                <div class="stat-label">Added</div>
+            </div>
+            <div class="stat removed">
+                <div class="stat-value">#REMOVED#</div>
+                <div class="stat-label">Removed</div>
+            </div>
+            <div class="stat unchanged">
+                <div class="stat-value">#UNCHANGED#</div>
+                <div class="stat-label">Unchanged</div>
+            </div>
+        </div>
+"#,
+        );
+
+        for (class, heading, entries) in [
+            ("added", "Added", &self.added),
+            ("removed", "Removed", &self.removed),
+            ("unchanged", "Unchanged", &self.unchanged),
+        ] {
+            html.push_str(&format!(
+                r#"        <h2 class="{}">{}</h2>
+        <table id="{}">
+            <thead>
+                <tr>
+                    <th>Site</th>
+                    <th>Profile URL</th>
+                </tr>
+            </thead>
+            <tbody>
+"#,
+                class, heading, class
+            ));
+            for (site, url) in entries {
+                html.push_str(&format!(
+                    r#"                <tr class="{}">
+                    <td>{}</td>
+                    <td><a href="{}" class="site-link" target="_blank">{}</a></td>
+                </tr>
+"#,
+                    class,
+                    escape_html(site),
+                    escape_html(url),
+                    escape_html(url)
+                ));
+            }
+            html.push_str(
+                r#"            </tbody>
+        </table>
+"#,
+            );
+        }
+
+        html.push_str(
+            r#"    </div>
+</body>
+</html>
+"#,
+        );
+
+        html = html.replace("#TITLE#", &escape_html(&self.title()));
+        html = html.replace("#ADDED#", &self.added.len().to_string());
+        html = html.replace("#REMOVED#", &self.removed.len().to_string());
+        html = html.replace("#UNCHANGED#", &self.unchanged.len().to_string());
+
+        html
+    }
+}
+
+/// Site columns shown in a heatmap; the most-claimed sites win when there are more.
+pub const HEATMAP_MAX_SITES: usize = 50;
+
+/// Where each searched username (typically `--variations`) is claimed, rendered
+/// as a usernames × sites grid.
+#[derive(Default)]
+pub struct VariationHeatmap {
+    /// Each username with its claimed `site_name -> profile_url`, in search order.
+    rows: Vec<(String, BTreeMap<String, String>)>,
+}
+
+impl VariationHeatmap {
+    pub fn add(&mut self, report: &SearchReport) {
+        let claimed = report
+            .results
+            .iter()
+            .filter(|r| r.is_claimed())
+            .map(|r| (r.site_name.clone(), r.profile_url.clone()))
+            .collect();
+        self.rows.push((report.username.clone(), claimed));
+    }
+
+    /// Sites claimed by at least one row, most-claimed first then by name,
+    /// capped at `HEATMAP_MAX_SITES`.
+    pub fn sites(&self) -> Vec<&str> {
+        let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+        for (_, claimed) in &self.rows {
+            for site in claimed.keys() {
+                *counts.entry(site).or_default() += 1;
+            }
+        }
+        let mut sites: Vec<(&str, usize)> = counts.into_iter().collect();
+        sites.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        sites.into_iter().take(HEATMAP_MAX_SITES).map(|(site, _)| site).collect()
+    }
+
+    /// Standalone page with one row per username and one cell per site; claimed
+    /// cells are colored and link to the profile.
+    pub fn to_html(&self) -> String {
+        let sites = self.sites();
+        let mut html = String::from(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="UTF-8">
+    <title>Watson Variation Heatmap</title>
+    <style>
+        body { font-family: Arial, sans-serif; margin: 20px; background: #f5f5f5; }
+        .container { background: white; padding: 20px; border-radius: 8px; box-shadow: 0 2px 4px rgba(0,0,0,0.1); overflow-x: auto; }
+        h1 { color: #333; border-bottom: 2px solid #007bff; padding-bottom: 10px; }
+        table { border-collapse: collapse; margin-top: 20px; }
+        th, td { padding: 6px; border: 1px solid #ddd; text-align: center; }
+        th { background: #007bff; color: white; font-size: 12px; }
+        th.site { writing-mode: vertical-rl; transform: rotate(180deg); }
+        th.username, td.count { text-align: left; }
+        td.claimed { background: #28a745; }
+        td.claimed a { display: block; color: white; text-decoration: none; }
+        td.empty { background: #f8f9fa; }
+    </style>
+</head>
+<body>
+    <div class="container">
+        <h1>Watson Variation Heatmap</h1>
+        <p><strong>#SUMMARY#</strong></p>
+        <table id="heatmap">
+            <thead>
+                <tr>
+                    <th class="username">Username</th>
+                    <th>Claimed</th>
+"#,
+        );
+        for site in &sites {
+            html.push_str(&format!("                    <th class=\"site\">{}</th>\n", escape_html(site)));
+        }
+        html.push_str(
+            r#"                </tr>
+            </thead>
+            <tbody>
+"#,
+        );
+
+        for (username, claimed) in &self.rows {
+            html.push_str(&format!(
+                "                <tr>\n                    <th class=\"username\">{}</th>\n                    <td class=\"count\">{}</td>\n",
+                escape_html(username),
+                claimed.len()
+            ));
+            for site in &sites {
+                match claimed.get(*site) {
+                    Some(url) => html.push_str(&format!(
+                        "                    <td class=\"claimed\" title=\"{} on {}\"><a href=\"{}\" target=\"_blank\">&#9679;</a></td>\n",
+                        escape_html(username),
+                        escape_html(site),
+                        escape_html(url)
+                    )),
+                    None => html.push_str(&format!(
+                        "                    <td class=\"empty\" title=\"{} on {}\"></td>\n",
+                        escape_html(username),
+                        escape_html(site)
+                    )),
+                }
+            }
+            html.push_str("                </tr>\n");
+        }
+
+        html.push_str(
+            r#"            </tbody>
+        </table>
+    </div>
+</body>
+</html>
+"#,
+        );
+
+        html.replace(
+            "#SUMMARY#",
+            &format!("{} usernames × {} sites with a claimed profile", self.rows.len(), sites.len()),
+        )
+    }
+}
+
+#[cfg(feature = "email")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RiskLevel {
+    /// Not found on any service
+    #[serde(rename = "low")]
+    Low,
+    /// Found on services but no known breaches
+    #[serde(rename = "medium")]
+    Medium,
+    /// Appears in at least one breach
+    #[serde(rename = "high")]
+    High,
+}
+
+#[cfg(feature = "email")]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EmailReport {
+    pub email: String,
+    pub total_services: usize,
+    pub found_count: usize,
+    pub error_count: usize,
+    /// Service name -> number of breaches reported by it
+    pub breach_counts: BTreeMap<String, usize>,
+    pub total_breaches: usize,
+    pub risk: RiskLevel,
+    /// e.g. "found on Gravatar, 3 breaches on HaveIBeenPwned"
+    pub summary: String,
+    pub results: Vec<QueryResult>,
+    pub tor_used: bool,
+}
+
+#[cfg(feature = "email")]
+impl EmailReport {
+    pub fn new(
+        email: String,
+        mut results: Vec<QueryResult>,
+        breach_counts: BTreeMap<String, usize>,
+        tor_used: bool,
+    ) -> Self {
+        results.sort_by(|a, b| a.site_name.cmp(&b.site_name));
+
+        let found_count = results.iter().filter(|r| r.is_claimed()).count();
+        let error_count = results
+            .iter()
+            .filter(|r| r.status == QueryStatus::Error)
+            .count();
+        let total_breaches = breach_counts.values().sum();
+
+        let risk = if total_breaches > 0 {
+            RiskLevel::High
+        } else if found_count > 0 {
+            RiskLevel::Medium
+        } else {
+            RiskLevel::Low
+        };
+
+        let found_on: Vec<&str> = results
+            .iter()
+            .filter(|r| r.is_claimed() && !breach_counts.contains_key(&r.site_name))
+            .map(|r| r.site_name.as_str())
+            .collect();
+        let mut parts = vec![];
+        if !found_on.is_empty() {
+            parts.push(format!("found on {}", found_on.join(", ")));
+        }
+        for (service, count) in &breach_counts {
+            parts.push(format!(
+                "{} breach{} on {}",
+                count,
+                if *count == 1 { "" } else { "es" },
+                service
+            ));
+        }
+        let summary = if parts.is_empty() {
+            "not found on any service".to_string()
+        } else {
+            parts.join(", ")
+        };
+
+        Self {
+            email,
+            total_services: results.len(),
+            found_count,
+            error_count,
+            breach_counts,
+            total_breaches,
+            risk,
+            summary,
+            results,
+            tor_used,
+        }
+    }
+
+    pub fn from_msgpack(bytes: &[u8]) -> Result<Self, rmp_serde::decode::Error> {
+        rmp_serde::from_slice(bytes)
+    }
+
+    fn risk_label(&self) -> String {
+        format!("{:?}", self.risk).to_lowercase()
+    }
+}
+
+#[cfg(feature = "email")]
+impl Report for EmailReport {
+    fn to_text(&self) -> String {
+        let mut output = format!("\n=== Watson Email Results for '{}' ===\n", self.email);
+        output.push_str(&format!("Services checked: {}\n", self.total_services));
+        output.push_str(&format!("Found on: {} services\n", self.found_count));
+        output.push_str(&format!("Breaches: {}\n", self.total_breaches));
+        output.push_str(&format!("Errors: {}\n", self.error_count));
+        output.push_str(&format!("Risk: {}\n", self.risk_label()));
+
+        if self.tor_used {
+            output.push_str("Using Tor: Yes\n");
+        }
+
+        output.push_str(&format!("\nSummary: {}\n", self.summary));
+
+        output.push_str("\n--- Services ---\n");
+        for result in &self.results {
+            let marker = if result.is_claimed() { "[+]" } else { "[-]" };
+            match self.breach_counts.get(&result.site_name) {
+                Some(count) => output.push_str(&format!(
+                    "{} {}: {} breaches\n",
+                    marker, result.site_name, count
+                )),
+                None => output.push_str(&format!(
+                    "{} {}: {}\n",
+                    marker, result.site_name, result.profile_url
+                )),
+            }
+        }
+
+        output
+    }
+
+    fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string_pretty(self)
+    }
+
+    fn to_json_compact(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    fn to_msgpack(&self) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        rmp_serde::to_vec_named(self)
+    }
+
+    fn to_maltego(&self) -> Result<String, csv::Error> {
+        maltego_csv("maltego.EmailAddress", &self.email, &self.results)
+    }
+
+    fn to_bookmarks(&self) -> String {
+        netscape_bookmarks(&self.email, &self.results)
+    }
+
+    fn to_urls(&self) -> String {
+        claimed_urls(&self.results)
+    }
+
+    fn to_csv(&self) -> String {
+        let mut output = String::from("service,url,status,http_status,breaches\n");
+
+        for result in &self.results {
+            output.push_str(&format!(
+                "{},{},{},{},{}\n",
+                result.site_name,
+                result.profile_url,
+                format!("{:?}", result.status).to_lowercase(),
+                result
+                    .http_status
+                    .map(|s| s.to_string())
+                    .unwrap_or_default(),
+                self.breach_counts
+                    .get(&result.site_name)
+                    .map(|c| c.to_string())
+                    .unwrap_or_default(),
+            ));
+        }
+
+        output
+    }
+
+    fn to_html(&self, _show_all: bool) -> String {
+        let mut html = String::from(
+            r#"<!DOCTYPE html>
+<html>
+<head>
+    <meta charset="UTF-8">
+    <title>Watson Email Report</title>
+    <style>
+        body { font-family: Arial, sans-serif; margin: 20px; background: #f5f5f5; }
+        .container { max-width: 900px; margin: 0 auto; background: white; padding: 20px; border-radius: 8px; box-shadow: 0 2px 4px rgba(0,0,0,0.1); }
+        h1 { color: #333; border-bottom: 2px solid #007bff; padding-bottom: 10px; }
+        .summary { padding: 15px; border-radius: 5px; background: #f8f9fa; margin: 20px 0; }
+        .risk-low { color: #28a745; font-weight: bold; }
+        .risk-medium { color: #ffc107; font-weight: bold; }
+        .risk-high { color: #dc3545; font-weight: bold; }
+        table { width: 100%; border-collapse: collapse; margin-top: 20px; }
+        th, td { padding: 12px; text-align: left; border-bottom: 1px solid #ddd; }
+        th { background: #007bff; color: white; }
+        .claimed { color: #28a745; font-weight: bold; }
+        .available { color: #dc3545; }
+        .error { color: #ffc107; }
+    </style>
+</head>
+<body>
+    <div class="container">
+        <h1>Watson Email Report</h1>
+        <p><strong>Email:</strong> #EMAIL#</p>
+        <div class="summary">
+            <p><strong>Risk:</strong> <span class="risk-#RISK#">#RISK#</span></p>
+            <p>#SUMMARY#</p>
+        </div>
+        <table>
+            <thead>
+                <tr>
+                    <th>Service</th>
+                    <th>Status</th>
+                    <th>HTTP Status</th>
+                    <th>Breaches</th>
+                </tr>
+            </thead>
+            <tbody>
+"#,
+        );
+
+        for result in &self.results {
+            let status_class = match result.status {
+                QueryStatus::Claimed => "claimed",
+                QueryStatus::Available => "available",
+                QueryStatus::Error | QueryStatus::TimedOut => "error",
+                _ => "",
+            };
+
+            html.push_str(&format!(
+                r#"                <tr>
+                    <td>{}</td>
+                    <td class="{}">{:?}</td>
+                    <td>{}</td>
+                    <td>{}</td>
+                </tr>
+"#,
+                escape_html(&result.site_name),
+                status_class,
+                result.status,
+                result
+                    .http_status
+                    .map(|s| s.to_string())
+                    .unwrap_or_else(|| "N/A".to_string()),
+                self.breach_counts
+                    .get(&result.site_name)
+                    .map(|c| c.to_string())
+                    .unwrap_or_else(|| "-".to_string()),
+            ));
+        }
+
+        html.push_str(
+            r#"            </tbody>
+        </table>
+    </div>
+</body>
+</html>
+"#,
+        );
+
+        html = html.replace("#EMAIL#", &escape_html(&self.email));
+        html = html.replace("#RISK#", &self.risk_label());
+        html = html.replace("#SUMMARY#", &escape_html(&self.summary));
+
+        html
+    }
+
+    fn to_term(&self, width: usize) -> String {
+        let mut output = truncate_to_width(
+            &format!("{}  risk={}  {}", self.email, self.risk_label(), self.summary),
+            width,
+        );
+        output.push('\n');
+        output
+    }
+}
+
+/// `field` RFC 4180 quoted if it contains a comma, quote or newline.
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Maltego CSV import layout, one row per claimed result:
+///
+/// `<entity>,maltego.Website,maltego.URL,maltego.link.label`
+///
+/// The first column holds the searched username/email, linked to the site's host
+/// (Website) and the profile (URL); the link label is the site name. Fields are
+/// RFC 4180 quoted when they contain commas, quotes or newlines.
+fn maltego_csv(entity_type: &str, entity: &str, results: &[QueryResult]) -> Result<String, csv::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record([entity_type, "maltego.Website", "maltego.URL", "maltego.link.label"])?;
+
+    for result in results.iter().filter(|r| r.is_claimed()) {
+        let website = url::Url::parse(&result.site_url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+            .unwrap_or_else(|| result.site_url.clone());
+        writer.write_record([entity, &website, &result.profile_url, &result.site_name])?;
+    }
+
+    let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+    Ok(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Claimed profiles as a Netscape bookmark file (the format browsers import and
+/// export), all inside one folder named `folder`:
+///
+/// ```text
+/// <DL><p>
+///     <DT><H3>folder</H3>
+///     <DL><p>
+///         <DT><A HREF="profile_url">site_name</A>
+///     </DL><p>
+/// </DL><p>
+/// ```
+fn netscape_bookmarks(folder: &str, results: &[QueryResult]) -> String {
+    let mut output = String::from(
+        "<!DOCTYPE NETSCAPE-Bookmark-file-1>\n\
+         <META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">\n\
+         <TITLE>Bookmarks</TITLE>\n\
+         <H1>Bookmarks</H1>\n\
+         <DL><p>\n",
+    );
+    output.push_str(&format!("    <DT><H3>{}</H3>\n    <DL><p>\n", escape_html(folder)));
+    for result in results.iter().filter(|r| r.is_claimed()) {
+        output.push_str(&format!(
+            "        <DT><A HREF=\"{}\">{}</A>\n",
+            escape_html(&result.profile_url),
+            escape_html(&result.site_name)
+        ));
+    }
+    output.push_str("    </DL><p>\n</DL><p>\n");
+    output
+}
+
+/// Profile URL of every claimed result, one per line, with no header.
+fn claimed_urls(results: &[QueryResult]) -> String {
+    results
+        .iter()
+        .filter(|r| r.is_claimed())
+        .map(|r| format!("{}\n", r.profile_url))
+        .collect()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn truncate_to_width(line: &str, width: usize) -> String {
+    if line.chars().count() <= width {
+        return line.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+    let mut truncated: String = line.chars().take(width - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+/// Syntax-highlights pretty-printed JSON for a terminal: keys blue, strings green,
+/// numbers cyan, booleans yellow, null dimmed. Honours `colored`'s global override,
+/// so with color off the input comes back unchanged.
+pub fn colorize_json(json: &str) -> String {
+    use colored::Colorize;
+
+    let mut output = String::with_capacity(json.len());
+    let mut chars = json.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        match c {
+            '"' => {
+                let mut end = json.len();
+                let mut escaped = false;
+                for (i, c) in chars.by_ref() {
+                    if escaped {
+                        escaped = false;
+                    } else if c == '\\' {
+                        escaped = true;
+                    } else if c == '"' {
+                        end = i + 1;
+                        break;
+                    }
+                }
+                let token = &json[start..end];
+                let is_key = json[end..].trim_start().starts_with(':');
+                let colored = if is_key { token.blue().bold() } else { token.green() };
+                output.push_str(&colored.to_string());
+            }
+            '-' | '0'..='9' | 't' | 'f' | 'n' => {
+                let mut end = start + c.len_utf8();
+                while let Some(&(i, c)) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || matches!(c, '.' | '+' | '-') {
+                        end = i + c.len_utf8();
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                let token = &json[start..end];
+                let colored = match token {
+                    "true" | "false" => token.yellow(),
+                    "null" => token.dimmed(),
+                    _ => token.cyan(),
+                };
+                output.push_str(&colored.to_string());
+            }
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_escapes_final_url() {
+        let mut result = QueryResult::claimed("alice", "Example", "https://example.com/", "https://example.com/alice");
+        result.final_url = Some("https://example.com/\"><script>alert(1)</script>".to_string());
+        let html = SearchReport::new("alice".to_string(), vec![result], false).to_html(true);
+        assert!(!html.contains("<script>alert(1)"));
+        assert!(html.contains("https://example.com/&quot;&gt;&lt;script&gt;alert(1)&lt;/script&gt;"));
+
+        // The username reaches the heading, the site name and both halves of the profile link
+        let hostile = "<script>alert(2)</script>";
+        let result = QueryResult::claimed(hostile, "<b>Site</b>", "https://example.com/", &format!("https://example.com/{}", hostile));
+        let html = SearchReport::new(hostile.to_string(), vec![result], false).to_html(true);
+        assert!(!html.contains("<script>alert(2)"));
+        assert!(!html.contains("<b>Site</b>"));
+        assert!(html.contains("<strong>Username:</strong> &lt;script&gt;alert(2)&lt;/script&gt;"));
+        assert!(html.contains(
+            r#"<a href="https://example.com/&lt;script&gt;alert(2)&lt;/script&gt;" class="site-link" target="_blank">https://example.com/&lt;script&gt;alert(2)&lt;/script&gt;</a>"#
+        ));
+    }
+
+    #[test]
+    fn html_paginates_and_collapses_non_claimed_rows() {
+        let results = vec![
+            QueryResult::available("alice", "A", "https://a.example/", "https://a.example/alice"),
+            QueryResult::claimed("alice", "B", "https://b.example/", "https://b.example/alice"),
+            QueryResult::available("alice", "C", "https://c.example/", "https://c.example/alice"),
+            QueryResult::claimed("alice", "D", "https://d.example/", "https://d.example/alice"),
+        ];
+        let report = SearchReport::new("alice".to_string(), results, false);
+        let html = report.to_html(false);
+
+        assert!(html.contains("var PAGE_SIZE = 50;"));
+        for id in ["toggle-others", "prev-page", "page-info", "next-page"] {
+            assert!(html.contains(&format!("<button id=\"{}\"", id)) || html.contains(&format!("<span id=\"{}\"", id)), "{}", id);
+        }
+        // Claimed rows come first; the rest are tagged for the script to hide
+        let flags: Vec<&str> = html.split("<tr data-claimed=\"").skip(1).map(|rest| rest.split('"').next().unwrap()).collect();
+        assert_eq!(flags, ["true", "true", "false", "false"]);
+        let sites: Vec<usize> = ["<td>B</td>", "<td>D</td>", "<td>A</td>", "<td>C</td>"].iter().map(|s| html.find(s).unwrap()).collect();
+        assert!(sites.windows(2).all(|w| w[0] < w[1]));
+
+        assert!(html.contains("var showAll = false;"));
+        assert!(report.to_html(true).contains("var showAll = true;"));
+        assert!(!html.contains("#SHOW_ALL#"));
+    }
+
+    #[test]
+    fn csv_quotes_final_url() {
+        let mut result = QueryResult::claimed("alice", "Example", "https://example.com/", "https://example.com/alice");
+        result.final_url = Some("https://example.com/login?next=alice,\"x\"".to_string());
+        let csv = SearchReport::new("alice".to_string(), vec![result], false).to_csv();
+        let row = csv.lines().nth(1).unwrap();
+        assert!(row.contains(",\"https://example.com/login?next=alice,\"\"x\"\"\","));
+        let columns = csv::Reader::from_reader(csv.as_bytes()).records().next().unwrap().unwrap().len();
+        assert_eq!(columns, 9);
+    }
+
+    #[test]
+    fn html_escapes_category() {
+        let mut result = QueryResult::claimed("alice", "Example", "https://example.com/", "https://example.com/alice");
+        result.category = Some("<img src=x onerror=alert(1)>".to_string());
+        let html = SearchReport::new("alice".to_string(), vec![result], false).to_html(true);
+        assert!(!html.contains("<img src=x"));
+        assert!(html.contains("&lt;img src=x onerror=alert(1)&gt;"));
+    }
+
+    #[test]
+    fn diff_html_escapes_username_site_and_url() {
+        let username = "a\"><svg/onload=alert(1)>";
+        let old = SearchReport::new(username.to_string(), vec![], false);
+        let new = SearchReport::new(
+            username.to_string(),
+            vec![QueryResult::claimed(
+                username,
+                "<b>Example</b>",
+                "https://example.com/",
+                "https://example.com/a\"><svg/onload=alert(1)>",
+            )],
+            false,
+        );
+        let html = ReportDiff::new(&old, &new).to_html();
+        assert!(!html.contains("<svg"));
+        assert!(!html.contains("<b>Example"));
+        assert!(html.contains("Changes for 'a&quot;&gt;&lt;svg/onload=alert(1)&gt;'"));
+        assert!(html.contains("href=\"https://example.com/a&quot;&gt;&lt;svg/onload=alert(1)&gt;\""));
+    }
+
+    #[test]
+    fn diff_html_puts_each_change_in_its_table() {
+        let claimed = |site: &str| QueryResult::claimed("alice", site, "https://example.com/", &format!("https://{}.example/alice", site));
+        let old = SearchReport::new("alice".to_string(), vec![claimed("Gone"), claimed("Kept")], false);
+        let new = SearchReport::new("alice".to_string(), vec![claimed("Kept"), claimed("New")], false);
+        let html = ReportDiff::new(&old, &new).to_html();
+
+        let table = |id: &str| {
+            let start = html.find(&format!("<table id=\"{}\">", id)).unwrap();
+            let end = start + html[start..].find("</table>").unwrap();
+            html[start..end].to_string()
+        };
+        for (id, site) in [("added", "New"), ("removed", "Gone"), ("unchanged", "Kept")] {
+            let rows = table(id);
+            assert_eq!(rows.matches("<tr class=").count(), 1, "{}", id);
+            assert!(rows.contains(&format!("<tr class=\"{}\">", id)));
+            assert!(rows.contains(&format!("<td>{}</td>", site)));
+            assert!(rows.contains(&format!("href=\"https://{}.example/alice\"", site)));
+        }
+    }
+
+    #[test]
+    fn heatmap_has_a_cell_per_username_and_site() {
+        let report = |username: &str, sites: &[&str]| {
+            let results = sites
+                .iter()
+                .map(|site| QueryResult::claimed(username, site, "https://example.com/", &format!("https://{}.example/{}", site, username)))
+                .collect();
+            SearchReport::new(username.to_string(), results, false)
+        };
+        let mut heatmap = VariationHeatmap::default();
+        heatmap.add(&report("alice", &["A", "B"]));
+        heatmap.add(&report("alicia", &["A"]));
+        let html = heatmap.to_html();
+
+        assert_eq!(heatmap.sites(), ["A", "B"]);
+        assert_eq!(html.matches("<th class=\"site\">").count(), 2);
+        assert_eq!(html.matches("<td class=\"claimed\"").count() + html.matches("<td class=\"empty\"").count(), 4);
+        for (username, site) in [("alice", "A"), ("alice", "B"), ("alicia", "A")] {
+            assert!(html.contains(&format!(
+                "<td class=\"claimed\" title=\"{} on {}\"><a href=\"https://{}.example/{}\"",
+                username, site, site, username
+            )));
+        }
+        assert!(html.contains("<td class=\"empty\" title=\"alicia on B\"></td>"));
+
+        // Past the cap, the sites claimed most often keep their columns
+        let many: Vec<String> = (0..HEATMAP_MAX_SITES + 10).map(|i| format!("Site{:02}", i)).collect();
+        let mut heatmap = VariationHeatmap::default();
+        heatmap.add(&report("alice", &many.iter().map(String::as_str).collect::<Vec<_>>()));
+        heatmap.add(&report("alicia", &["Site59"]));
+        let sites = heatmap.sites();
+        assert_eq!(sites.len(), HEATMAP_MAX_SITES);
+        assert_eq!(sites[0], "Site59");
+        let html = heatmap.to_html();
+        assert_eq!(html.matches("<th class=\"site\">").count(), HEATMAP_MAX_SITES);
+        assert_eq!(html.matches("<td class=\"claimed\"").count(), HEATMAP_MAX_SITES + 1);
+    }
+
+    #[cfg(feature = "email")]
+    #[test]
+    fn email_html_escapes_address_site_and_summary() {
+        let email = "a\"><svg/onload=alert(1)>@example.com";
+        let results = vec![QueryResult::claimed(email, "<b>Gravatar</b>", "https://gravatar.com/", "https://gravatar.com/")];
+        let html = EmailReport::new(email.to_string(), results, BTreeMap::new(), false).to_html(true);
+        assert!(!html.contains("<svg"));
+        assert!(!html.contains("<b>Gravatar"));
+        assert!(html.contains("a&quot;&gt;&lt;svg/onload=alert(1)&gt;@example.com"));
+        assert!(html.contains("found on &lt;b&gt;Gravatar&lt;/b&gt;"));
+    }
+
+    #[cfg(feature = "email")]
+    #[test]
+    fn email_report_rates_risk_and_summarizes_breaches() {
+        let email = "alice@example.com";
+        let results = vec![
+            QueryResult::claimed(email, "HaveIBeenPwned", "https://haveibeenpwned.com", "https://haveibeenpwned.com/alice"),
+            QueryResult::available(email, "DuckDuckGo", "https://duckduckgo.com", "https://duckduckgo.com/alice"),
+            QueryResult::claimed(email, "Gravatar", "https://gravatar.com", "https://gravatar.com/alice"),
+            QueryResult::error(email, "Broken", "https://broken.example", "https://broken.example/alice", "timeout"),
+        ];
+        let breaches = BTreeMap::from([("HaveIBeenPwned".to_string(), 3)]);
+        let report = EmailReport::new(email.to_string(), results.clone(), breaches, false);
+        assert_eq!(report.risk, RiskLevel::High);
+        assert_eq!((report.total_services, report.found_count, report.error_count), (4, 2, 1));
+        assert_eq!(report.total_breaches, 3);
+        assert_eq!(report.summary, "found on Gravatar, 3 breaches on HaveIBeenPwned");
+
+        let report = EmailReport::new(email.to_string(), results, BTreeMap::new(), false);
+        assert_eq!(report.risk, RiskLevel::Medium);
+        assert_eq!(report.summary, "found on Gravatar, HaveIBeenPwned");
+
+        let missing = vec![QueryResult::available(email, "Gravatar", "https://gravatar.com", "https://gravatar.com/alice")];
+        let report = EmailReport::new(email.to_string(), missing, BTreeMap::new(), false);
+        assert_eq!((report.risk, report.found_count), (RiskLevel::Low, 0));
+        assert_eq!(report.summary, "not found on any service");
+    }
+
+    #[test]
+    fn top_fastest_shows_only_the_fastest_claimed_sites() {
+        let timed = |site: &str, ms: Option<u64>| {
+            let mut result = QueryResult::claimed("alice", site, "https://example.com/", &format!("https://{}.example/alice", site));
+            result.response_time_ms = ms;
+            result
+        };
+        let results = vec![
+            timed("Slow", Some(900)),
+            timed("Fast", Some(40)),
+            timed("Untimed", None),
+            timed("Medium", Some(300)),
+            QueryResult::available("alice", "Free", "https://example.com/", "https://free.example/alice"),
+        ];
+        let report = SearchReport::new("alice".to_string(), results, false).with_top_fastest(2);
+
+        let shown: Vec<String> = report.shown_results().iter().map(|r| r.site_name.clone()).collect();
+        assert_eq!(shown, ["Fast", "Medium", "Free"]);
+        assert_eq!(report.to_urls(), "https://Fast.example/alice\nhttps://Medium.example/alice\n");
+
+        // Counts still describe the whole scan
+        assert_eq!((report.total_sites, report.claimed_count, report.available_count), (5, 4, 1));
+        assert_eq!(report.results.len(), 5);
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(json["results"].as_array().unwrap().len(), 3);
+        assert_eq!(json["claimed_count"], 4);
+    }
+
+    #[test]
+    fn maltego_has_the_header_and_a_quoted_row_per_claim() {
+        let results = vec![
+            QueryResult::claimed("alice", "Acme, Inc", "https://acme.example/", "https://acme.example/u/alice"),
+            QueryResult::available("alice", "Free", "https://free.example/", "https://free.example/alice"),
+        ];
+        let csv = SearchReport::new("alice".to_string(), results, false).to_maltego().unwrap();
+        assert_eq!(
+            csv,
+            "maltego.Alias,maltego.Website,maltego.URL,maltego.link.label\n\
+             alice,acme.example,https://acme.example/u/alice,\"Acme, Inc\"\n"
+        );
+    }
+
+    #[test]
+    fn bookmarks_list_each_claimed_profile() {
+        let results = vec![
+            QueryResult::claimed("alice", "Q&A", "https://qa.example/", "https://qa.example/?user=alice&tab=1"),
+            QueryResult::claimed("alice", "GitHub", "https://github.com/", "https://github.com/alice"),
+            QueryResult::available("alice", "Free", "https://free.example/", "https://free.example/alice"),
+        ];
+        let bookmarks = SearchReport::new("alice".to_string(), results, false).to_bookmarks();
+        assert!(bookmarks.starts_with("<!DOCTYPE NETSCAPE-Bookmark-file-1>\n"));
+        assert!(bookmarks.contains("    <DT><H3>alice</H3>\n"));
+        let entries: Vec<&str> = bookmarks.lines().filter(|l| l.trim_start().starts_with("<DT><A ")).map(str::trim).collect();
+        assert_eq!(
+            entries,
+            [
+                r#"<DT><A HREF="https://github.com/alice">GitHub</A>"#,
+                r#"<DT><A HREF="https://qa.example/?user=alice&amp;tab=1">Q&amp;A</A>"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn urls_are_the_claimed_profiles_one_per_line() {
+        let results = vec![
+            QueryResult::claimed("alice", "GitHub", "https://github.com/", "https://github.com/alice"),
+            QueryResult::available("alice", "Free", "https://free.example/", "https://free.example/alice"),
+            QueryResult::error("alice", "Broken", "https://broken.example/", "https://broken.example/alice", "refused"),
+            QueryResult::claimed("alice", "Acme", "https://acme.example/", "https://acme.example/u/alice"),
+        ];
+        let urls = SearchReport::new("alice".to_string(), results, false).to_urls();
+        assert_eq!(urls, "https://acme.example/u/alice\nhttps://github.com/alice\n");
+    }
+
+    #[test]
+    fn counts_add_up_to_the_total() {
+        let results = vec![
+            QueryResult::claimed("alice", "A", "https://a.example/", "https://a.example/alice"),
+            QueryResult::available("alice", "B", "https://b.example/", "https://b.example/alice"),
+            QueryResult::error("alice", "C", "https://c.example/", "https://c.example/alice", "refused"),
+            QueryResult::unknown("alice", "D", "https://d.example/", "https://d.example/alice", "rate limited"),
+            QueryResult::timed_out("alice", "E", "https://e.example/", "https://e.example/alice", 500),
+            QueryResult::illegal("alice", "F", "https://f.example/"),
+        ];
+        let report = SearchReport::new("alice".to_string(), results, false);
+        assert_eq!(report.total_sites, 6);
+        assert_eq!(
+            (report.claimed_count, report.available_count, report.error_count, report.unknown_count, report.skipped.len()),
+            (1, 1, 1, 2, 1)
+        );
+        assert_eq!(
+            report.claimed_count + report.available_count + report.error_count + report.unknown_count + report.skipped.len(),
+            report.total_sites
+        );
+    }
+
+    #[test]
+    fn compact_json_is_one_line_with_the_same_value() {
+        let results = vec![
+            QueryResult::claimed("alice", "GitHub", "https://github.com/", "https://github.com/alice"),
+            QueryResult::available("alice", "Free", "https://free.example/", "https://free.example/alice"),
+        ];
+        let report = SearchReport::new("alice".to_string(), results, false);
+        let compact = report.to_json_compact().unwrap();
+        assert!(!compact.contains('\n'));
+        assert!(!compact.contains(": "));
+        let pretty: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        assert_eq!(serde_json::from_str::<serde_json::Value>(&compact).unwrap(), pretty);
+    }
+
+    #[test]
+    fn metadata_is_serialized_with_the_report() {
+        let metadata = ScanMetadata::new("data.json", "1a2b3c4d", 20)
+            .with_tor(true)
+            .with_proxies(vec!["http://proxy.example:8080".to_string()])
+            .with_sites_considered(42);
+        let report = SearchReport::new("alice".to_string(), vec![], true).with_metadata(metadata);
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        let meta = &json["metadata"];
+        assert_eq!(meta["watson_version"], env!("CARGO_PKG_VERSION"));
+        assert!(meta["started_at"].as_str().unwrap().parse::<DateTime<Utc>>().is_ok());
+        assert_eq!(meta["site_data_source"], "data.json");
+        assert_eq!(meta["site_data_hash"], "1a2b3c4d");
+        assert_eq!(meta["max_concurrent"], 20);
+        assert_eq!(meta["tor_used"], true);
+        assert_eq!(meta["proxies"], serde_json::json!(["http://proxy.example:8080"]));
+        assert_eq!(meta["sites_considered"], 42);
+    }
+
+    #[test]
+    fn detection_breakdown_covers_every_checked_site() {
+        let with_method = |mut result: QueryResult, method: Option<crate::data::ErrorType>| {
+            result.detection = method;
+            result
+        };
+        let results = vec![
+            with_method(QueryResult::claimed("alice", "A", "https://a.example/", "https://a.example/alice"), Some(crate::data::ErrorType::StatusCode)),
+            with_method(QueryResult::available("alice", "B", "https://b.example/", "https://b.example/alice"), Some(crate::data::ErrorType::StatusCode)),
+            with_method(QueryResult::claimed("alice", "C", "https://c.example/", "https://c.example/alice"), Some(crate::data::ErrorType::Message)),
+            with_method(QueryResult::error("alice", "D", "https://d.example/", "https://d.example/alice", "refused"), None),
+        ];
+        let report = SearchReport::new("alice".to_string(), results, false);
+        let methods = &report.detection_methods;
+        assert_eq!(methods.keys().collect::<Vec<_>>(), ["message", "status_code", "unrecorded"]);
+        assert_eq!((methods["status_code"].checked, methods["status_code"].claimed), (2, 1));
+        assert_eq!(methods["status_code"].claimed_rate, 0.5);
+        assert_eq!((methods["message"].checked, methods["message"].claimed), (1, 1));
+        assert_eq!((methods["unrecorded"].checked, methods["unrecorded"].claimed), (1, 0));
+        assert_eq!(methods.values().map(|s| s.checked).sum::<usize>(), report.total_sites);
+        assert_eq!(methods.values().map(|s| s.claimed).sum::<usize>(), report.claimed_count);
+    }
+
+    #[test]
+    fn shared_emails_are_listed_once_with_every_site() {
+        let found = vec![
+            ("GitLab".to_string(), "https://gitlab.com/alice".to_string(), Some(vec!["alice@example.com".to_string()])),
+            (
+                "GitHub".to_string(),
+                "https://github.com/alice".to_string(),
+                Some(vec!["alice@example.com".to_string(), "a@work.example".to_string()]),
+            ),
+            ("Blog".to_string(), "https://blog.example/alice".to_string(), None),
+        ];
+        assert_eq!(
+            dedupe_emails(&found),
+            [
+                ScrapedEmail { email: "a@work.example".to_string(), sites: vec!["GitHub".to_string()] },
+                ScrapedEmail {
+                    email: "alice@example.com".to_string(),
+                    sites: vec!["GitHub".to_string(), "GitLab".to_string()],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn skipped_sites_carry_their_reason() {
+        let results = vec![
+            QueryResult::claimed("alice", "Found", "https://found.example/", "https://found.example/alice"),
+            QueryResult::illegal("alice", "Strict", "https://strict.example/"),
+        ];
+        let sites = ["Found", "Strict", "Filtered", "Unreached"].map(String::from);
+        let report = SearchReport::new("alice".to_string(), results, false)
+            .with_skipped(vec![SkippedSite::new("Filtered", SkipReason::Excluded)])
+            .with_capped(&sites);
+
+        let reasons: Vec<(&str, SkipReason)> = report.skipped.iter().map(|s| (s.site_name.as_str(), s.reason)).collect();
+        assert_eq!(
+            reasons,
+            [("Filtered", SkipReason::Excluded), ("Strict", SkipReason::Regex), ("Unreached", SkipReason::Capped)]
+        );
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json().unwrap()).unwrap();
+        let json_reasons: Vec<&str> = json["skipped"].as_array().unwrap().iter().map(|s| s["reason"].as_str().unwrap()).collect();
+        assert_eq!(json_reasons, ["excluded", "regex", "capped"]);
+        assert!(report.to_html(true).contains("capped"));
+    }
+
+    #[test]
+    fn json_is_identical_whatever_order_results_arrive_in() {
+        let results = || {
+            let mut timed = QueryResult::claimed("alice", "GitHub", "https://github.com/", "https://github.com/alice");
+            timed.response_time_ms = Some(120);
+            vec![
+                timed,
+                QueryResult::available("alice", "Reddit", "https://reddit.com/", "https://reddit.com/user/alice"),
+                QueryResult::error("alice", "Dead", "https://dead.example/", "https://dead.example/alice", "connection refused"),
+                QueryResult::illegal("alice", "Strict", "https://strict.example/"),
+                QueryResult::claimed("alice", "Mastodon", "https://mastodon.social/", "https://mastodon.social/@alice"),
+            ]
+        };
+        let mut shuffled = results();
+        shuffled.reverse();
+        shuffled.swap(0, 2);
+
+        let first = SearchReport::new("alice".to_string(), results(), false).to_json().unwrap();
+        let second = SearchReport::new("alice".to_string(), shuffled, false).to_json().unwrap();
+        assert_eq!(first, second);
+        assert!(first.find("\"Dead\"").unwrap() < first.find("\"GitHub\"").unwrap());
+    }
+
+    #[test]
+    fn msgpack_round_trips_the_report() {
+        let mut timed = QueryResult::claimed("alice", "GitHub", "https://github.com/", "https://github.com/alice");
+        timed.response_time_ms = Some(120);
+        let report = SearchReport::new(
+            "alice".to_string(),
+            vec![
+                timed,
+                QueryResult::available("alice", "Reddit", "https://reddit.com/", "https://reddit.com/user/alice"),
+                QueryResult::error("alice", "Dead", "https://dead.example/", "https://dead.example/alice", "connection refused"),
+            ],
+            false,
+        )
+        .with_skipped(vec![SkippedSite::new("Adult", SkipReason::Nsfw)]);
+
+        let read = SearchReport::from_msgpack(&report.to_msgpack().unwrap()).unwrap();
+        assert_eq!(read.to_json().unwrap(), report.to_json().unwrap());
+    }
+
+    /// Records what it's given, in order.
+    #[derive(Default)]
+    struct VecSink(Vec<String>);
+
+    impl OutputSink for VecSink {
+        fn write_result(&mut self, result: &QueryResult) -> Result<(), Box<dyn std::error::Error>> {
+            self.0.push(result.site_name.clone());
+            Ok(())
+        }
+
+        fn finish(&mut self, report: &SearchReport) -> Result<(), Box<dyn std::error::Error>> {
+            self.0.push(format!("finish {}", report.username));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sink_sees_each_result_then_the_report() {
+        let report = SearchReport::new(
+            "alice".to_string(),
+            vec![
+                QueryResult::claimed("alice", "GitHub", "https://github.com/", "https://github.com/alice"),
+                QueryResult::available("alice", "Reddit", "https://reddit.com/", "https://reddit.com/user/alice"),
+                QueryResult::error("alice", "Dead", "https://dead.example/", "https://dead.example/alice", "timed out"),
+            ],
+            false,
+        );
+        let mut sink = VecSink::default();
+        report.write_to(&mut sink).unwrap();
+
+        let mut expected: Vec<String> = report.results.iter().map(|r| r.site_name.clone()).collect();
+        expected.push("finish alice".to_string());
+        assert_eq!(sink.0, expected);
+    }
+
+    #[test]
+    fn term_lines_fit_the_width() {
+        let long_url = format!("https://example.com/{}", "a".repeat(200));
+        let results = vec![
+            QueryResult::claimed("alice", "Example", "https://example.com/", &long_url),
+            QueryResult::claimed("alice", "Short", "https://s.example/", "https://s.example/alice"),
+            QueryResult::available("alice", "Missing", "https://m.example/", "https://m.example/alice"),
+        ];
+        let term = SearchReport::new("alice".to_string(), results, false).to_term(60);
+        let lines: Vec<&str> = term.lines().collect();
+        assert_eq!(lines.len(), 2, "only claimed sites are listed");
+        assert!(lines.iter().all(|line| line.chars().count() <= 60), "{:?}", lines);
+        assert!(lines[0].starts_with("Example  claimed"));
+        assert!(lines[1].ends_with("https://s.example/alice"));
+    }
+}
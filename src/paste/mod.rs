@@ -0,0 +1,64 @@
+use crate::http::{read_body_text, HttpClient, RequestOptions};
+
+/// Uploads `body` to a paste service and returns the URL of the new paste.
+/// The service may answer with the bare URL or with JSON carrying `url`/`link`.
+pub async fn upload(client: &HttpClient, paste_url: &str, body: String) -> Result<String, Box<dyn std::error::Error>> {
+    let response = client.post(paste_url, Some(body), &RequestOptions::default()).await?;
+    let status = response.status();
+    let text = read_body_text(response).await?;
+    if !status.is_success() {
+        return Err(format!("paste service returned HTTP {}", status).into());
+    }
+    parse_paste_url(&text).ok_or_else(|| "paste service response did not contain a URL".into())
+}
+
+fn parse_paste_url(body: &str) -> Option<String> {
+    let body = body.trim();
+    if body.starts_with("http://") || body.starts_with("https://") {
+        return Some(body.to_string());
+    }
+    let value: serde_json::Value = serde_json::from_str(body).ok()?;
+    ["url", "link"]
+        .iter()
+        .find_map(|key| value.get(key).and_then(|v| v.as_str()).map(|s| s.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{path_of, serve};
+
+    fn paste_service(request: &str) -> String {
+        let body = match (request.starts_with("POST "), path_of(request)) {
+            (false, _) => return "HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+            (true, "/bare") => "https://paste.example/abc\n",
+            (true, "/json") => r#"{"key":"abc","link":"https://paste.example/abc"}"#,
+            (true, "/junk") => "stored!",
+            _ => return "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+        };
+        format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+    }
+
+    #[tokio::test]
+    async fn upload_reads_a_bare_or_json_url() {
+        let addr = serve(paste_service).await;
+        let client = HttpClient::new(5, false).unwrap();
+        let upload_to = |path: &str| {
+            let url = format!("http://{}{}", addr, path);
+            let client = &client;
+            async move { upload(client, &url, "{}".to_string()).await }
+        };
+
+        assert_eq!(upload_to("/bare").await.unwrap(), "https://paste.example/abc");
+        assert_eq!(upload_to("/json").await.unwrap(), "https://paste.example/abc");
+        assert_eq!(upload_to("/junk").await.unwrap_err().to_string(), "paste service response did not contain a URL");
+        assert_eq!(upload_to("/down").await.unwrap_err().to_string(), "paste service returned HTTP 500 Internal Server Error");
+    }
+
+    #[test]
+    fn paste_url_prefers_url_over_link() {
+        assert_eq!(parse_paste_url(r#"{"url":"https://a.example/1","link":"https://b.example/1"}"#).as_deref(), Some("https://a.example/1"));
+        assert_eq!(parse_paste_url(r#"{"id":"1"}"#), None);
+        assert_eq!(parse_paste_url("  http://paste.example/x  ").as_deref(), Some("http://paste.example/x"));
+    }
+}
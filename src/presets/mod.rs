@@ -0,0 +1,40 @@
+use std::collections::BTreeMap;
+
+/// Curated site groups shipped with the binary.
+const BUILTIN_PRESETS: &str = include_str!("../../data/presets.json");
+
+/// Preset name -> site names, e.g. `"dev": ["GitHub", "GitLab"]`.
+pub type Presets = BTreeMap<String, Vec<String>>;
+
+/// Loads the built-in presets, then merges in user presets from `path`
+/// (same format), letting user entries override built-ins of the same name.
+pub fn load_presets(path: Option<&str>) -> Result<Presets, Box<dyn std::error::Error>> {
+    let mut presets: Presets = serde_json::from_str(BUILTIN_PRESETS)?;
+
+    if let Some(path) = path {
+        let content = std::fs::read_to_string(path)?;
+        let user: Presets = serde_json::from_str(&content)?;
+        presets.extend(user.into_iter().map(|(name, sites)| (name.to_lowercase(), sites)));
+    }
+
+    Ok(presets)
+}
+
+/// The first preset (by name) that lists `site_name`, used as its category when
+/// the site data carries no tags.
+pub fn preset_for_site<'a>(presets: &'a Presets, site_name: &str) -> Option<&'a str> {
+    presets
+        .iter()
+        .find(|(_, sites)| sites.iter().any(|s| s.eq_ignore_ascii_case(site_name)))
+        .map(|(name, _)| name.as_str())
+}
+
+/// Returns true if `site_name` belongs to any of the named presets.
+pub fn preset_contains(presets: &Presets, names: &[String], site_name: &str) -> bool {
+    names.iter().any(|name| {
+        presets
+            .get(&name.to_lowercase())
+            .map(|sites| sites.iter().any(|s| s.eq_ignore_ascii_case(site_name)))
+            .unwrap_or(false)
+    })
+}
@@ -1,44 +1,141 @@
+use dashmap::DashMap;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::sync::RwLock;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 
+/// Spaces requests to the same domain `delay_ms` apart. Each domain's next slot
+/// is an atomic reserved with a compare-and-swap, and the map of domains is
+/// sharded, so callers for unrelated domains don't wait on each other; the
+/// sleep happens after the slot is taken.
 pub struct RateLimiter {
-    delays: HashMap<String, Instant>,
-    delay_ms: u64,
+    /// Earliest start of the next request to each domain, as nanoseconds since `epoch`.
+    next_slots: DashMap<String, AtomicU64>,
+    epoch: Instant,
+    delay: Duration,
 }
 
 impl RateLimiter {
     pub fn new(delay_ms: u64) -> Self {
         Self {
-            delays: HashMap::new(),
-            delay_ms,
+            next_slots: DashMap::new(),
+            epoch: Instant::now(),
+            delay: Duration::from_millis(delay_ms),
         }
     }
 
-    pub async fn wait_for(&mut self, domain: &str) {
-        if self.delay_ms == 0 {
+    pub async fn wait_for(&self, domain: &str) {
+        if self.delay.is_zero() {
             return;
         }
+        tokio::time::sleep_until(self.reserve(domain).into()).await;
+    }
 
-        let now = Instant::now();
-        
-        if let Some(last_request) = self.delays.get(domain) {
-            let elapsed = now.duration_since(*last_request);
-            let delay = Duration::from_millis(self.delay_ms);
-            
-            if elapsed < delay {
-                let sleep_time = delay - elapsed;
-                tokio::time::sleep(sleep_time).await;
-            }
-        }
-        
-        self.delays.insert(domain.to_string(), Instant::now());
+    /// Takes the next free slot for `domain` and returns when it starts.
+    fn reserve(&self, domain: &str) -> Instant {
+        let now = self.epoch.elapsed().as_nanos() as u64;
+        let delay = self.delay.as_nanos() as u64;
+        let take = |next: &AtomicU64| {
+            let previous = next
+                .fetch_update(Ordering::AcqRel, Ordering::Acquire, |next| Some(next.max(now) + delay))
+                .unwrap();
+            previous.max(now)
+        };
+        // The common case only takes a shard's read lock
+        let slot = match self.next_slots.get(domain) {
+            Some(next) => take(&next),
+            None => take(&self.next_slots.entry(domain.to_string()).or_insert_with(|| AtomicU64::new(0))),
+        };
+        self.epoch + Duration::from_nanos(slot)
     }
 }
 
-pub type RateLimiterHandle = Arc<RwLock<RateLimiter>>;
+pub type RateLimiterHandle = Arc<RateLimiter>;
 
 pub fn create_rate_limiter(delay_ms: u64) -> RateLimiterHandle {
-    Arc::new(RwLock::new(RateLimiter::new(delay_ms)))
+    Arc::new(RateLimiter::new(delay_ms))
+}
+
+/// Caps in-flight requests per host. Owned by the engine, so the cap holds across
+/// every username searched in a run rather than resetting per search.
+pub struct HostLimiter {
+    per_host: usize,
+    hosts: std::sync::Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+impl HostLimiter {
+    pub fn new(per_host: usize) -> Self {
+        Self {
+            per_host: per_host.max(1),
+            hosts: std::sync::Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn acquire(&self, host: &str) -> OwnedSemaphorePermit {
+        let semaphore = self
+            .hosts
+            .lock()
+            .unwrap()
+            .entry(host.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.per_host)))
+            .clone();
+        semaphore.acquire_owned().await.unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_domain_slots_are_spaced_by_the_delay() {
+        let limiter = RateLimiter::new(1000);
+        let first = limiter.reserve("example.com");
+        assert_eq!(limiter.reserve("example.com") - first, Duration::from_millis(1000));
+        assert_eq!(limiter.reserve("example.com") - first, Duration::from_millis(2000));
+    }
+
+    #[test]
+    fn other_domains_are_not_delayed() {
+        let limiter = RateLimiter::new(1000);
+        for _ in 0..5 {
+            limiter.reserve("busy.example");
+        }
+        assert!(limiter.reserve("idle.example") < Instant::now() + Duration::from_millis(500));
+    }
+
+    #[test]
+    fn concurrent_reservations_never_share_a_slot() {
+        let limiter = Arc::new(RateLimiter::new(1));
+        let threads: Vec<_> = (0..8)
+            .map(|_| {
+                let limiter = limiter.clone();
+                std::thread::spawn(move || (0..200).map(|_| limiter.reserve("example.com")).collect::<Vec<_>>())
+            })
+            .collect();
+        let mut slots: Vec<Instant> = threads.into_iter().flat_map(|t| t.join().unwrap()).collect();
+        slots.sort();
+        assert!(slots.windows(2).all(|w| w[1] - w[0] >= Duration::from_millis(1)));
+    }
+
+    /// A reservation never waits on another one in progress, for its own domain
+    /// or any other: holding a slot open doesn't block the rest.
+    #[test]
+    fn reservations_do_not_wait_on_a_held_slot() {
+        let limiter = Arc::new(RateLimiter::new(1000));
+        let first_a = limiter.reserve("a.example");
+        let first_b = limiter.reserve("b.example");
+        // Stands in for a reservation of a.example that hasn't finished yet
+        let held = limiter.next_slots.get("a.example").unwrap();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let other = limiter.clone();
+        std::thread::spawn(move || {
+            let _ = tx.send((other.reserve("b.example"), other.reserve("a.example")));
+        });
+        let (b, a) = rx.recv_timeout(Duration::from_secs(5)).expect("reservation waited on a held slot");
+        drop(held);
+        assert_eq!(b - first_b, Duration::from_millis(1000));
+        assert_eq!(a - first_a, Duration::from_millis(1000));
+    }
 }
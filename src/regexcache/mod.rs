@@ -0,0 +1,68 @@
+use lru::LruCache;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+
+const CACHE_CAPACITY: usize = 256;
+
+static REGEX_CACHE: Lazy<Mutex<RegexCache>> = Lazy::new(|| Mutex::new(RegexCache::new(CACHE_CAPACITY)));
+
+/// Compiled regexes by pattern, least recently used evicted first.
+struct RegexCache {
+    entries: LruCache<String, Regex>,
+    /// Patterns compiled so far, i.e. cache misses that compiled.
+    #[cfg_attr(not(test), allow(dead_code))]
+    compiled: usize,
+}
+
+impl RegexCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            entries: LruCache::new(NonZeroUsize::new(capacity).unwrap()),
+            compiled: 0,
+        }
+    }
+
+    fn get(&mut self, pattern: &str) -> Option<Regex> {
+        if let Some(re) = self.entries.get(pattern) {
+            return Some(re.clone());
+        }
+
+        let re = Regex::new(pattern).ok()?;
+        self.compiled += 1;
+        self.entries.put(pattern.to_string(), re.clone());
+        Some(re)
+    }
+}
+
+/// Returns a compiled regex for `pattern`, compiling it at most once per process
+/// while it stays in the cache. Invalid patterns return `None` and are not cached.
+pub fn get_regex(pattern: &str) -> Option<Regex> {
+    REGEX_CACHE.lock().ok()?.get(pattern)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_pattern_compiles_once() {
+        let mut cache = RegexCache::new(2);
+        for _ in 0..3 {
+            assert!(cache.get("^[a-z]+$").unwrap().is_match("alice"));
+        }
+        assert_eq!(cache.compiled, 1);
+
+        // Invalid patterns aren't cached, so each call tries again
+        assert!(cache.get("(").is_none());
+        assert!(cache.get("(").is_none());
+        assert_eq!(cache.compiled, 1);
+
+        // Past capacity the least recently used pattern is compiled again
+        cache.get("a");
+        cache.get("b");
+        cache.get("^[a-z]+$");
+        assert_eq!(cache.compiled, 4);
+    }
+}
@@ -0,0 +1,256 @@
+use crate::data::SiteInfo;
+use crate::engine::{classify_response, CheckConfig, ProbeResponse, QueryResult};
+use crate::http::decode_body;
+use crate::regexcache::get_regex;
+use base64::Engine as _;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// Redirect hops followed through a capture, matching the live client.
+const MAX_REDIRECTS: usize = 10;
+
+/// The parts of a HAR 1.2 file replay needs; everything else is ignored.
+#[derive(Debug, Deserialize)]
+pub struct Har {
+    log: HarLog,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarLog {
+    entries: Vec<HarEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarEntry {
+    request: HarRequest,
+    response: HarResponse,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarRequest {
+    url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarResponse {
+    /// 0 when the browser got no response (blocked, aborted or offline).
+    status: u16,
+    #[serde(default)]
+    headers: Vec<HarHeader>,
+    #[serde(default)]
+    content: HarContent,
+    #[serde(rename = "redirectURL", default)]
+    redirect_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarHeader {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct HarContent {
+    text: Option<String>,
+    encoding: Option<String>,
+    #[serde(rename = "mimeType")]
+    mime_type: Option<String>,
+}
+
+impl HarResponse {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|h| h.name.eq_ignore_ascii_case(name))
+            .map(|h| h.value.as_str())
+    }
+
+    /// The recorded body, base64-decoded and charset-decoded as a live response would be.
+    fn body(&self) -> Option<String> {
+        let text = self.content.text.as_deref()?;
+        if self.content.encoding.as_deref() != Some("base64") {
+            return Some(text.to_string());
+        }
+        let bytes = base64::engine::general_purpose::STANDARD.decode(text).ok()?;
+        let content_type = self.header("content-type").or(self.content.mime_type.as_deref());
+        Some(decode_body(&bytes, content_type))
+    }
+}
+
+impl Har {
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Follows recorded redirects from entry `start` to the response the client
+    /// would have ended on. Stops at a hop the capture doesn't contain.
+    fn final_entry(&self, start: usize) -> &HarEntry {
+        let mut entry = &self.log.entries[start];
+        for _ in 0..MAX_REDIRECTS {
+            if !(300..400).contains(&entry.response.status) || entry.response.redirect_url.is_empty() {
+                break;
+            }
+            let target = match url::Url::parse(&entry.request.url).and_then(|base| base.join(&entry.response.redirect_url)) {
+                Ok(target) => target.to_string(),
+                Err(_) => break,
+            };
+            match self.log.entries.iter().find(|e| e.request.url == target) {
+                Some(next) => entry = next,
+                None => break,
+            }
+        }
+        entry
+    }
+}
+
+/// Anchored regex matching the URLs `pattern` produces, capturing the username.
+fn url_regex(pattern: &str) -> Option<regex::Regex> {
+    let escaped = regex::escape(pattern).replace(r"\{\}", "([^/?#&]+)");
+    get_regex(&format!("^{}$", escaped))
+}
+
+/// The username a site's probe would have used to request `url`, if any. Names
+/// the site's `regexCheck` rejects are never requested live, so don't count.
+fn username_in(site_info: &SiteInfo, url: &str) -> Option<String> {
+    let patterns = match site_info.url_probe {
+        Some(ref probe) => probe.patterns(),
+        None => vec![site_info.url.as_str()],
+    };
+    let username = patterns.into_iter().find_map(|pattern| {
+        let captured = url_regex(pattern)?.captures(url)?.get(1)?.as_str().to_string();
+        Some(percent_encoding::percent_decode_str(&captured).decode_utf8_lossy().into_owned())
+    })?;
+    match site_info.regex_check.as_deref().and_then(get_regex) {
+        Some(re) if !re.is_match(&username) => None,
+        _ => Some(username),
+    }
+}
+
+/// Classifies every request in `har` that one of `sites` would have sent, using
+/// the recorded responses instead of the network. Each site is judged on the
+/// first matching request per username, and only `only_username` when given.
+/// Results are grouped by username.
+pub fn replay(
+    har: &Har,
+    sites: &HashMap<String, SiteInfo>,
+    config: &CheckConfig,
+    only_username: Option<&str>,
+) -> BTreeMap<String, Vec<QueryResult>> {
+    let mut site_names: Vec<&String> = sites.keys().collect();
+    site_names.sort();
+
+    let mut by_username: BTreeMap<String, Vec<QueryResult>> = BTreeMap::new();
+    for site_name in site_names {
+        let site_info = &sites[site_name];
+        let mut seen = Vec::new();
+        for (index, entry) in har.log.entries.iter().enumerate() {
+            let Some(username) = username_in(site_info, &entry.request.url) else { continue };
+            if seen.contains(&username) || only_username.is_some_and(|only| only != username) {
+                continue;
+            }
+            let profile_url = site_info.url.replace("{}", &username);
+            let last = har.final_entry(index);
+            let result = if last.response.status == 0 {
+                QueryResult::error(&username, site_name, &site_info.url_main, &profile_url, "no response recorded")
+            } else {
+                let body = last.response.body();
+                let response = ProbeResponse {
+                    status: last.response.status,
+                    final_url: &last.request.url,
+                    retry_after: last.response.header("retry-after"),
+                    body: body.as_deref(),
+                };
+                classify_response(&username, site_name, site_info, &profile_url, &response, config)
+            };
+            let result = QueryResult {
+                detection: Some(site_info.error_type.clone()),
+                ..result
+            };
+            by_username.entry(username.clone()).or_default().push(result);
+            seen.push(username);
+        }
+    }
+    by_username
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::QueryStatus;
+
+    fn har() -> Har {
+        let entry = |url: &str, response: serde_json::Value| serde_json::json!({ "request": { "url": url }, "response": response });
+        serde_json::from_value(serde_json::json!({
+            "log": {
+                "entries": [
+                    entry("https://status.example/alice", serde_json::json!({ "status": 200 })),
+                    entry("https://status.example/bob", serde_json::json!({ "status": 404 })),
+                    entry("https://message.example/u/alice", serde_json::json!({
+                        "status": 302,
+                        "redirectURL": "/notfound",
+                    })),
+                    entry("https://message.example/notfound", serde_json::json!({
+                        "status": 200,
+                        // "<p>No such user</p>"
+                        "content": { "text": "PHA+Tm8gc3VjaCB1c2VyPC9wPg==", "encoding": "base64", "mimeType": "text/html" },
+                    })),
+                    entry("https://message.example/u/bob", serde_json::json!({
+                        "status": 200,
+                        "content": { "text": "<p>bob's profile</p>" },
+                    })),
+                    entry("https://blocked.example/alice", serde_json::json!({ "status": 0 })),
+                ]
+            }
+        }))
+        .unwrap()
+    }
+
+    fn sites() -> HashMap<String, SiteInfo> {
+        let site = |json: serde_json::Value| serde_json::from_value(json).unwrap();
+        HashMap::from([
+            (
+                "Status".to_string(),
+                site(serde_json::json!({
+                    "url": "https://status.example/{}",
+                    "urlMain": "https://status.example/",
+                    "errorType": "status_code",
+                })),
+            ),
+            (
+                "Message".to_string(),
+                site(serde_json::json!({
+                    "url": "https://message.example/u/{}",
+                    "urlMain": "https://message.example/",
+                    "errorType": "message",
+                    "errorMsg": "No such user",
+                })),
+            ),
+            (
+                "Blocked".to_string(),
+                site(serde_json::json!({
+                    "url": "https://blocked.example/{}",
+                    "urlMain": "https://blocked.example/",
+                    "errorType": "status_code",
+                })),
+            ),
+        ])
+    }
+
+    fn statuses(results: &[QueryResult]) -> Vec<(&str, QueryStatus)> {
+        results.iter().map(|r| (r.site_name.as_str(), r.status)).collect()
+    }
+
+    #[test]
+    fn replays_recorded_responses() {
+        let results = replay(&har(), &sites(), &CheckConfig::default(), None);
+        assert_eq!(results.keys().collect::<Vec<_>>(), ["alice", "bob"]);
+        assert_eq!(
+            statuses(&results["alice"]),
+            [("Blocked", QueryStatus::Error), ("Message", QueryStatus::Available), ("Status", QueryStatus::Claimed)]
+        );
+        assert_eq!(statuses(&results["bob"]), [("Message", QueryStatus::Claimed), ("Status", QueryStatus::Available)]);
+
+        let only_bob = replay(&har(), &sites(), &CheckConfig::default(), Some("bob"));
+        assert_eq!(only_bob.keys().collect::<Vec<_>>(), ["bob"]);
+    }
+}
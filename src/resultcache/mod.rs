@@ -0,0 +1,96 @@
+use crate::engine::{QueryResult, QueryStatus};
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct CachedResult {
+    cached_at: DateTime<Utc>,
+    result: QueryResult,
+}
+
+/// Last conclusive result per (username, site), persisted between runs so
+/// repeated monitoring of a handle doesn't re-probe every site each time.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ResultCache {
+    /// username -> site name -> cached result
+    entries: BTreeMap<String, BTreeMap<String, CachedResult>>,
+}
+
+impl ResultCache {
+    /// Reads the cache at `path`; a missing file is an empty cache.
+    pub fn load(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        match std::fs::read(path) {
+            Ok(bytes) => Ok(serde_json::from_slice(&bytes)?),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    /// The cached result for `site` if it was stored less than `ttl_secs` ago,
+    /// marked as served from cache.
+    pub fn fresh(&self, username: &str, site: &str, ttl_secs: u64) -> Option<QueryResult> {
+        let cached = self.entries.get(username)?.get(site)?;
+        if Utc::now() - cached.cached_at >= Duration::seconds(ttl_secs as i64) {
+            return None;
+        }
+        Some(QueryResult {
+            from_cache: true,
+            ..cached.result.clone()
+        })
+    }
+
+    /// Remembers claimed/available results; errors and other inconclusive
+    /// outcomes are always re-checked on the next run.
+    pub fn store(&mut self, results: &[QueryResult]) {
+        let now = Utc::now();
+        for result in results {
+            if !matches!(result.status, QueryStatus::Claimed | QueryStatus::Available) {
+                continue;
+            }
+            self.entries
+                .entry(result.username.clone())
+                .or_default()
+                .insert(
+                    result.site_name.clone(),
+                    CachedResult {
+                        cached_at: now,
+                        result: result.clone(),
+                    },
+                );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn results_are_fresh_until_the_ttl_passes() {
+        let mut cache = ResultCache::default();
+        cache.store(&[
+            QueryResult::claimed("alice", "GitHub", "https://github.com/", "https://github.com/alice"),
+            QueryResult::error("alice", "Dead", "https://dead.example/", "https://dead.example/alice", "timed out"),
+        ]);
+
+        let hit = cache.fresh("alice", "GitHub", 3600).unwrap();
+        assert_eq!(hit.status, QueryStatus::Claimed);
+        assert!(hit.from_cache);
+        assert!(cache.fresh("bob", "GitHub", 3600).is_none());
+        // Errors are never cached
+        assert!(cache.fresh("alice", "Dead", 3600).is_none());
+
+        // Stored two hours ago: fresh for a day, expired for an hour
+        let entry = cache.entries.get_mut("alice").unwrap().get_mut("GitHub").unwrap();
+        entry.cached_at = Utc::now() - Duration::hours(2);
+        assert!(cache.fresh("alice", "GitHub", 86400).is_some());
+        assert!(cache.fresh("alice", "GitHub", 3600).is_none());
+    }
+}
@@ -1,64 +1,81 @@
-use crate::ua::UserAgentRotator;
-use regex::Regex;
-use reqwest::{Client, ClientBuilder};
+use crate::http::{is_public_ip, read_body_limited, HttpClient, RequestOptions};
+use crate::regexcache::get_regex;
+use reqwest::Url;
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::RwLock;
+use tracing::info;
+
+/// Profile pages are read up to this size; the rest is ignored.
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
 
 pub struct EmailScraper {
-    client: Client,
-    ua_rotator: Arc<RwLock<UserAgentRotator>>,
-    rotate_ua: bool,
+    http_client: HttpClient,
+    allow_domains: Arc<Vec<String>>,
 }
 
-impl EmailScraper {
-    pub fn new(timeout: u64, rotate_ua: bool) -> Result<Self, reqwest::Error> {
-        let ua_rotator = Arc::new(RwLock::new(UserAgentRotator::new()));
-        
-        let default_ua = if rotate_ua {
-            ua_rotator.blocking_read().get_random()
-        } else {
-            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36".to_string()
-        };
-
-        let client = ClientBuilder::new()
-            .timeout(Duration::from_secs(timeout))
-            .connect_timeout(Duration::from_secs(5))
-            .user_agent(&default_ua)
-            .build()?;
+/// Whether `host` is one of `domains` or a subdomain of one.
+fn domain_allowed(host: &str, domains: &[String]) -> bool {
+    domains.iter().any(|domain| {
+        let domain = domain.trim_start_matches('.').to_lowercase();
+        host == domain || host.ends_with(&format!(".{}", domain))
+    })
+}
 
-        Ok(Self {
-            client,
-            ua_rotator,
-            rotate_ua,
-        })
+/// Whether `url` may be fetched at all: not `localhost` and not a loopback,
+/// private or link-local IP literal. Hostnames that resolve to such addresses
+/// are refused by the client's resolver instead.
+fn host_is_public(url: &Url) -> bool {
+    match url.host() {
+        Some(url::Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_lowercase();
+            domain != "localhost" && !domain.ends_with(".localhost")
+        }
+        Some(url::Host::Ipv4(ip)) => is_public_ip(ip.into()),
+        Some(url::Host::Ipv6(ip)) => is_public_ip(ip.into()),
+        None => false,
     }
+}
 
-    async fn get_user_agent(&self) -> String {
-        if self.rotate_ua {
-            self.ua_rotator.read().await.get_random()
-        } else {
-            "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36".to_string()
-        }
+/// Redirects may stay on the profile's own host or move to an allowed domain,
+/// and never to a non-public address; anything else stops and the redirect
+/// response itself is returned.
+fn redirect_allowed(url: &Url, origin: Option<&Url>, allow_domains: &[String]) -> bool {
+    let Some(host) = url.host_str() else { return false };
+    host_is_public(url) && (origin.and_then(|o| o.host_str()) == Some(host) || domain_allowed(host, allow_domains))
+}
+
+impl EmailScraper {
+    /// Fetches through `http_client`, so scraping uses the same proxy, Tor and
+    /// user agent settings as the search. With `allow_domains` empty only each
+    /// profile's own host is fetched. Loopback, private and link-local addresses
+    /// are never fetched, whatever a profile links or redirects to.
+    pub fn new(http_client: HttpClient, allow_domains: Vec<String>) -> Result<Self, reqwest::Error> {
+        let allow_domains = Arc::new(allow_domains);
+        let redirect_domains = allow_domains.clone();
+        let http_client = http_client
+            .with_public_only()?
+            .with_redirect_filter(Arc::new(move |url, origin| redirect_allowed(url, origin, &redirect_domains)))?;
+        Ok(Self { http_client, allow_domains })
     }
 
     pub async fn scrape_profile(&self, url: &str) -> Option<Vec<String>> {
-        let ua = self.get_user_agent().await;
-        
-        let custom_client = ClientBuilder::new()
-            .timeout(Duration::from_secs(15))
-            .connect_timeout(Duration::from_secs(5))
-            .user_agent(&ua)
-            .build()
-            .ok()?;
+        let parsed = Url::parse(url).ok()?;
+        let host = parsed.host_str()?.to_string();
+        if !host_is_public(&parsed) {
+            info!("Not scraping {}: {} is not a public address", url, host);
+            return None;
+        }
+        if !self.allow_domains.is_empty() && !domain_allowed(&host, &self.allow_domains) {
+            info!("Not scraping {}: {} is not in --scrape-allow-domains", url, host);
+            return None;
+        }
 
-        let response = custom_client.get(url).send().await.ok()?;
+        let response = self.http_client.get(url, &RequestOptions::default()).await.ok()?;
 
         if !response.status().is_success() {
             return None;
         }
 
-        let text = response.text().await.ok()?;
+        let text = read_body_limited(response, MAX_BODY_BYTES).await.ok()?;
         let emails = self.extract_emails(&text);
 
         if emails.is_empty() {
@@ -69,11 +86,9 @@ impl EmailScraper {
     }
 
     fn extract_emails(&self, text: &str) -> Vec<String> {
-        let email_regex = match Regex::new(
-            r"[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}"
-        ) {
-            Ok(r) => r,
-            Err(_) => return vec![],
+        let email_regex = match get_regex(r"[a-zA-Z0-9._%+-]+@[a-zA-Z0-9.-]+\.[a-zA-Z]{2,}") {
+            Some(r) => r,
+            None => return vec![],
         };
 
         let mut emails: Vec<String> = email_regex
@@ -87,31 +102,26 @@ impl EmailScraper {
     }
 }
 
+/// Scrapes every profile for emails through `http_client`, at most `max_concurrent` at a time.
 pub async fn scrape_emails_from_results(
     profile_urls: Vec<(String, String)>,
-    timeout: u64,
-    rotate_ua: bool,
+    http_client: HttpClient,
+    max_concurrent: usize,
+    allow_domains: Vec<String>,
 ) -> Vec<(String, String, Option<Vec<String>>)> {
     use tokio::sync::Semaphore;
-    use std::sync::Arc;
 
-    let scraper = match EmailScraper::new(timeout, rotate_ua) {
-        Ok(s) => s,
+    let scraper = match EmailScraper::new(http_client, allow_domains) {
+        Ok(scraper) => Arc::new(scraper),
         Err(_) => return vec![],
     };
 
-    let semaphore = Arc::new(Semaphore::new(10));
+    let semaphore = Arc::new(Semaphore::new(max_concurrent));
     let mut handles = vec![];
 
     for (site_name, profile_url) in profile_urls {
         let permit = semaphore.clone().acquire_owned().await.unwrap();
-        let scraper = EmailScraper::new(timeout, rotate_ua).ok();
-
-        if scraper.is_none() {
-            continue;
-        }
-
-        let scraper = scraper.unwrap();
+        let scraper = scraper.clone();
         let handle = tokio::spawn(async move {
             let emails = scraper.scrape_profile(&profile_url).await;
             drop(permit);
@@ -129,3 +139,136 @@ pub async fn scrape_emails_from_results(
 
     results
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{path_of, serve};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn redirects_to_internal_addresses_are_refused() {
+        let origin = url("https://example.com/alice");
+        let allow = vec!["example.com".to_string()];
+        for target in ["http://127.0.0.1/", "http://169.254.169.254/latest/meta-data/", "http://localhost:8080/", "http://[::1]/"] {
+            assert!(!redirect_allowed(&url(target), Some(&origin), &allow), "{}", target);
+        }
+        assert!(redirect_allowed(&url("https://example.com/alice/"), Some(&origin), &[]));
+        assert!(!redirect_allowed(&url("https://elsewhere.net/"), Some(&origin), &[]));
+        assert!(redirect_allowed(&url("https://www.example.com/"), Some(&origin), &allow));
+    }
+
+    static LOOPBACK_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+
+    #[tokio::test]
+    async fn loopback_profiles_are_never_fetched() {
+        let addr = serve(|_| {
+            LOOPBACK_REQUESTS.fetch_add(1, Ordering::SeqCst);
+            "HTTP/1.1 200 OK\r\nContent-Length: 17\r\nConnection: close\r\n\r\nme@example.com   ".to_string()
+        })
+        .await;
+        let scraper = EmailScraper::new(HttpClient::new(5, false).unwrap(), vec![]).unwrap();
+        assert_eq!(scraper.scrape_profile(&format!("http://{}/alice", addr)).await, None);
+        assert_eq!(LOOPBACK_REQUESTS.load(Ordering::SeqCst), 0);
+    }
+
+    /// What a forward proxy gets for each profile: `/hop` redirects off the
+    /// profile's domain, anything else is a page with an address on its host.
+    fn profile_page(request: &str) -> String {
+        let url = Url::parse(path_of(request)).unwrap();
+        if url.path() == "/hop" {
+            return "HTTP/1.1 302 Found\r\nLocation: http://elsewhere.example/alice\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                .to_string();
+        }
+        let body = format!("<a href=\"mailto:alice@{}\">mail</a>", url.host_str().unwrap());
+        format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body)
+    }
+
+    static ALLOWLIST_REQUESTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    #[tokio::test]
+    async fn urls_off_the_allowlist_are_skipped() {
+        let proxy = serve(|request| {
+            ALLOWLIST_REQUESTS.lock().unwrap().push(path_of(request).to_string());
+            profile_page(request)
+        })
+        .await;
+        let client = HttpClient::new(5, false).unwrap().with_proxy(format!("http://{}", proxy)).unwrap();
+        let scraper = EmailScraper::new(client, vec!["allowed.example".to_string()]).unwrap();
+
+        assert_eq!(
+            scraper.scrape_profile("http://www.allowed.example/alice").await,
+            Some(vec!["alice@www.allowed.example".to_string()])
+        );
+        assert_eq!(scraper.scrape_profile("http://elsewhere.example/alice").await, None);
+        // The redirect off the allowlist isn't followed
+        assert_eq!(scraper.scrape_profile("http://allowed.example/hop").await, None);
+        assert_eq!(
+            *ALLOWLIST_REQUESTS.lock().unwrap(),
+            ["http://www.allowed.example/alice", "http://allowed.example/hop"]
+        );
+    }
+
+    #[tokio::test]
+    async fn at_most_max_concurrent_profiles_are_fetched_at_once() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // A proxy that holds each page briefly, tracking how many are in flight
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy = listener.local_addr().unwrap();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (counting, peaked) = (in_flight.clone(), peak.clone());
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let (in_flight, peak) = (counting.clone(), peaked.clone());
+                tokio::spawn(async move {
+                    let mut buf = vec![0; 4096];
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    let response = profile_page(&String::from_utf8_lossy(&buf[..n]));
+                    let _ = socket.write_all(response.as_bytes()).await;
+                });
+            }
+        });
+
+        let client = HttpClient::new(5, false).unwrap().with_proxy(format!("http://{}", proxy)).unwrap();
+        let profiles: Vec<(String, String)> =
+            (0..6).map(|i| (format!("Site{}", i), format!("http://site{}.example/alice", i))).collect();
+        let results = scrape_emails_from_results(profiles, client, 2, vec![]).await;
+
+        assert_eq!(results.len(), 6);
+        assert!(results.iter().all(|(_, _, emails)| emails.as_ref().is_some_and(|e| e.len() == 1)));
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+    }
+
+    static SHARED_CLIENT_REQUESTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    #[tokio::test]
+    async fn shared_client_routes_through_its_proxy() {
+        let proxy = serve(|request| {
+            SHARED_CLIENT_REQUESTS.lock().unwrap().push(request.lines().next().unwrap_or_default().to_string());
+            profile_page(request)
+        })
+        .await;
+        let engine = crate::engine::SearchEngine::new(5, 1, false, false)
+            .unwrap()
+            .with_proxy(format!("http://{}", proxy))
+            .unwrap();
+        let scraper = EmailScraper::new(engine.http_client().clone(), vec![]).unwrap();
+
+        assert_eq!(
+            scraper.scrape_profile("http://profiles.example/alice").await,
+            Some(vec!["alice@profiles.example".to_string()])
+        );
+        assert_eq!(*SHARED_CLIENT_REQUESTS.lock().unwrap(), ["GET http://profiles.example/alice HTTP/1.1"]);
+    }
+}
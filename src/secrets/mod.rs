@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+
+/// Environment variable read for `--hibp-api-key` when the flag is omitted.
+pub const HIBP_KEY_ENV: &str = "WATSON_HIBP_KEY";
+/// Environment variable read for `--proxy-pass` when the flag is omitted.
+pub const PROXY_PASS_ENV: &str = "WATSON_PROXY_PASS";
+
+/// Credentials that shouldn't have to appear on the command line. Each is taken
+/// from its flag, else its environment variable, else the `--secrets-file` entry
+/// of the same name as the variable.
+#[derive(Debug, Clone, Default)]
+pub struct Secrets {
+    #[cfg_attr(not(feature = "email"), allow(dead_code))]
+    pub hibp_api_key: Option<String>,
+    pub proxy_pass: Option<String>,
+}
+
+impl Secrets {
+    pub fn resolve(
+        hibp_api_key: Option<&str>,
+        proxy_pass: Option<&str>,
+        secrets_file: Option<&str>,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = match secrets_file {
+            Some(path) => parse_dotenv(&std::fs::read_to_string(path)?),
+            None => HashMap::new(),
+        };
+        let env = |name: &str| std::env::var(name).ok();
+
+        Ok(Self {
+            hibp_api_key: lookup(hibp_api_key, HIBP_KEY_ENV, env, &file),
+            proxy_pass: lookup(proxy_pass, PROXY_PASS_ENV, env, &file),
+        })
+    }
+}
+
+/// Flag, then environment (via `env`), then secrets file; an empty value counts
+/// as unset, so the next source is tried.
+fn lookup(
+    flag: Option<&str>,
+    name: &str,
+    env: impl Fn(&str) -> Option<String>,
+    file: &HashMap<String, String>,
+) -> Option<String> {
+    let set = |v: &String| !v.is_empty();
+    flag.map(|v| v.to_string())
+        .filter(set)
+        .or_else(|| env(name).filter(set))
+        .or_else(|| file.get(name).cloned().filter(set))
+}
+
+/// Parses `KEY=value` lines as in a `.env` file: blank lines and `#` comments are
+/// skipped, a leading `export ` is allowed and matching outer quotes are stripped.
+pub fn parse_dotenv(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let line = line.strip_prefix("export ").unwrap_or(line);
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            let value = ['"', '\'']
+                .iter()
+                .find_map(|q| value.strip_prefix(*q).and_then(|v| v.strip_suffix(*q)))
+                .unwrap_or(value);
+            Some((key.trim().to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flag_beats_env_beats_file_and_empty_is_unset() {
+        let file = HashMap::from([(HIBP_KEY_ENV.to_string(), "from-file".to_string())]);
+        let env_with = |value: &'static str| move |name: &str| (name == HIBP_KEY_ENV).then(|| value.to_string());
+        let no_env = |_: &str| None;
+
+        assert_eq!(lookup(Some("from-flag"), HIBP_KEY_ENV, env_with("from-env"), &file).as_deref(), Some("from-flag"));
+        assert_eq!(lookup(None, HIBP_KEY_ENV, env_with("from-env"), &file).as_deref(), Some("from-env"));
+        assert_eq!(lookup(None, HIBP_KEY_ENV, no_env, &file).as_deref(), Some("from-file"));
+        assert_eq!(lookup(None, HIBP_KEY_ENV, no_env, &HashMap::new()), None);
+
+        // Empty values fall through to the next source
+        assert_eq!(lookup(Some(""), HIBP_KEY_ENV, env_with("from-env"), &file).as_deref(), Some("from-env"));
+        assert_eq!(lookup(None, HIBP_KEY_ENV, env_with(""), &file).as_deref(), Some("from-file"));
+        let empty_file = HashMap::from([(HIBP_KEY_ENV.to_string(), String::new())]);
+        assert_eq!(lookup(None, HIBP_KEY_ENV, no_env, &empty_file), None);
+    }
+
+    #[test]
+    fn dotenv_skips_comments_and_strips_export_and_quotes() {
+        let parsed = parse_dotenv(
+            "# credentials\n\nWATSON_HIBP_KEY=abc123\nexport WATSON_PROXY_PASS = \"p@ss word\"\nQUOTED='single'\nMIXED=\"left'\nEMPTY=\nnot a pair\n",
+        );
+        assert_eq!(
+            parsed,
+            HashMap::from([
+                ("WATSON_HIBP_KEY".to_string(), "abc123".to_string()),
+                ("WATSON_PROXY_PASS".to_string(), "p@ss word".to_string()),
+                ("QUOTED".to_string(), "single".to_string()),
+                ("MIXED".to_string(), "\"left'".to_string()),
+                ("EMPTY".to_string(), String::new()),
+            ])
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use crate::data::SiteInfo;
+use crate::engine::{QueryResult, QueryStatus, SearchEngine};
+use futures::stream::{self, StreamExt};
+use std::collections::{BTreeMap, HashMap};
+
+/// `--self-test` outcome for one site.
+#[derive(Debug, Default)]
+pub struct SiteSelfTest {
+    pub site_name: String,
+    /// Known usernames that came back with the wrong answer: detection is broken.
+    pub failures: Vec<String>,
+    /// Known usernames whose check errored or timed out, so they prove nothing.
+    pub inconclusive: Vec<String>,
+}
+
+impl SiteSelfTest {
+    pub fn is_broken(&self) -> bool {
+        !self.failures.is_empty()
+    }
+
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty() && self.inconclusive.is_empty()
+    }
+}
+
+/// Checks every site that names a known username both ways: `username_claimed`
+/// must come back claimed and `username_unclaimed`, where present, available.
+/// Sites with neither aren't tested. Up to `concurrency` usernames are searched
+/// at once; results are sorted by site name.
+pub async fn run(engine: &SearchEngine, sites: &HashMap<String, SiteInfo>, concurrency: usize) -> Vec<SiteSelfTest> {
+    // Sites sharing a known username are checked in one search
+    let mut groups: BTreeMap<(String, bool), HashMap<String, SiteInfo>> = BTreeMap::new();
+    for (name, info) in sites {
+        let known = [(&info.username_claimed, true), (&info.username_unclaimed, false)];
+        for (username, claimed) in known {
+            if let Some(username) = username.as_deref().filter(|u| !u.is_empty()) {
+                groups
+                    .entry((username.to_string(), claimed))
+                    .or_default()
+                    .insert(name.clone(), info.clone());
+            }
+        }
+    }
+
+    let searches = stream::iter(groups)
+        .map(|((username, claimed), group)| async move {
+            let results = engine.search_username(&username, &group).await;
+            (username, claimed, group, results)
+        })
+        .buffer_unordered(concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut tests: BTreeMap<String, SiteSelfTest> = BTreeMap::new();
+    for (username, claimed, group, results) in searches {
+        for site_name in group.keys() {
+            let result = results.iter().find(|r| r.site_name == *site_name);
+            let test = tests.entry(site_name.clone()).or_insert_with(|| SiteSelfTest {
+                site_name: site_name.clone(),
+                ..Default::default()
+            });
+            judge(test, &username, claimed, result);
+        }
+    }
+    tests.into_values().collect()
+}
+
+/// Records how one known username's check went against what the site should say.
+fn judge(test: &mut SiteSelfTest, username: &str, claimed: bool, result: Option<&QueryResult>) {
+    let (role, expected) = if claimed {
+        ("claimed", QueryStatus::Claimed)
+    } else {
+        ("unclaimed", QueryStatus::Available)
+    };
+    let Some(result) = result else {
+        test.inconclusive.push(format!("{} username '{}' was skipped", role, username));
+        return;
+    };
+    let got = match result.status {
+        QueryStatus::Claimed => "claimed",
+        QueryStatus::Available => "available",
+        QueryStatus::Error => "error",
+        QueryStatus::Illegal => "illegal",
+        QueryStatus::Unknown => "unknown",
+        QueryStatus::TimedOut => "timed_out",
+    };
+    let detail = match result.error_message {
+        Some(ref message) => format!("{} ({})", got, message),
+        None => got.to_string(),
+    };
+    match result.status {
+        status if status == expected => {}
+        QueryStatus::Error | QueryStatus::TimedOut => {
+            test.inconclusive.push(format!("{} username '{}' gave {}", role, username, detail));
+        }
+        _ => test.failures.push(format!("{} username '{}' gave {}", role, username, detail)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mock_site(url: &str, claimed: &str, unclaimed: Option<&str>) -> SiteInfo {
+        let mut info: SiteInfo = serde_json::from_value(serde_json::json!({
+            "url": url,
+            "urlMain": "https://mock.watson.invalid/",
+            "errorType": "status_code",
+        }))
+        .unwrap();
+        info.username_claimed = Some(claimed.to_string());
+        info.username_unclaimed = unclaimed.map(|u| u.to_string());
+        info
+    }
+
+    fn engine() -> SearchEngine {
+        SearchEngine::new(5, 4, false, false)
+            .unwrap()
+            .with_negative_cache(false)
+            .with_mock()
+    }
+
+    #[tokio::test]
+    async fn site_detecting_both_directions_passes() {
+        // The mock answers 200 for the `claimed` path segment and 404 otherwise
+        let sites = HashMap::from([("Works".to_string(), mock_site("https://mock.watson.invalid/{}", "claimed", Some("nobody")))]);
+        let tests = run(&engine(), &sites, 4).await;
+        assert_eq!(tests.len(), 1);
+        assert!(tests[0].passed(), "{:?}", tests[0]);
+    }
+
+    #[tokio::test]
+    async fn site_claiming_the_unclaimed_username_is_broken() {
+        // Every username is "found", so only the unclaimed check catches it
+        let sites = HashMap::from([(
+            "AlwaysFound".to_string(),
+            mock_site("https://mock.watson.invalid/claimed/{}", "alice", Some("nobody")),
+        )]);
+        let tests = run(&engine(), &sites, 4).await;
+        assert!(tests[0].is_broken());
+        assert_eq!(tests[0].failures, vec!["unclaimed username 'nobody' gave claimed".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn site_missing_the_claimed_username_is_broken() {
+        let sites = HashMap::from([("NeverFound".to_string(), mock_site("https://mock.watson.invalid/{}", "alice", Some("nobody")))]);
+        let tests = run(&engine(), &sites, 4).await;
+        assert!(tests[0].is_broken());
+        assert_eq!(tests[0].failures, vec!["claimed username 'alice' gave available".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn sites_without_known_usernames_are_not_tested() {
+        let mut info = mock_site("https://mock.watson.invalid/{}", "claimed", None);
+        info.username_claimed = None;
+        let sites = HashMap::from([("Unknown".to_string(), info)]);
+        assert!(run(&engine(), &sites, 4).await.is_empty());
+    }
+
+    #[test]
+    fn errors_are_inconclusive_not_broken() {
+        let mut test = SiteSelfTest::default();
+        let result = QueryResult::error("alice", "Down", "", "", "connection refused");
+        judge(&mut test, "alice", true, Some(&result));
+        assert!(!test.is_broken());
+        assert_eq!(test.inconclusive, vec!["claimed username 'alice' gave error (connection refused)".to_string()]);
+    }
+}
@@ -0,0 +1,197 @@
+use crate::data::SiteInfo;
+use crate::engine::SearchEngine;
+use crate::output::{Report, ScanMetadata, SearchReport};
+use axum::extract::{Query, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Description of the API served at `/openapi.json`.
+const OPENAPI_SPEC: &str = r##"{
+  "openapi": "3.0.3",
+  "info": { "title": "Watson", "description": "Username lookup across social networks", "version": "0.1.1" },
+  "paths": {
+    "/search": {
+      "get": {
+        "summary": "Check a username against every loaded site",
+        "parameters": [
+          { "name": "username", "in": "query", "required": true, "schema": { "type": "string" } },
+          { "name": "format", "in": "query", "required": false,
+            "schema": { "type": "string", "enum": ["json", "text", "csv", "html"], "default": "json" } }
+        ],
+        "responses": {
+          "200": { "description": "The search report; with format=json a SearchReport object" },
+          "400": { "description": "Missing or invalid username, or unsupported format" }
+        }
+      }
+    },
+    "/healthz": {
+      "get": {
+        "summary": "Liveness check",
+        "responses": { "200": { "description": "The server is up", "content": { "text/plain": {} } } }
+      }
+    }
+  }
+}
+"##;
+
+/// Shared by every request: the engine, the site data loaded once at startup,
+/// and the cap on scans running at the same time.
+struct AppState {
+    engine: SearchEngine,
+    sites: HashMap<String, SiteInfo>,
+    /// Stamped with each search's start time before it's added to the report.
+    metadata: ScanMetadata,
+    scans: Semaphore,
+    max_username_len: usize,
+}
+
+#[derive(Deserialize)]
+struct SearchParams {
+    username: Option<String>,
+    format: Option<String>,
+}
+
+/// Serves the search API on `127.0.0.1:port` until the process is stopped.
+/// At most `max_scans` searches run at once; further requests wait their turn.
+pub async fn serve(
+    port: u16,
+    max_scans: usize,
+    engine: SearchEngine,
+    sites: HashMap<String, SiteInfo>,
+    metadata: ScanMetadata,
+    max_username_len: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let app = router(AppState {
+        engine,
+        sites,
+        metadata,
+        scans: Semaphore::new(max_scans),
+        max_username_len,
+    });
+
+    let listener = tokio::net::TcpListener::bind(("127.0.0.1", port)).await?;
+    println!("Serving on http://{}", listener.local_addr()?);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/search", get(search))
+        .route("/healthz", get(|| async { "ok" }))
+        .route(
+            "/openapi.json",
+            get(|| async { ([(header::CONTENT_TYPE, "application/json")], OPENAPI_SPEC) }),
+        )
+        .with_state(Arc::new(state))
+}
+
+async fn search(State(state): State<Arc<AppState>>, Query(params): Query<SearchParams>) -> Response {
+    let username = match params.username {
+        Some(ref u) if crate::username_len_ok(u, state.max_username_len) => u.clone(),
+        _ => {
+            let message = format!("username must be between 1 and {} characters", state.max_username_len);
+            return (StatusCode::BAD_REQUEST, message).into_response();
+        }
+    };
+    if let Some(c) = crate::disallowed_char(&username, None) {
+        return (StatusCode::BAD_REQUEST, format!("username contains {:?}", c)).into_response();
+    }
+
+    let format = params.format.as_deref().unwrap_or("json");
+    if !matches!(format, "json" | "text" | "csv" | "html") {
+        return (StatusCode::BAD_REQUEST, format!("unsupported format '{}'", format)).into_response();
+    }
+
+    let Ok(_permit) = state.scans.acquire().await else {
+        return StatusCode::SERVICE_UNAVAILABLE.into_response();
+    };
+    let traffic_before = state.engine.traffic();
+    let metadata = state.metadata.restarted();
+    let results = state.engine.search_username(&username, &state.sites).await;
+    let report = SearchReport::new(username, results, state.engine.is_using_tor())
+        .with_skipped(state.engine.nsfw_skipped(&state.sites))
+        .with_skipped(state.engine.token_skipped(&state.sites))
+        .with_traffic(state.engine.traffic().since(traffic_before))
+        .with_metadata(metadata);
+
+    match format {
+        // API clients parse the body, so skip the pretty-printing
+        "json" => match report.to_json_compact() {
+            Ok(json) => ([(header::CONTENT_TYPE, "application/json")], json).into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        },
+        "csv" => ([(header::CONTENT_TYPE, "text/csv")], report.to_csv()).into_response(),
+        "html" => ([(header::CONTENT_TYPE, "text/html; charset=utf-8")], report.to_html(false)).into_response(),
+        _ => report.to_text().into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{path_of, serve as serve_site};
+
+    /// Serves the API over one mock site that knows only `alice`.
+    async fn api() -> std::net::SocketAddr {
+        let site_addr = serve_site(|request| match path_of(request) {
+            "/alice" => "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+            _ => "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string(),
+        })
+        .await;
+        let site: SiteInfo = serde_json::from_value(serde_json::json!({
+            "url": format!("http://{}/{{}}", site_addr),
+            "urlMain": format!("http://{}/", site_addr),
+            "errorType": "status_code",
+        }))
+        .unwrap();
+        let app = router(AppState {
+            engine: SearchEngine::new(5, 4, false, false).unwrap().with_negative_cache(false),
+            sites: HashMap::from([("Local".to_string(), site)]),
+            metadata: ScanMetadata::new("test", "00000000", 4),
+            scans: Semaphore::new(1),
+            max_username_len: 50,
+        });
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        addr
+    }
+
+    async fn get_text(url: String) -> (u16, String) {
+        let response = reqwest::get(url).await.unwrap();
+        (response.status().as_u16(), response.text().await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn healthz_and_search_answer_over_mock_sites() {
+        let addr = api().await;
+        assert_eq!(get_text(format!("http://{}/healthz", addr)).await, (200, "ok".to_string()));
+
+        let (status, body) = get_text(format!("http://{}/search?username=alice", addr)).await;
+        assert_eq!(status, 200);
+        let report: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(report["claimed_count"], 1);
+        let (_, body) = get_text(format!("http://{}/search?username=bob", addr)).await;
+        let report: serde_json::Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(report["available_count"], 1);
+
+        assert_eq!(get_text(format!("http://{}/search", addr)).await.0, 400);
+        assert_eq!(get_text(format!("http://{}/search?username=alice&format=xml", addr)).await.0, 400);
+    }
+
+    #[tokio::test]
+    async fn search_html_escapes_the_username() {
+        let addr = api().await;
+        let (status, body) = get_text(format!("http://{}/search?format=html&username=%3Cscript%3Ealert(1)%3C/script%3E", addr)).await;
+        assert_eq!(status, 200);
+        assert!(!body.contains("<script>alert(1)"));
+        assert!(body.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+    }
+}
@@ -0,0 +1,97 @@
+use crate::engine::ScanProgress;
+use std::io::{IsTerminal, Write};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
+
+const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+const TICK: Duration = Duration::from_millis(100);
+
+/// One-line "checked X/Y, N found" indicator on stderr for `--spinner`, redrawn in place
+/// from the engine's `ScanProgress` until `finish` clears it.
+pub struct Spinner {
+    task: JoinHandle<()>,
+    clear: Box<dyn FnOnce() + Send>,
+}
+
+impl Spinner {
+    /// Starts drawing, or returns `None` when stderr isn't a terminal, where the
+    /// carriage returns would only clutter a log.
+    pub fn start(progress: Arc<ScanProgress>) -> Option<Self> {
+        if !std::io::stderr().is_terminal() {
+            return None;
+        }
+        Some(Self::draw_to(progress, || std::io::stderr().lock()))
+    }
+
+    /// Draws to the writers `out` opens; `start` only ever passes stderr, so
+    /// stdout stays clean for results.
+    fn draw_to<W: Write>(progress: Arc<ScanProgress>, out: impl Fn() -> W + Send + Sync + 'static) -> Self {
+        let out = Arc::new(out);
+        let drawing = out.clone();
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(TICK);
+            for frame in FRAMES.iter().cycle() {
+                interval.tick().await;
+                let mut writer = drawing();
+                let _ = write!(
+                    writer,
+                    "\r{} checked {}/{}, {} found",
+                    frame,
+                    progress.completed(),
+                    progress.total(),
+                    progress.found()
+                );
+                let _ = writer.flush();
+            }
+        });
+        let clear = Box::new(move || {
+            let mut writer = out();
+            let _ = write!(writer, "\r\x1b[2K");
+            let _ = writer.flush();
+        });
+        Self { task, clear }
+    }
+
+    /// Stops drawing and erases the line.
+    pub async fn finish(self) {
+        self.task.abort();
+        // Let a frame being written finish first, so it can't land after the clear
+        let _ = self.task.await;
+        (self.clear)();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// Appends to a shared buffer, standing in for stderr.
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn frames_go_to_the_given_writer_and_are_cleared() {
+        let progress = crate::engine::SearchEngine::new(5, 1, false, false).unwrap().progress_handle();
+        let buffer = Arc::new(Mutex::new(Vec::new()));
+        let captured = buffer.clone();
+        let spinner = Spinner::draw_to(progress, move || Captured(captured.clone()));
+        tokio::time::sleep(TICK).await;
+        spinner.finish().await;
+
+        let drawn = String::from_utf8(buffer.lock().unwrap().clone()).unwrap();
+        assert!(drawn.starts_with("\r| checked 0/0, 0 found"), "{:?}", drawn);
+        assert!(drawn.ends_with("\r\x1b[2K"));
+    }
+}
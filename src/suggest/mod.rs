@@ -0,0 +1,123 @@
+use crate::data::SiteInfo;
+use crate::engine::{QueryResult, QueryStatus, SearchEngine};
+use crate::variations::generate_variations;
+use std::collections::HashMap;
+
+/// Widely used sites a `--suggest` alternative is checked against.
+pub const TOP_SITES: &[&str] = &[
+    "GitHub",
+    "GitLab",
+    "Instagram",
+    "Medium",
+    "Pinterest",
+    "Reddit",
+    "SoundCloud",
+    "Spotify",
+    "Telegram",
+    "TikTok",
+    "Twitch",
+    "Twitter",
+    "YouTube",
+];
+
+/// A variation of the searched username and how many top sites it is free on.
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    pub username: String,
+    pub available: usize,
+    pub checked: usize,
+}
+
+/// The loaded sites among `TOP_SITES`, or all of `sites` when none of them are
+/// loaded (e.g. with `--site` or `--mock`).
+pub fn top_sites(sites: &HashMap<String, SiteInfo>) -> HashMap<String, SiteInfo> {
+    let top: HashMap<String, SiteInfo> = sites
+        .iter()
+        .filter(|(name, _)| TOP_SITES.contains(&name.as_str()))
+        .map(|(name, info)| (name.clone(), info.clone()))
+        .collect();
+    if top.is_empty() {
+        sites.clone()
+    } else {
+        top
+    }
+}
+
+fn available_on(results: &[QueryResult]) -> usize {
+    results.iter().filter(|r| r.status == QueryStatus::Available).count()
+}
+
+/// Probes every variation of `username` on `sites` and returns the one available
+/// on the most of them, earliest variation first on ties. `None` when no
+/// variation is available on more sites than `username` itself (`baseline`).
+pub async fn suggest(
+    engine: &SearchEngine,
+    username: &str,
+    sites: &HashMap<String, SiteInfo>,
+    baseline: &[QueryResult],
+) -> Option<Suggestion> {
+    let baseline = available_on(baseline);
+    let mut best: Option<Suggestion> = None;
+    for variation in generate_variations(username).into_iter().skip(1) {
+        if crate::disallowed_char(&variation, None).is_some() {
+            continue;
+        }
+        let results = engine.search_username(&variation, sites).await;
+        let available = available_on(&results);
+        if available > best.as_ref().map_or(baseline, |b| b.available) {
+            let done = available == sites.len();
+            best = Some(Suggestion { username: variation, available, checked: sites.len() });
+            if done {
+                break;
+            }
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{path_of, serve};
+
+    /// Every handle is taken except `alice12` on /a and `alice0` on both.
+    fn top_site_responses(request: &str) -> String {
+        let status = match path_of(request) {
+            "/a/alice12" | "/a/alice0" | "/b/alice0" => "404 Not Found",
+            _ => "200 OK",
+        };
+        format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status)
+    }
+
+    #[tokio::test]
+    async fn suggests_the_most_available_variation() {
+        let addr = serve(top_site_responses).await;
+        let local = |path: &str| -> SiteInfo {
+            serde_json::from_value(serde_json::json!({
+                "url": format!("http://{}/{}/{{}}", addr, path),
+                "urlMain": format!("http://{}/", addr),
+                "errorType": "status_code",
+            }))
+            .unwrap()
+        };
+        let sites = HashMap::from([
+            ("GitHub".to_string(), local("a")),
+            ("GitLab".to_string(), local("b")),
+            ("Elsewhere".to_string(), local("c")),
+        ]);
+        let top = top_sites(&sites);
+        let mut names: Vec<&String> = top.keys().collect();
+        names.sort();
+        assert_eq!(names, ["GitHub", "GitLab"]);
+
+        let engine = SearchEngine::new(5, 2, false, false).unwrap();
+        let baseline = engine.search_username("alice", &top).await;
+        assert_eq!(available_on(&baseline), 0);
+        let suggestion = suggest(&engine, "alice", &top, &baseline).await.expect("a suggestion");
+        assert_eq!((suggestion.username.as_str(), suggestion.available, suggestion.checked), ("alice0", 2, 2));
+
+        // Nothing is suggested when no variation beats the searched name
+        let taken = HashMap::from([("GitHub".to_string(), local("c"))]);
+        assert!(suggest(&engine, "alice", &taken, &engine.search_username("alice", &taken).await).await.is_none());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::output::SearchReport;
+use syslog::{Facility, Formatter3164, Logger, LoggerBackend};
+use tracing::warn;
+
+/// Sends claimed results and per-username summaries to the local syslog daemon
+/// as `key=value` messages under the `user` facility: claims at `notice`,
+/// summaries at `info`.
+pub struct SyslogSink {
+    logger: Logger<LoggerBackend, Formatter3164>,
+}
+
+impl SyslogSink {
+    /// Connects to the local syslog socket.
+    pub fn connect() -> Result<Self, syslog::Error> {
+        Ok(Self {
+            logger: syslog::unix(formatter())?,
+        })
+    }
+
+    /// One message per claimed site, then a summary line for the username.
+    /// A message the daemon refuses is logged and skipped.
+    pub fn send_report(&mut self, report: &SearchReport) {
+        for result in report.results.iter().filter(|r| r.is_claimed()) {
+            let message = key_values(&[
+                ("event", "claimed"),
+                ("username", &result.username),
+                ("site", &result.site_name),
+                ("url", &result.profile_url),
+            ]);
+            if let Err(e) = self.logger.notice(message) {
+                warn!("Could not send result to syslog: {}", e);
+            }
+        }
+
+        let message = key_values(&[
+            ("event", "summary"),
+            ("username", &report.username),
+            ("checked", &report.total_sites.to_string()),
+            ("claimed", &report.claimed_count.to_string()),
+            ("available", &report.available_count.to_string()),
+            ("errors", &report.error_count.to_string()),
+            ("aborted", &report.aborted.to_string()),
+        ]);
+        if let Err(e) = self.logger.info(message) {
+            warn!("Could not send summary to syslog: {}", e);
+        }
+    }
+}
+
+fn formatter() -> Formatter3164 {
+    Formatter3164 {
+        facility: Facility::LOG_USER,
+        hostname: None,
+        process: "watson".to_string(),
+        pid: std::process::id(),
+    }
+}
+
+/// `key=value` pairs separated by spaces; values with spaces, quotes or `=` are
+/// double-quoted with `"` and `\` escaped.
+fn key_values(pairs: &[(&str, &str)]) -> String {
+    pairs
+        .iter()
+        .map(|(key, value)| {
+            if value.is_empty() || value.contains(|c: char| c.is_whitespace() || c == '"' || c == '=') {
+                format!("{}=\"{}\"", key, value.replace('\\', "\\\\").replace('"', "\\\""))
+            } else {
+                format!("{}={}", key, value)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::QueryResult;
+
+    #[cfg(unix)]
+    #[test]
+    fn report_is_sent_as_a_claim_then_a_summary() {
+        let path = std::env::temp_dir().join(format!("watson-test-{}-syslog.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let daemon = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+        let mut sink = SyslogSink {
+            logger: syslog::unix_custom(formatter(), &path).unwrap(),
+        };
+
+        let results = vec![
+            QueryResult::claimed("alice", "My Site", "https://my.example/", "https://my.example/alice"),
+            QueryResult::available("alice", "Free", "https://free.example/", "https://free.example/alice"),
+        ];
+        sink.send_report(&SearchReport::new("alice".to_string(), results, false));
+
+        let mut received = vec![];
+        for _ in 0..2 {
+            let mut buf = [0; 1024];
+            let n = daemon.recv(&mut buf).unwrap();
+            received.push(String::from_utf8_lossy(&buf[..n]).to_string());
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        // <priority> is facility user (1) * 8 + severity: notice 5, info 6
+        let tag = format!("watson[{}]: ", std::process::id());
+        assert!(received[0].starts_with("<13>"), "{}", received[0]);
+        assert!(received[0].ends_with(&format!("{}event=claimed username=alice site=\"My Site\" url=https://my.example/alice", tag)));
+        assert!(received[1].starts_with("<14>"), "{}", received[1]);
+        assert!(received[1].ends_with(&format!(
+            "{}event=summary username=alice checked=2 claimed=1 available=1 errors=0 aborted=false",
+            tag
+        )));
+    }
+
+    #[test]
+    fn values_are_quoted_only_when_needed() {
+        assert_eq!(
+            key_values(&[("a", "plain"), ("b", ""), ("c", "say \"hi\""), ("d", "x=y"), ("e", "back\\slash")]),
+            r#"a=plain b="" c="say \"hi\"" d="x=y" e=back\slash"#
+        );
+    }
+}
@@ -0,0 +1,66 @@
+use std::net::SocketAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// Serves each request on 127.0.0.1 with the raw HTTP response `respond`
+/// gives for the raw request; connections are closed after one response.
+pub async fn serve(respond: fn(&str) -> String) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut buf = vec![0; 4096];
+                let n = socket.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let _ = socket.write_all(respond(&request).as_bytes()).await;
+            });
+        }
+    });
+    addr
+}
+
+/// The test CA that signed `localhost.pem`.
+pub const TEST_CA_PEM: &str = include_str!("ca.pem");
+
+/// Like `serve`, but over TLS with `localhost.pem`: valid for `localhost` and
+/// 127.0.0.1 until 2126, and signed by the test CA in `ca.pem`, which no
+/// system trust store knows.
+#[cfg(feature = "tls")]
+pub async fn serve_tls(respond: fn(&str) -> String) -> SocketAddr {
+    use rustls::pki_types::pem::PemObject;
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+    use std::sync::Arc;
+
+    let cert = CertificateDer::from_pem_slice(include_bytes!("localhost.pem")).unwrap();
+    let key = PrivateKeyDer::from_pem_slice(include_bytes!("localhost.key")).unwrap();
+    let config = rustls::ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_no_client_auth()
+        .with_single_cert(vec![cert], key)
+        .unwrap();
+    let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            let acceptor = acceptor.clone();
+            tokio::spawn(async move {
+                // Clients that reject the certificate abort the handshake
+                let Ok(mut stream) = acceptor.accept(socket).await else { return };
+                let mut buf = vec![0; 4096];
+                let n = stream.read(&mut buf).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let _ = stream.write_all(respond(&request).as_bytes()).await;
+                let _ = stream.shutdown().await;
+            });
+        }
+    });
+    addr
+}
+
+/// The request target of a raw HTTP request.
+pub fn path_of(request: &str) -> &str {
+    request.split_whitespace().nth(1).unwrap_or("/")
+}
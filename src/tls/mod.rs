@@ -0,0 +1,193 @@
+use crate::output::TlsCertificate;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{ClientConfig, DigitallySignedStruct, SignatureScheme};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_rustls::TlsConnector;
+use tracing::info;
+use x509_parser::extensions::GeneralName;
+
+/// Accepts any certificate: the point is to record what a host presents,
+/// including self-signed or expired ones. Handshake signatures are still checked.
+#[derive(Debug)]
+struct RecordAnyCert(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for RecordAnyCert {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.0.signature_verification_algorithms)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+fn client_config() -> Result<Arc<ClientConfig>, rustls::Error> {
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let config = ClientConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(RecordAnyCert(provider)))
+        .with_no_client_auth();
+    Ok(Arc::new(config))
+}
+
+/// The `https://` host and port of a profile URL; other schemes have no certificate.
+pub fn tls_endpoint(profile_url: &str) -> Option<(String, u16)> {
+    let url = url::Url::parse(profile_url).ok()?;
+    if url.scheme() != "https" {
+        return None;
+    }
+    Some((url.host_str()?.to_string(), url.port_or_known_default()?))
+}
+
+/// Handshakes with `host:port` and reads the leaf certificate's subject CN,
+/// SANs, issuer and expiry.
+async fn fetch_certificate(
+    config: Arc<ClientConfig>,
+    host: &str,
+    port: u16,
+) -> Result<TlsCertificate, Box<dyn std::error::Error + Send + Sync>> {
+    let server_name = ServerName::try_from(host.to_string())?;
+    let tcp = TcpStream::connect((host, port)).await?;
+    let stream = TlsConnector::from(config).connect(server_name, tcp).await?;
+    let der = stream
+        .get_ref()
+        .1
+        .peer_certificates()
+        .and_then(|chain| chain.first())
+        .ok_or("server sent no certificate")?;
+    let (_, cert) = x509_parser::parse_x509_certificate(der)?;
+
+    let common_name = cert
+        .subject()
+        .iter_common_name()
+        .next()
+        .and_then(|cn| cn.as_str().ok())
+        .map(|cn| cn.to_string());
+    let sans = match cert.subject_alternative_name()? {
+        Some(ext) => ext
+            .value
+            .general_names
+            .iter()
+            .filter_map(|name| match name {
+                GeneralName::DNSName(dns) => Some(dns.to_string()),
+                GeneralName::IPAddress(bytes) => match bytes.len() {
+                    4 => Some(std::net::Ipv4Addr::from(<[u8; 4]>::try_from(*bytes).ok()?).to_string()),
+                    16 => Some(std::net::Ipv6Addr::from(<[u8; 16]>::try_from(*bytes).ok()?).to_string()),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    Ok(TlsCertificate {
+        host: host.to_string(),
+        common_name,
+        sans,
+        issuer: Some(cert.issuer().to_string()),
+        not_after: DateTime::<Utc>::from_timestamp(cert.validity().not_after.timestamp(), 0),
+        error: None,
+    })
+}
+
+/// Captures the certificate of every distinct `https` host among `profile_urls`,
+/// at most `max_concurrent` handshakes at a time. A host that can't be reached or
+/// parsed gets an entry with `error` set instead. Sorted by host.
+pub async fn capture_certificates(profile_urls: &[String], timeout: u64, max_concurrent: usize) -> Vec<TlsCertificate> {
+    let endpoints: BTreeSet<(String, u16)> = profile_urls.iter().filter_map(|url| tls_endpoint(url)).collect();
+    let config = match client_config() {
+        Ok(config) => config,
+        Err(e) => {
+            return endpoints
+                .into_iter()
+                .map(|(host, _)| TlsCertificate::failed(&host, &e.to_string()))
+                .collect();
+        }
+    };
+
+    let mut certificates: Vec<TlsCertificate> = stream::iter(endpoints)
+        .map(|(host, port)| {
+            let config = config.clone();
+            async move {
+                let fetched = tokio::time::timeout(Duration::from_secs(timeout), fetch_certificate(config, &host, port)).await;
+                match fetched {
+                    Ok(Ok(certificate)) => certificate,
+                    Ok(Err(e)) => {
+                        info!("TLS capture for {} failed: {}", host, e);
+                        TlsCertificate::failed(&host, &e.to_string())
+                    }
+                    Err(_) => TlsCertificate::failed(&host, "handshake timed out"),
+                }
+            }
+        })
+        .buffer_unordered(max_concurrent.max(1))
+        .collect()
+        .await;
+    certificates.sort_by(|a, b| a.host.cmp(&b.host));
+    certificates
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Datelike;
+
+    #[tokio::test]
+    async fn captures_a_local_servers_certificate() {
+        let addr = crate::testutil::serve_tls(|_| String::new()).await;
+        let refused = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let urls = [
+            format!("https://localhost:{}/alice", addr.port()),
+            format!("https://localhost:{}/bob", addr.port()),
+            "http://plain.example/alice".to_string(),
+            format!("https://127.0.0.1:{}/alice", refused.port()),
+        ];
+
+        let certificates = capture_certificates(&urls, 5, 4).await;
+        assert_eq!(certificates.len(), 2);
+        assert_eq!(certificates[0].host, "127.0.0.1");
+        assert!(certificates[0].error.is_some());
+
+        let local = &certificates[1];
+        assert_eq!(local.host, "localhost");
+        assert_eq!(local.error, None);
+        assert_eq!(local.common_name.as_deref(), Some("localhost"));
+        assert_eq!(local.sans, ["localhost", "127.0.0.1"]);
+        assert_eq!(local.issuer.as_deref(), Some("CN=Watson Test CA"));
+        assert_eq!(local.not_after.map(|at| at.year()), Some(2126));
+    }
+}
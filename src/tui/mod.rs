@@ -7,7 +7,7 @@ use ratatui::{
     widgets::{Block, Borders, Gauge, List, ListItem, Paragraph},
     Terminal,
 };
-use std::io;
+use std::io::{self, IsTerminal};
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
@@ -20,6 +20,7 @@ pub struct TUIState {
     pub is_running: Arc<AtomicBool>,
     pub results: Arc<Mutex<Vec<(String, String)>>>,
     pub current_site: Arc<Mutex<String>>,
+    pub username: Arc<Mutex<String>>,
     pub start_time: Arc<Mutex<Instant>>,
     pub scroll_position: Arc<AtomicUsize>,
 }
@@ -33,6 +34,7 @@ impl TUIState {
             is_running: Arc::new(AtomicBool::new(true)),
             results: Arc::new(Mutex::new(Vec::new())),
             current_site: Arc::new(Mutex::new(String::new())),
+            username: Arc::new(Mutex::new(String::new())),
             start_time: Arc::new(Mutex::new(Instant::now())),
             scroll_position: Arc::new(AtomicUsize::new(0)),
         }
@@ -40,8 +42,11 @@ impl TUIState {
 
     pub fn handle_progress(&self, update: ProgressUpdate) {
         match update {
-            ProgressUpdate::Started { total, .. } => {
+            ProgressUpdate::Started { total, username } => {
                 self.total_sites.store(total, Ordering::Relaxed);
+                if let Ok(mut current) = self.username.lock() {
+                    *current = username;
+                }
             }
             ProgressUpdate::SiteChecked { site, url, found } => {
                 self.completed.fetch_add(1, Ordering::Relaxed);
@@ -55,7 +60,8 @@ impl TUIState {
                     *current = site;
                 }
             }
-            ProgressUpdate::Completed { .. } => {
+            ProgressUpdate::Completed { total_found } => {
+                self.found_results.store(total_found, Ordering::Relaxed);
                 self.is_running.store(false, Ordering::Relaxed);
             }
         }
@@ -82,6 +88,26 @@ impl TUIState {
     }
 }
 
+/// Why the TUI can't run here, if it can't: stdout must be a terminal and `TERM`
+/// must name something other than `dumb`.
+pub fn unsupported_reason() -> Option<&'static str> {
+    unsupported_reason_for(io::stdout().is_terminal(), std::env::var("TERM").ok().as_deref())
+}
+
+fn unsupported_reason_for(stdout_is_terminal: bool, term: Option<&str>) -> Option<&'static str> {
+    if !stdout_is_terminal {
+        return Some("stdout is not a terminal");
+    }
+    if cfg!(unix) {
+        match term {
+            Some("" | "dumb") => return Some("TERM does not support it"),
+            None => return Some("TERM is not set"),
+            _ => {}
+        }
+    }
+    None
+}
+
 pub fn run_tui(state: TUIState) -> io::Result<()> {
     let backend = CrosstermBackend::new(io::stdout());
     let mut terminal = Terminal::new(backend)?;
@@ -103,7 +129,7 @@ pub fn run_tui(state: TUIState) -> io::Result<()> {
                 .split(size);
 
             // Title
-            let title = Paragraph::new("⚡ Watson OSINT Tool")
+            let title = Paragraph::new(title_for(&state.username.lock().unwrap(), ""))
                 .style(Style::default().fg(Color::Cyan).bold())
                 .block(Block::default().borders(Borders::ALL).title(" Watson "));
             f.render_widget(title, chunks[0]);
@@ -240,7 +266,7 @@ pub fn run_tui(state: TUIState) -> io::Result<()> {
             ])
             .split(size);
 
-        let title = Paragraph::new("⚡ Watson OSINT Tool - COMPLETE")
+        let title = Paragraph::new(title_for(&state.username.lock().unwrap(), " - COMPLETE"))
             .style(Style::default().fg(Color::Green).bold())
             .block(Block::default().borders(Borders::ALL).title(" Watson "));
         f.render_widget(title, chunks[0]);
@@ -287,6 +313,14 @@ pub fn run_tui(state: TUIState) -> io::Result<()> {
     Ok(())
 }
 
+fn title_for(username: &str, suffix: &str) -> String {
+    if username.is_empty() {
+        format!("⚡ Watson OSINT Tool{}", suffix)
+    } else {
+        format!("⚡ Watson OSINT Tool: {}{}", username, suffix)
+    }
+}
+
 fn format_time(seconds: u64) -> String {
     let mins = seconds / 60;
     let secs = seconds % 60;
@@ -296,3 +330,18 @@ fn format_time(seconds: u64) -> String {
         format!("{}s", secs)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_off_a_terminal() {
+        assert_eq!(unsupported_reason_for(false, Some("xterm-256color")), Some("stdout is not a terminal"));
+        assert_eq!(unsupported_reason_for(true, Some("xterm-256color")), None);
+        if cfg!(unix) {
+            assert_eq!(unsupported_reason_for(true, Some("dumb")), Some("TERM does not support it"));
+            assert_eq!(unsupported_reason_for(true, None), Some("TERM is not set"));
+        }
+    }
+}
@@ -1,8 +1,7 @@
 use rand::seq::SliceRandom;
-use rand::Rng;
 
 pub struct UserAgentRotator {
-    user_agents: Vec<String>,
+    pub user_agents: Vec<String>,
 }
 
 impl UserAgentRotator {
@@ -46,10 +45,6 @@ impl UserAgentRotator {
         let mut rng = rand::thread_rng();
         self.user_agents.choose(&mut rng).unwrap().clone()
     }
-
-    pub fn get_all(&self) -> &Vec<String> {
-        &self.user_agents
-    }
 }
 
 impl Default for UserAgentRotator {
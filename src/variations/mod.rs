@@ -1,40 +1,185 @@
+/// Above this many site checks (variations x sites) a `--variations` scan warns first.
+pub const REQUEST_WARN_THRESHOLD: usize = 5_000;
+
+/// Returns `username` followed by its variations. Candidates are built in this
+/// exact order, and any candidate equal to an earlier one is dropped, so the
+/// output depends only on `username` and the original always comes first:
+///
+/// 1. `username` itself
+/// 2. `_username`, `username_`
+/// 3. `_` replaced by `.`, then `-` replaced by `.`
+/// 4. `.` replaced by `_`, then `-` replaced by `_`
+/// 5. `-username`, `username-`
+/// 6. suffixes `1`, `12`, `123`, `0`
+/// 7. prefixes `the_`, `real_`
+/// 8. all lowercase, then all uppercase
+///
+/// Replacements and case changes that leave the name unchanged fall out as
+/// duplicates. For `John.Doe` this gives `John.Doe`, `_John.Doe`, `John.Doe_`,
+/// `John_Doe`, `-John.Doe`, `John.Doe-`, `John.Doe1`, `John.Doe12`,
+/// `John.Doe123`, `John.Doe0`, `the_John.Doe`, `real_John.Doe`, `john.doe`,
+/// `JOHN.DOE`.
 pub fn generate_variations(username: &str) -> Vec<String> {
-    let mut variations = vec![username.to_string()];
+    let candidates = [
+        username.to_string(),
+        format!("_{}", username),
+        format!("{}_", username),
+        username.replace('_', "."),
+        username.replace('-', "."),
+        username.replace('.', "_"),
+        username.replace('-', "_"),
+        format!("-{}", username),
+        format!("{}-", username),
+        format!("{}1", username),
+        format!("{}12", username),
+        format!("{}123", username),
+        format!("{}0", username),
+        format!("the_{}", username),
+        format!("real_{}", username),
+        username.to_lowercase(),
+        username.to_uppercase(),
+    ];
 
-    // Add underscore prefix/suffix
-    variations.push(format!("_{}", username));
-    variations.push(format!("{}_", username));
+    let mut seen = std::collections::HashSet::new();
+    candidates.into_iter().filter(|v| seen.insert(v.clone())).collect()
+}
 
-    // Add dots
-    variations.push(username.replace("_", "."));
-    variations.push(username.replace("-", "."));
+/// Most usernames a single `-u` pattern may expand to.
+pub const MAX_EXPANSION: usize = 1_000;
 
-    // Add underscore
-    variations.push(username.replace(".", "_"));
-    variations.push(username.replace("-", "_"));
+/// Expands shell-style brace patterns in `pattern`: `john{,_dev,2024}` gives
+/// `john`, `john_dev`, `john2024`, and `user{1..3}` / `user{08..10}` give numeric
+/// ranges (zero-padded when an endpoint is). Groups may nest and combine; braces
+/// that are neither a list nor a range stay literal. Fails past `max` results.
+pub fn expand(pattern: &str, max: usize) -> Result<Vec<String>, String> {
+    let mut expanded = vec![];
+    expand_into(pattern.to_string(), &mut expanded, max)?;
+    let mut seen = std::collections::HashSet::new();
+    expanded.retain(|v| seen.insert(v.clone()));
+    Ok(expanded)
+}
+
+fn expand_into(pattern: String, out: &mut Vec<String>, max: usize) -> Result<(), String> {
+    let Some((start, end, alternatives)) = first_group(&pattern, max) else {
+        if out.len() >= max {
+            return Err(format!("Pattern expands to more than {} usernames", max));
+        }
+        out.push(pattern);
+        return Ok(());
+    };
+    for alternative in alternatives {
+        let next = format!("{}{}{}", &pattern[..start], alternative, &pattern[end + 1..]);
+        expand_into(next, out, max)?;
+    }
+    Ok(())
+}
+
+/// The first brace group that is a list or a range, as its byte span and its
+/// alternatives. Inner groups are left in the alternatives for the next pass.
+fn first_group(pattern: &str, max: usize) -> Option<(usize, usize, Vec<String>)> {
+    let bytes = pattern.as_bytes();
+    for start in (0..bytes.len()).filter(|&i| bytes[i] == b'{') {
+        let mut depth = 0;
+        let mut commas = vec![];
+        let mut end = None;
+        for (i, &b) in bytes.iter().enumerate().skip(start) {
+            match b {
+                b'{' => depth += 1,
+                b'}' => {
+                    depth -= 1;
+                    if depth == 0 {
+                        end = Some(i);
+                        break;
+                    }
+                }
+                b',' if depth == 1 => commas.push(i),
+                _ => {}
+            }
+        }
+        let Some(end) = end else { continue };
+        let body = &pattern[start + 1..end];
+        if !commas.is_empty() {
+            let mut alternatives = vec![];
+            let mut from = start + 1;
+            for comma in commas.into_iter().chain(std::iter::once(end)) {
+                alternatives.push(pattern[from..comma].to_string());
+                from = comma + 1;
+            }
+            return Some((start, end, alternatives));
+        }
+        if let Some(range) = numeric_range(body, max) {
+            return Some((start, end, range));
+        }
+    }
+    None
+}
+
+/// `a..b` as the integers from `a` to `b` (either direction), padded to the
+/// wider endpoint when either is written with a leading zero. Stops one past
+/// `max` so a huge range fails the cap instead of filling memory.
+fn numeric_range(body: &str, max: usize) -> Option<Vec<String>> {
+    let (from, to) = body.split_once("..")?;
+    let (a, b): (i64, i64) = (from.parse().ok()?, to.parse().ok()?);
+    let padded = |s: &str| s.trim_start_matches('-').len() > 1 && s.trim_start_matches('-').starts_with('0');
+    let width = if padded(from) || padded(to) { from.len().max(to.len()) } else { 0 };
+    let values: Box<dyn Iterator<Item = i64>> = if a <= b { Box::new(a..=b) } else { Box::new((b..=a).rev()) };
+    Some(values.take(max + 1).map(|n| format!("{:0width$}", n, width = width)).collect())
+}
 
-    // Add dash prefix/suffix
-    variations.push(format!("-{}", username));
-    variations.push(format!("{}-", username));
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    // Common suffixes
-    variations.push(format!("{}1", username));
-    variations.push(format!("{}12", username));
-    variations.push(format!("{}123", username));
-    variations.push(format!("{}0", username));
+    #[test]
+    fn variations_golden() {
+        assert_eq!(
+            generate_variations("John.Doe"),
+            [
+                "John.Doe", "_John.Doe", "John.Doe_", "John_Doe", "-John.Doe", "John.Doe-", "John.Doe1", "John.Doe12",
+                "John.Doe123", "John.Doe0", "the_John.Doe", "real_John.Doe", "john.doe", "JOHN.DOE",
+            ]
+        );
+        assert_eq!(
+            generate_variations("jo_hn-d"),
+            [
+                "jo_hn-d", "_jo_hn-d", "jo_hn-d_", "jo.hn-d", "jo_hn.d", "jo_hn_d", "-jo_hn-d", "jo_hn-d-", "jo_hn-d1",
+                "jo_hn-d12", "jo_hn-d123", "jo_hn-d0", "the_jo_hn-d", "real_jo_hn-d", "JO_HN-D",
+            ]
+        );
+    }
 
-    // Common prefixes
-    variations.push(format!("the_{}", username));
-    variations.push(format!("_{}", username));
-    variations.push(format!("real_{}", username));
+    #[test]
+    fn variations_have_no_duplicates() {
+        let variations = generate_variations("_");
+        let unique: std::collections::HashSet<_> = variations.iter().collect();
+        assert_eq!(unique.len(), variations.len());
+        assert_eq!(variations[0], "_");
+    }
 
-    // Case variations
-    variations.push(username.to_lowercase());
-    variations.push(username.to_uppercase());
+    #[test]
+    fn braces_expand_lists_and_ranges() {
+        assert_eq!(expand("john{,_dev,2024}", MAX_EXPANSION).unwrap(), ["john", "john_dev", "john2024"]);
+        assert_eq!(expand("user{1..3}", MAX_EXPANSION).unwrap(), ["user1", "user2", "user3"]);
+        assert_eq!(expand("user{3..1}", MAX_EXPANSION).unwrap(), ["user3", "user2", "user1"]);
+        assert_eq!(expand("{a,b}{1,2}", MAX_EXPANSION).unwrap(), ["a1", "a2", "b1", "b2"]);
+        assert_eq!(expand("x{a,b{1..2}}", MAX_EXPANSION).unwrap(), ["xa", "xb1", "xb2"]);
+        assert_eq!(expand("{a,a}", MAX_EXPANSION).unwrap(), ["a"]);
+        assert_eq!(expand("lit{eral}{}", MAX_EXPANSION).unwrap(), ["lit{eral}{}"]);
+    }
 
-    // Remove duplicates
-    variations.sort();
-    variations.dedup();
+    #[test]
+    fn ranges_with_a_leading_zero_are_padded() {
+        assert_eq!(expand("user{08..10}", MAX_EXPANSION).unwrap(), ["user08", "user09", "user10"]);
+        assert_eq!(expand("u{1..010}", MAX_EXPANSION).unwrap()[..2], ["u001", "u002"]);
+        assert_eq!(expand("u{9..10}", MAX_EXPANSION).unwrap(), ["u9", "u10"]);
+    }
 
-    variations
+    #[test]
+    fn expansion_past_the_cap_fails() {
+        assert_eq!(expand("u{1..1000}", MAX_EXPANSION).unwrap().len(), MAX_EXPANSION);
+        let too_many = "Pattern expands to more than 1000 usernames";
+        assert_eq!(expand("u{1..1001}", MAX_EXPANSION).unwrap_err(), too_many);
+        assert_eq!(expand("u{0..9}{0..9}{0..9}{0..9}", MAX_EXPANSION).unwrap_err(), too_many);
+        assert_eq!(expand("u{1..9999999999}", MAX_EXPANSION).unwrap_err(), too_many);
+    }
 }
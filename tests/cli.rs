@@ -0,0 +1,138 @@
+//! End-to-end checks of the `watson` binary against the offline `--mock` sites.
+
+use std::process::{Command, Output};
+
+fn watson(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_watson"))
+        .args(args)
+        .env_remove("NO_COLOR")
+        .output()
+        .expect("run watson")
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+#[test]
+fn quiet_prints_only_results_on_stdout() {
+    let chatty = watson(&["-u", "alice", "--mock", "-s"]);
+    assert!(chatty.status.success());
+    assert!(stdout(&chatty).contains("Searching for username: alice"));
+
+    let quiet = watson(&["-q", "-u", "alice", "--mock", "-s"]);
+    assert!(quiet.status.success());
+    let lines: Vec<String> = stdout(&quiet).lines().map(str::to_string).collect();
+    assert_eq!(
+        lines,
+        [
+            "[+] MockClaimed: https://mock.watson.invalid/claimed/alice",
+            "[+] MockJsonClaimed: https://mock.watson.invalid/json-claimed/alice",
+            "[+] MockMessageClaimed: https://mock.watson.invalid/message-claimed/alice",
+        ]
+    );
+}
+
+#[test]
+fn spinner_leaves_stdout_unchanged() {
+    let plain = watson(&["-q", "-u", "alice", "--mock", "-s"]);
+    let spinning = watson(&["-q", "-u", "alice", "--mock", "-s", "--spinner"]);
+    assert!(spinning.status.success());
+    assert_eq!(stdout(&spinning), stdout(&plain));
+}
+
+#[test]
+fn tui_falls_back_to_plain_output_when_piped() {
+    let plain = watson(&["-q", "-u", "alice", "--mock", "-s"]);
+    let tui = watson(&["-q", "-u", "alice", "--mock", "-s", "--tui"]);
+    assert!(tui.status.success());
+    assert!(String::from_utf8_lossy(&tui.stderr).contains("using plain output instead"));
+    assert_eq!(stdout(&tui), stdout(&plain));
+}
+
+#[test]
+fn output_dir_writes_a_sanitized_file_per_username() {
+    let dir = std::env::temp_dir().join(format!("watson-cli-{}-output-dir", std::process::id()));
+    let run = watson(&["-q", "-u", "{alice,bob/../x:y}", "--mock", "-f", "json", "--output-dir", dir.to_str().unwrap()]);
+    assert!(run.status.success());
+
+    let mut written: Vec<String> =
+        std::fs::read_dir(&dir).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+    written.sort();
+    assert_eq!(written, ["alice.json", "bob_.._x_y.json"]);
+    let report: serde_json::Value = serde_json::from_slice(&std::fs::read(dir.join("bob_.._x_y.json")).unwrap()).unwrap();
+    assert_eq!(report["username"], "bob/../x:y");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn utf8_bom_files_start_with_the_bom() {
+    let path = std::env::temp_dir().join(format!("watson-cli-{}-bom.csv", std::process::id()));
+    let run = watson(&["-q", "-u", "alice", "--mock", "-f", "csv", "-o", path.to_str().unwrap(), "--output-encoding", "utf8-bom"]);
+    assert!(run.status.success());
+    let bytes = std::fs::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(bytes[..3], [0xEF, 0xBB, 0xBF]);
+    assert!(bytes[3..].starts_with(b"site_name,"));
+}
+
+#[test]
+fn variation_results_carry_their_source() {
+    let dir = std::env::temp_dir().join(format!("watson-cli-{}-sources", std::process::id()));
+    let run = watson(&[
+        "-q", "-u", "alice", "--variations", "--include-source-column", "--mock", "-f", "json", "--output-dir",
+        dir.to_str().unwrap(),
+    ]);
+    assert!(run.status.success());
+
+    let report = |name: &str| -> serde_json::Value {
+        serde_json::from_slice(&std::fs::read(dir.join(format!("{}.json", name))).unwrap()).unwrap()
+    };
+    for (name, source) in [("alice", "-u"), ("alice123", "variation of alice"), ("the_alice", "variation of alice")] {
+        let results = report(name)["results"].as_array().unwrap().clone();
+        assert_eq!(results.len(), 7);
+        assert!(results.iter().all(|r| r["username"] == name && r["source"] == source), "{}: {:?}", name, results);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn baseline_reports_a_claim_new_since_the_last_run() {
+    let path = std::env::temp_dir().join(format!("watson-cli-{}-baseline.json", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let monitor = || watson(&["-u", "alice", "--mock", "--baseline", path.to_str().unwrap()]);
+
+    let first = monitor();
+    assert!(first.status.success());
+    assert_eq!(stdout(&first), "");
+
+    // As if MockClaimed had been unclaimed when the baseline was written
+    let mut saved: serde_json::Value = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    assert!(saved["usernames"]["alice"].as_object_mut().unwrap().remove("MockClaimed").is_some());
+    std::fs::write(&path, serde_json::to_vec(&saved).unwrap()).unwrap();
+
+    let second = monitor();
+    assert_eq!(second.status.code(), Some(1));
+    assert_eq!(stdout(&second), "[+] MockClaimed: https://mock.watson.invalid/claimed/alice (new for alice)\n");
+
+    let third = monitor();
+    std::fs::remove_file(&path).unwrap();
+    assert!(third.status.success());
+    assert_eq!(stdout(&third), "");
+}
+
+#[test]
+fn explain_gives_the_detection_reason() {
+    let available = stdout(&watson(&["-q", "-u", "alice", "--mock", "--explain", "MockMessageAvailable"]));
+    assert!(available.contains("=== Explain: MockMessageAvailable for 'alice' ==="));
+    assert!(available.contains(
+        "Detection rule: message: available if the page contains \"Mock user not found\", else claimed\n"
+    ));
+    assert!(available.contains("Reason:         error marker \"Mock user not found\" appeared in the page\n"));
+    assert!(available.contains("Classification: available\n"));
+
+    let claimed = stdout(&watson(&["-q", "-u", "alice", "--mock", "--explain", "MockMessageClaimed"]));
+    assert!(claimed.contains("Marker:         none of the markers found\n"));
+    assert!(claimed.contains("Reason:         HTTP 200 and no error marker in the page\n"));
+    assert!(claimed.contains("Classification: claimed\n"));
+}